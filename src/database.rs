@@ -1,12 +1,17 @@
 // src/database.rs
-use rusqlite::{Connection, Result as SqlResult, params, OptionalExtension, OpenFlags};
+use rusqlite::{Connection, Result as SqlResult, params, OptionalExtension, OpenFlags, Transaction};
 use std::path::Path;
-use std::collections::HashMap; // Still used for histograms
+use std::collections::{BTreeMap, HashMap}; // HashMap still used for histograms
+use std::time::Instant;
 
 // Crate-level imports
-use crate::spectrogram::create_spectrogram;
-use crate::peaks::{find_peaks};
-use crate::hashing::{create_hashes, Fingerprint};
+use crate::audio_loader::AudioTags;
+use crate::error::SivanaError;
+use crate::fingerprinter::{DEFAULT_FFT_HOPSIZE, DEFAULT_SAMPLE_RATE};
+use crate::spectrogram::SpectrogramBuilder;
+use crate::peaks::{find_peaks, PeakParams};
+use crate::hashing::{create_hashes, Fingerprint, HashConfig, TargetZone};
+use crate::matching::OffsetHistogram;
 
 // --- Type Aliases and Structs ---
 pub type SongId = u32;
@@ -16,26 +21,262 @@ pub struct Song {
     pub id: SongId,
     pub name: String,
     pub file_path: Option<String>,
+    pub duration_seconds: Option<f64>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+/// One recorded query, from `recent_queries`.
+#[derive(Debug, Clone)]
+pub struct QueryLogEntry {
+    pub query_id: i64,
+    /// UTC time of the query as "YYYY-MM-DD HH:MM:SS".
+    pub queried_at: String,
+    pub snippet_path: Option<String>,
+    /// The matched song; None if nothing matched or the match came from an attached database.
+    pub song_id: Option<SongId>,
+    /// The song's current name; None if nothing matched or the song has since been deleted.
+    pub song_name: Option<String>,
+    pub score: Option<usize>,
+    pub confidence: Option<f32>,
+}
+
+/// Two enrolled songs whose stored fingerprints match each other, from `find_duplicate_songs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicatePair {
+    /// The lower of the two song IDs.
+    pub song_id: SongId,
+    pub other_song_id: SongId,
+    /// The lower of the two directions' match scores.
+    pub score: usize,
+    /// The lower of the two directions' confidences.
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct DbStats {
+    pub song_count: usize,
+    pub fingerprint_count: usize,
+    pub avg_fingerprints_per_song: f64,
+    pub db_file_size_bytes: u64,
+    /// Size of the `-wal` sidecar file, if one exists.
+    pub wal_file_size_bytes: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
 pub struct MatchResult {
     pub song_id: SongId,
+    /// Database the song is stored in, as an index into `fingerprint_databases`: 0 for the
+    /// connection's main database, then each database added with `attach_database`.
+    pub source_db: usize,
     pub score: usize,
+    /// Sum of the hits' strengths with magnitude weighting (see
+    /// `OffsetHistogram::with_magnitude_weighting`), otherwise equal to `score`.
+    pub weighted_score: usize,
+    /// Distinct query hashes among the `score` hits. Much lower than `score` means the
+    /// alignment rests on a few repeated landmarks (e.g. a loop); used to break score ties.
+    pub distinct_hash_score: usize,
     pub time_offset_in_song_frames: isize,
+    /// `score` divided by the number of query fingerprints that hit anything in the
+    /// database, clamped to 0..1. Unlike `score`, this does not grow with snippet length.
+    pub confidence: f32,
+    /// Share (0..1) of query fingerprints that hit any stored entry, in any song. Unrelated
+    /// audio only collides on a few common hashes, so this stays low even when one song's
+    /// histogram peak happens to be tall.
+    pub query_coverage: f32,
+    /// Where the hits behind `score` start in the song: the earliest agreeing anchor
+    /// (clamped to 0 if the query begins earlier than the song).
+    pub match_start_seconds: f32,
+    /// Where the hits behind `score` end in the song: just past the latest agreeing target peak.
+    pub match_end_seconds: f32,
+    /// The same span in the query: trimming the query to
+    /// `query_match_start_seconds..query_match_end_seconds` keeps all of the matching audio.
+    pub query_match_start_seconds: f32,
+    pub query_match_end_seconds: f32,
+}
+
+/// Database file used when no explicit path is given.
+pub const DEFAULT_DB_FILE_NAME: &str = "sivana_fingerprints.sqlite";
+/// Number of fingerprint rows written per multi-row INSERT statement (5 bound values each,
+/// well under SQLite's host-parameter limit).
+const FINGERPRINT_INSERT_BATCH_SIZE: usize = 500;
+/// Hashes per "WHERE hash IN (...)" lookup; stays under SQLite's historical 999-parameter limit.
+const HASH_LOOKUP_CHUNK_SIZE: usize = 900;
+/// Default minimum histogram peak height for a candidate to count as a match.
+pub const DEFAULT_MIN_MATCH_SCORE: usize = 100;
+/// Default share of query fingerprints that must re-align with the matched song for
+/// `verify_match` to confirm it.
+pub const DEFAULT_VERIFY_MIN_FRACTION: f32 = 0.05;
+/// Default minimum `MatchResult::query_coverage` for `query_db_and_match` to report a match;
+/// below it the query is treated as audio that is not in the database.
+pub const DEFAULT_MIN_QUERY_COVERAGE: f32 = 0.05;
+/// Anchor times may differ by this many frames and still count as aligned during verification.
+const VERIFY_TIME_TOLERANCE_FRAMES: isize = 1;
+/// Minimum confidence at which a new song is considered a re-enrollment of existing content.
+pub const DUPLICATE_MIN_CONFIDENCE: f32 = 0.5;
+/// Songs with fewer fingerprints than this are unlikely to match reliably (very short,
+/// mostly silent or badly decoded audio).
+pub const LOW_FINGERPRINT_COUNT: usize = 100;
+
+/// How `query_db_and_match` and its variants score candidates and which they accept.
+/// `Fingerprinter::query_options` fills in the fingerprinter's frame duration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryOptions {
+    /// Minimum histogram score a candidate needs to be reported.
+    pub min_score: usize,
+    /// Hop size / sample rate, converting frame positions into match start/end times.
+    pub frame_duration_seconds: f32,
+    /// Minimum `MatchResult::query_coverage` of a match (`query_db_and_match` only).
+    pub min_query_coverage: Option<f32>,
+    /// Share of the query that must align with a match (see `verify_match`); `None` skips
+    /// verification. Unused by `query_db_and_match_topn`.
+    pub verify_min_fraction: Option<f32>,
+    /// Hashes stored more often than this across the database are ignored.
+    pub max_hash_popularity: Option<usize>,
+    /// Rank by peak-strength-weighted hits (see `OffsetHistogram::with_magnitude_weighting`).
+    pub weight_by_magnitude: bool,
+    /// Also count hits up to this many frames from a candidate offset towards it (see
+    /// `OffsetHistogram::with_offset_tolerance`); 0 counts exact offsets only.
+    pub offset_tolerance_frames: usize,
 }
 
-const DB_FILE_NAME: &str = "sivana_fingerprints.sqlite";
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions {
+            min_score: DEFAULT_MIN_MATCH_SCORE,
+            frame_duration_seconds: DEFAULT_FFT_HOPSIZE as f32 / DEFAULT_SAMPLE_RATE as f32,
+            min_query_coverage: Some(DEFAULT_MIN_QUERY_COVERAGE),
+            verify_min_fraction: Some(DEFAULT_VERIFY_MIN_FRACTION),
+            max_hash_popularity: None,
+            weight_by_magnitude: false,
+            offset_tolerance_frames: 0,
+        }
+    }
+}
 
-pub fn open_db_connection() -> SqlResult<Connection> {
+/// Phase of an enrollment, as reported to a progress callback together with a 0..1 fraction
+/// of that phase. Stages arrive in declaration order; callers that start from decoded
+/// samples never see `Decoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnrollStage {
+    Decoding,
+    Spectrogram,
+    Peaks,
+    Hashing,
+    Inserting,
+}
+
+/// Opens (creating if needed) the database at `path`, including any missing parent directories.
+pub fn open_db_connection(path: &Path) -> SqlResult<Connection> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+                Some(format!("Failed to create directory '{}': {}", parent.display(), e)),
+            )
+        })?;
+    }
     let conn = Connection::open_with_flags(
-        Path::new(DB_FILE_NAME),
+        path,
         OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
     )?;
     conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")?;
     Ok(conn)
 }
 
+/// `open_db_connection` tuned for mass enrollment: `synchronous = NORMAL`, a 64 MB page
+/// cache and in-memory temp storage. The settings only apply to this connection.
+/// Durability trade-off: in WAL mode with `synchronous = NORMAL`, the most recently
+/// committed transactions can be lost on a power failure or OS crash (an application crash
+/// is still safe), though the database itself stays consistent. That is fine for a
+/// re-runnable import, but query and one-off connections should use `open_db_connection`.
+pub fn open_bulk_db_connection(path: &Path) -> SqlResult<Connection> {
+    let conn = open_db_connection(path)?;
+    conn.execute_batch("PRAGMA synchronous = NORMAL; PRAGMA cache_size = -64000; PRAGMA temp_store = MEMORY;")?;
+    Ok(conn)
+}
+
+/// Opens a throwaway in-memory database with the schema already created.
+/// Nothing is written to disk and all data is lost when the connection is dropped.
+pub fn open_in_memory_connection() -> SqlResult<Connection> {
+    let conn = Connection::open_in_memory()?;
+    // WAL is meaningless for an in-memory database, so only foreign keys are enabled.
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    init_db(&conn)?;
+    Ok(conn)
+}
+
+/// Attaches another fingerprint database to `conn` so queries search it along with the main
+/// one (see `fingerprint_databases`). Returns the schema name it was attached under. The
+/// file must exist and contain a fingerprints table; nothing is created.
+pub fn attach_database(conn: &Connection, path: &Path) -> Result<String, SivanaError> {
+    if !path.is_file() {
+        return Err(SivanaError::InvalidInput(format!("Database '{}' does not exist.", path.display())));
+    }
+    let context = || format!("Failed to attach database '{}'", path.display());
+    let databases = fingerprint_databases(conn).map_err(|e| SivanaError::sqlite(context(), e))?;
+    let schema = format!("source{}", databases.len());
+    conn.execute(&format!("ATTACH DATABASE ?1 AS {}", quote_schema(&schema)), params![path.to_string_lossy()])
+        .map_err(|e| SivanaError::sqlite(context(), e))?;
+
+    let has_fingerprints: bool = conn.query_row(
+        &format!("SELECT EXISTS(SELECT 1 FROM {}.sqlite_master WHERE type = 'table' AND name = 'fingerprints')", quote_schema(&schema)),
+        [],
+        |row| row.get(0),
+    ).map_err(|e| SivanaError::sqlite(context(), e))?;
+    if !has_fingerprints {
+        conn.execute(&format!("DETACH DATABASE {}", quote_schema(&schema)), [])
+            .map_err(|e| SivanaError::sqlite(context(), e))?;
+        return Err(SivanaError::InvalidInput(format!("'{}' is not a fingerprint database.", path.display())));
+    }
+    // Attached databases aren't modified, so a missing index can only be reported.
+    let missing = missing_indexes(conn, &schema).map_err(|e| SivanaError::sqlite(context(), e))?;
+    if !missing.is_empty() {
+        log::warn!(
+            "'{}' lacks the index(es) {}; queries against it will be slow until it is opened as the main database once.",
+            path.display(), missing.join(", ")
+        );
+    }
+    log::debug!("attach_database - Attached '{}' as {}.", path.display(), schema);
+    Ok(schema)
+}
+
+/// Schema names of the databases a query searches, in `MatchResult::source_db` order:
+/// "main" first, then the attached databases in the order they were attached.
+pub fn fingerprint_databases(conn: &Connection) -> SqlResult<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA database_list")?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?.collect::<SqlResult<Vec<_>>>()?;
+    Ok(names.into_iter().filter(|name| name != "temp").collect())
+}
+
+/// File behind the `source_db`-th searched database; None if there is no such database,
+/// Some("") for an in-memory one.
+pub fn database_file(conn: &Connection, source_db: usize) -> SqlResult<Option<String>> {
+    let Some(schema) = fingerprint_databases(conn)?.into_iter().nth(source_db) else { return Ok(None) };
+    let mut stmt = conn.prepare("PRAGMA database_list")?;
+    let mut rows = stmt.query_map([], |row| Ok((row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?)))?;
+    rows.find_map(|row| match row {
+        Ok((name, file)) if name == schema => Some(Ok(Some(file.unwrap_or_default()))),
+        Ok(_) => None,
+        Err(e) => Some(Err(e)),
+    }).unwrap_or(Ok(None))
+}
+
+// The schema name as a quoted SQL identifier.
+fn quote_schema(schema: &str) -> String {
+    format!("\"{}\"", schema.replace('"', "\"\""))
+}
+
+// Schema of the `source_db`-th searched database, for building queries against it.
+fn source_schema(conn: &Connection, source_db: usize) -> SqlResult<String> {
+    if source_db == 0 {
+        return Ok(quote_schema("main"));
+    }
+    fingerprint_databases(conn)?.into_iter().nth(source_db)
+        .map(|schema| quote_schema(&schema))
+        .ok_or(rusqlite::Error::QueryReturnedNoRows)
+}
+
 pub fn init_db(conn: &Connection) -> SqlResult<()> { // init_db can take &Connection if execute_batch allows
     conn.execute_batch(
         "BEGIN;
@@ -43,224 +284,1115 @@ pub fn init_db(conn: &Connection) -> SqlResult<()> { // init_db can take &Connec
              song_id INTEGER PRIMARY KEY,
              name TEXT NOT NULL,
              file_path TEXT UNIQUE,
-             enrolled_at DATETIME DEFAULT CURRENT_TIMESTAMP
+             enrolled_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+             duration_seconds REAL,
+             artist TEXT,
+             album TEXT,
+             file_size INTEGER,
+             file_mtime_ns INTEGER
          );
          CREATE TABLE IF NOT EXISTS fingerprints (
              hash INTEGER NOT NULL,
              song_id INTEGER NOT NULL,
              anchor_time_idx INTEGER NOT NULL,
+             target_delta_frames INTEGER,
+             strength INTEGER,
              FOREIGN KEY (song_id) REFERENCES songs(song_id) ON DELETE CASCADE
          );
-         CREATE INDEX IF NOT EXISTS idx_fingerprints_hash ON fingerprints (hash);
-         CREATE INDEX IF NOT EXISTS idx_fingerprints_song_id ON fingerprints (song_id);
+         CREATE TABLE IF NOT EXISTS params (
+             key TEXT PRIMARY KEY,
+             value TEXT NOT NULL
+         );
+         -- No foreign key: the history outlives deleted songs.
+         CREATE TABLE IF NOT EXISTS query_log (
+             query_id INTEGER PRIMARY KEY,
+             queried_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+             snippet_path TEXT,
+             song_id INTEGER,
+             score INTEGER,
+             confidence REAL
+         );
          COMMIT;"
     )?;
-    println!("Database '{}' initialized successfully.", DB_FILE_NAME);
+    migrate_db(conn)?;
+    log::debug!("Database '{}' initialized successfully.", conn.path().filter(|p| !p.is_empty()).unwrap_or(":memory:"));
+    Ok(())
+}
+
+/// Brings databases created by older versions up to the current schema.
+/// Columns added after the initial schema are nullable, so old rows simply keep NULL.
+fn migrate_db(conn: &Connection) -> SqlResult<()> {
+    // A NULL target_delta_frames skips the anchor-target delta check during matching.
+    add_column_if_missing(conn, "fingerprints", "target_delta_frames", "INTEGER")?;
+    // A NULL strength counts as unknown when matching with magnitude weighting.
+    add_column_if_missing(conn, "fingerprints", "strength", "INTEGER")?;
+    add_column_if_missing(conn, "songs", "duration_seconds", "REAL")?;
+    add_column_if_missing(conn, "songs", "artist", "TEXT")?;
+    add_column_if_missing(conn, "songs", "album", "TEXT")?;
+    add_column_if_missing(conn, "songs", "file_size", "INTEGER")?;
+    add_column_if_missing(conn, "songs", "file_mtime_ns", "INTEGER")?;
+    // The indexes are created here, after target_delta_frames is guaranteed to exist; the
+    // covering hash index supersedes the old single-column one.
+    ensure_indexes(conn)?;
+    conn.execute_batch("DROP INDEX IF EXISTS idx_fingerprints_hash;")?;
+    Ok(())
+}
+
+/// Indexes on the fingerprints table, as (name, indexed columns). The first covers the match
+/// lookup (hash IN (...) -> song_id, anchor_time_idx, target_delta_frames) so it never visits
+/// the table; the second keeps per-song deletes and counts fast.
+const FINGERPRINT_INDEXES: [(&str, &str); 2] = [
+    ("idx_fp_hash_cover", "hash, song_id, anchor_time_idx, target_delta_frames"),
+    ("idx_fingerprints_song_id", "song_id"),
+];
+
+// Names of the FINGERPRINT_INDEXES missing from `schema`'s fingerprints table.
+fn missing_indexes(conn: &Connection, schema: &str) -> SqlResult<Vec<&'static str>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {}.sqlite_master WHERE type = 'index' AND name = ?1 AND tbl_name = 'fingerprints')",
+        quote_schema(schema)
+    ))?;
+    let mut missing = Vec::new();
+    for (name, _) in FINGERPRINT_INDEXES {
+        if !stmt.query_row(params![name], |row| row.get::<_, bool>(0))? {
+            missing.push(name);
+        }
+    }
+    Ok(missing)
+}
+
+/// Creates whichever fingerprint indexes the main database lacks and returns their names.
+/// Without the hash index every query scans the whole table, so a database from before the
+/// index existed, or one whose fingerprints were loaded with raw SQL, gets it here (`init_db`
+/// calls this). Building it over many fingerprints takes a while, which is logged.
+pub fn ensure_indexes(conn: &Connection) -> SqlResult<Vec<&'static str>> {
+    let missing = missing_indexes(conn, "main")?;
+    if missing.is_empty() {
+        return Ok(missing);
+    }
+    let fingerprint_count: i64 = conn.query_row("SELECT COUNT(*) FROM fingerprints", [], |row| row.get(0))?;
+    for (name, columns) in FINGERPRINT_INDEXES.iter().filter(|(name, _)| missing.contains(name)) {
+        if fingerprint_count > 0 {
+            log::warn!("Building missing index {} over {} fingerprints; this may take a while...", name, fingerprint_count);
+        }
+        let start_time = Instant::now();
+        conn.execute_batch(&format!("CREATE INDEX IF NOT EXISTS {} ON fingerprints ({});", name, columns))?;
+        log::debug!("ensure_indexes - Built {} in {:.2?}.", name, start_time.elapsed());
+    }
+    Ok(missing)
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, column_type: &str) -> SqlResult<()> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        log::info!("Migrating database: adding '{}' column to '{}'.", column, table);
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {};", table, column, column_type))?;
+    }
     Ok(())
 }
 
+/// Fingerprints `song_audio_samples` (mono, already at the fingerprinting sample rate) with a
+/// fresh `window_size` spectrogram and stores the song and its fingerprints, returning the
+/// new song ID. See `enroll_song_with_builder` to reuse the FFT plan across many files.
 #[allow(clippy::too_many_arguments)]
 pub fn enroll_song(
-    conn: &mut Connection, // <<< CHANGED TO &mut Connection HERE
+    conn: &mut Connection,
     song_name: &str,
     song_file_path: Option<&str>,
+    song_tags: &AudioTags,
     song_audio_samples: &[f32],
-    sample_rate: u32,
     window_size: usize,
     hop_size: usize,
-    peak_params: (usize, usize, f32),
-    hash_params: (usize, usize, usize, usize),
-) -> Result<SongId, String> {
-    println!("Attempting to enroll song: Name='{}'", song_name);
-
-    // It's good practice to wrap song insertion and fingerprint insertion in one transaction
-    // if possible, but song insertion might need to happen first to get an ID,
-    // or handle conflicts. For simplicity, we'll do song insertion, then a
-    // transaction for fingerprints. More advanced: one transaction for all.
-
-    // 1. Add song metadata to 'songs' table
-    // Using a transaction for the whole enrollment might be better, but requires careful handling of last_insert_rowid
-    // Let's keep song insertion separate for now to easily get last_insert_rowid,
-    // and then use a transaction for the bulk fingerprint inserts.
-
-    let preliminary_song_id_result = conn.execute(
-        "INSERT INTO songs (name, file_path) VALUES (?1, ?2)
-         ON CONFLICT(file_path) DO UPDATE SET name = excluded.name, enrolled_at = CURRENT_TIMESTAMP RETURNING song_id;",
-        params![song_name, song_file_path],
-    );
+    peak_params: PeakParams,
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+) -> Result<SongId, SivanaError> {
+    let spectrogram_builder = SpectrogramBuilder::new(window_size);
+    enroll_song_with_builder(
+        conn, song_name, song_file_path, song_tags, song_audio_samples,
+        &spectrogram_builder, hop_size, peak_params, target_zone, hash_config,
+    )
+}
 
-    let db_song_id_i64: i64 = match preliminary_song_id_result {
-        Ok(_) => conn.last_insert_rowid(),
-        Err(e_insert) => {
-            // If INSERT with ON CONFLICT RETURNING failed, try to SELECT the ID by file_path if provided
-            if let Some(p) = song_file_path {
-                match conn.query_row(
-                    "SELECT song_id FROM songs WHERE file_path = ?1",
-                    params![p],
-                    |row| row.get(0),
-                ).optional() {
-                    Ok(Some(id_val)) => id_val, // Found existing song by path
-                    Ok(None) => return Err(format!("Failed to insert song '{}' and it was not found by path '{}' after conflict: {}", song_name, p, e_insert)),
-                    Err(e_select) => return Err(format!("Failed to insert song '{}' (error: {}), and also failed to retrieve by path '{}' (error: {})", song_name, e_insert, p, e_select)),
-                }
-            } else {
-                // No file_path to check for existing, and insert failed
-                return Err(format!("Failed to insert song '{}' (no file_path for conflict lookup): {}", song_name, e_insert));
-            }
+/// Same as `enroll_song`, but reuses an already-planned `SpectrogramBuilder`.
+/// Use this when enrolling many files with the same window size.
+#[allow(clippy::too_many_arguments)]
+pub fn enroll_song_with_builder(
+    conn: &mut Connection,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+    song_audio_samples: &[f32],
+    spectrogram_builder: &SpectrogramBuilder,
+    hop_size: usize,
+    peak_params: PeakParams,
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+) -> Result<SongId, SivanaError> {
+    enroll_song_with_progress(
+        conn, song_name, song_file_path, song_tags, song_audio_samples,
+        spectrogram_builder, hop_size, peak_params, target_zone, hash_config, None,
+    )
+}
+
+/// `enroll_song_with_builder` that reports each stage to `progress` as it runs, e.g. to
+/// drive a progress bar. The insert stage advances once per batch of fingerprints.
+#[allow(clippy::too_many_arguments)]
+pub fn enroll_song_with_progress(
+    conn: &mut Connection,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+    song_audio_samples: &[f32],
+    spectrogram_builder: &SpectrogramBuilder,
+    hop_size: usize,
+    peak_params: PeakParams,
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+    progress: Option<&dyn Fn(EnrollStage, f32)>,
+) -> Result<SongId, SivanaError> {
+    log::info!("Attempting to enroll song: Name='{}'", song_name);
+
+    // Done before touching the database so a failure here leaves no trace.
+    let fingerprints = fingerprint_samples(
+        song_name, song_audio_samples, spectrogram_builder, hop_size, peak_params, target_zone, hash_config, progress,
+    )?;
+    enroll_fingerprints_with_progress(conn, song_name, song_file_path, song_tags, &fingerprints, progress)
+}
+
+/// Spectrogram -> peaks -> hashes for enrollment, reporting each stage to `progress`.
+/// Errors if any stage produces nothing.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fingerprint_samples(
+    song_name: &str,
+    song_audio_samples: &[f32],
+    spectrogram_builder: &SpectrogramBuilder,
+    hop_size: usize,
+    peak_params: PeakParams,
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+    progress: Option<&dyn Fn(EnrollStage, f32)>,
+) -> Result<Vec<Fingerprint>, SivanaError> {
+    let report = |stage: EnrollStage, fraction: f32| {
+        if let Some(progress) = progress {
+            progress(stage, fraction);
         }
     };
 
-    if db_song_id_i64 == 0 {
-        // This case should ideally be caught by the RETURNING clause or the subsequent SELECT.
-        // If file_path was None, and insert somehow didn't error but gave 0, it's an issue.
-        return Err(format!("Failed to obtain a valid database song ID for '{}'. last_insert_rowid was 0.", song_name));
+    report(EnrollStage::Spectrogram, 0.0);
+    let spectrogram = spectrogram_builder.build(song_audio_samples, hop_size);
+    if spectrogram.is_empty() { return Err(SivanaError::NoSpectrogram { song_name: song_name.to_string() }); }
+    report(EnrollStage::Spectrogram, 1.0);
+
+    report(EnrollStage::Peaks, 0.0);
+    let peaks = find_peaks(&spectrogram, peak_params);
+    if peaks.is_empty() { return Err(SivanaError::NoPeaks { song_name: song_name.to_string() }); }
+    log::info!("Found {} peaks for song '{}'", peaks.len(), song_name);
+    report(EnrollStage::Peaks, 1.0);
+
+    report(EnrollStage::Hashing, 0.0);
+    let fingerprints = create_hashes(&peaks, target_zone, hash_config);
+    if fingerprints.is_empty() { return Err(SivanaError::NoFingerprints { song_name: song_name.to_string() }); }
+    log::info!("Generated {} fingerprints for song '{}'", fingerprints.len(), song_name);
+    report(EnrollStage::Hashing, 1.0);
+    Ok(fingerprints)
+}
+
+/// Stores already-computed fingerprints under a song, creating the song row or, if
+/// `song_file_path` is already enrolled, renaming it and replacing its fingerprints.
+/// Artist and album are taken from `song_tags` (the title is expected in `song_name`).
+/// The song upsert and all fingerprint writes happen in a single transaction, so a
+/// failure part-way leaves the database exactly as it was.
+pub fn enroll_fingerprints(
+    conn: &mut Connection,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+    fingerprints: &[Fingerprint],
+) -> Result<SongId, SivanaError> {
+    enroll_fingerprints_with_progress(conn, song_name, song_file_path, song_tags, fingerprints, None)
+}
+
+/// `enroll_fingerprints` that reports `EnrollStage::Inserting` progress after each batch.
+pub fn enroll_fingerprints_with_progress(
+    conn: &mut Connection,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+    fingerprints: &[Fingerprint],
+    progress: Option<&dyn Fn(EnrollStage, f32)>,
+) -> Result<SongId, SivanaError> {
+    store_song_fingerprints(conn, None, song_name, song_file_path, song_tags, fingerprints, progress)
+}
+
+/// `enroll_fingerprints` under the caller's `song_id` instead of the next free one, e.g. to
+/// keep the IDs of another database when copying songs over. A song already stored under
+/// that ID, or under `song_file_path`, is replaced (`INSERT OR REPLACE`) along with its
+/// fingerprints, so repeating the same import leaves the database unchanged.
+pub fn enroll_fingerprints_with_id(
+    conn: &mut Connection,
+    song_id: SongId,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+    fingerprints: &[Fingerprint],
+) -> Result<SongId, SivanaError> {
+    store_song_fingerprints(conn, Some(song_id), song_name, song_file_path, song_tags, fingerprints, None)
+}
+
+fn store_song_fingerprints(
+    conn: &mut Connection,
+    song_id: Option<SongId>,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+    fingerprints: &[Fingerprint],
+    progress: Option<&dyn Fn(EnrollStage, f32)>,
+) -> Result<SongId, SivanaError> {
+    let tx = conn.transaction().map_err(|e| SivanaError::sqlite("Failed to start enrollment transaction", e))?;
+    let db_song_id_i64 = match song_id {
+        Some(song_id) => replace_song_clearing_fingerprints(&tx, song_id, song_name, song_file_path, song_tags)?,
+        None => upsert_song_clearing_fingerprints(&tx, song_name, song_file_path, song_tags)?,
+    };
+
+    let insert_start = Instant::now();
+    insert_fingerprint_batches(&tx, db_song_id_i64, fingerprints, progress)
+        .map_err(|e| SivanaError::sqlite(format!("Failed to insert fingerprints for song ID {}", db_song_id_i64), e))?;
+    log::debug!(
+        "enroll - Inserted {} fingerprints in {:.1?} (batches of {}).",
+        fingerprints.len(), insert_start.elapsed(), FINGERPRINT_INSERT_BATCH_SIZE
+    );
+    // Dropping `tx` without committing (any early return above) rolls everything back.
+    tx.commit().map_err(|e| SivanaError::sqlite("Failed to commit enrollment transaction", e))?;
+
+    let song_id_u32 = db_song_id_i64 as SongId;
+    log::info!("Successfully enrolled song: DB ID={}, Name='{}'", song_id_u32, song_name);
+    Ok(song_id_u32)
+}
+
+/// Like `enroll_fingerprints`, but pulls the fingerprints from `next_batch` until it returns
+/// `Ok(None)`, inserting each batch as it arrives, so the full set never has to be held in
+/// memory. Everything still happens in one transaction: an error from `next_batch` or the
+/// database, or a stream that yields no fingerprints at all, leaves the database untouched.
+/// Returns the song ID and the number of fingerprints stored.
+pub fn enroll_fingerprint_stream<F>(
+    conn: &mut Connection,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+    mut next_batch: F,
+) -> Result<(SongId, usize), SivanaError>
+where
+    F: FnMut() -> Result<Option<Vec<Fingerprint>>, SivanaError>,
+{
+    let tx = conn.transaction().map_err(|e| SivanaError::sqlite("Failed to start enrollment transaction", e))?;
+    let db_song_id_i64 = upsert_song_clearing_fingerprints(&tx, song_name, song_file_path, song_tags)?;
+
+    let insert_start = Instant::now();
+    let mut total = 0;
+    while let Some(batch) = next_batch()? {
+        insert_fingerprint_batches(&tx, db_song_id_i64, &batch, None)
+            .map_err(|e| SivanaError::sqlite(format!("Failed to insert fingerprints for song ID {}", db_song_id_i64), e))?;
+        total += batch.len();
     }
+    if total == 0 {
+        return Err(SivanaError::NoFingerprints { song_name: song_name.to_string() });
+    }
+    log::debug!("enroll - Streamed {} fingerprints into the database in {:.1?}.", total, insert_start.elapsed());
+    tx.commit().map_err(|e| SivanaError::sqlite("Failed to commit enrollment transaction", e))?;
 
     let song_id_u32 = db_song_id_i64 as SongId;
-    println!("Enrolling with DB Song ID: {}, Name='{}'", song_id_u32, song_name);
+    log::info!("Successfully enrolled song: DB ID={}, Name='{}'", song_id_u32, song_name);
+    Ok((song_id_u32, total))
+}
 
-    // --- Fingerprint Generation ---
-    let spectrogram = create_spectrogram(song_audio_samples, sample_rate, window_size, hop_size);
-    if spectrogram.is_empty() { return Err(format!("Failed to generate spectrogram for song ID {}", song_id_u32)); }
+/// Creates the song row (or, if `song_file_path` is already enrolled, updates it) and
+/// removes any fingerprints it had, so re-enrolling never duplicates them.
+fn upsert_song_clearing_fingerprints(
+    tx: &Transaction<'_>,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+) -> Result<i64, SivanaError> {
+    // RETURNING yields the row's ID on both the insert and the conflict-update path
+    // (last_insert_rowid is not updated when the upsert turns into an UPDATE).
+    let db_song_id_i64: i64 = tx.query_row(
+        "INSERT INTO songs (name, file_path, artist, album) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(file_path) DO UPDATE SET
+             name = excluded.name, artist = excluded.artist, album = excluded.album,
+             enrolled_at = CURRENT_TIMESTAMP, file_size = NULL, file_mtime_ns = NULL
+         RETURNING song_id;",
+        params![song_name, song_file_path, song_tags.artist, song_tags.album],
+        |row| row.get(0),
+    ).map_err(|e| SivanaError::sqlite(format!("Failed to insert song '{}'", song_name), e))?;
+    log::debug!("Enrolling with DB Song ID: {}, Name='{}'", db_song_id_i64, song_name);
 
-    let peaks = find_peaks(&spectrogram, peak_params.0, peak_params.1, peak_params.2);
-    if peaks.is_empty() { return Err(format!("No peaks found for song ID {}", song_id_u32)); }
-    println!("Found {} peaks for song ID {}", peaks.len(), song_id_u32);
+    tx.execute("DELETE FROM fingerprints WHERE song_id = ?1", params![db_song_id_i64])
+        .map_err(|e| SivanaError::sqlite(format!("Failed to clear old fingerprints for song ID {}", db_song_id_i64), e))?;
+    Ok(db_song_id_i64)
+}
 
-    let fingerprints = create_hashes(&peaks, hash_params.0, hash_params.1, hash_params.2, hash_params.3);
-    if fingerprints.is_empty() { return Err(format!("No fingerprints generated for song ID {}", song_id_u32)); }
-    println!("Generated {} fingerprints for song ID {}", fingerprints.len(), song_id_u32);
+/// `upsert_song_clearing_fingerprints` with an explicit song ID: whatever is stored under
+/// that ID or `song_file_path` is replaced by the new song row.
+fn replace_song_clearing_fingerprints(
+    tx: &Transaction<'_>,
+    song_id: SongId,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+) -> Result<i64, SivanaError> {
+    let db_song_id_i64 = song_id as i64;
+    // REPLACE removes conflicting rows without reliably cascading to their fingerprints,
+    // so those are cleared first.
+    tx.execute(
+        "DELETE FROM fingerprints WHERE song_id IN (SELECT song_id FROM songs WHERE song_id = ?1 OR file_path = ?2)",
+        params![db_song_id_i64, song_file_path],
+    ).map_err(|e| SivanaError::sqlite(format!("Failed to clear old fingerprints for song ID {}", song_id), e))?;
+    tx.execute(
+        "INSERT OR REPLACE INTO songs (song_id, name, file_path, artist, album) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![db_song_id_i64, song_name, song_file_path, song_tags.artist, song_tags.album],
+    ).map_err(|e| SivanaError::sqlite(format!("Failed to insert song '{}' with ID {}", song_name, song_id), e))?;
+    log::debug!("Enrolling with caller-supplied DB Song ID: {}, Name='{}'", song_id, song_name);
+    Ok(db_song_id_i64)
+}
 
-    // --- Store fingerprints in DB within a transaction ---
-    // conn is now &mut Connection, so conn.transaction() is valid.
-    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction for fingerprints: {}", e))?;
-    {
-        // Optimization: Clear old fingerprints for this song_id before inserting new ones if re-enrolling
-        // This prevents duplicate fingerprints if a song is enrolled multiple times.
-        tx.execute("DELETE FROM fingerprints WHERE song_id = ?1", params![db_song_id_i64])
-            .map_err(|e| format!("Failed to clear old fingerprints for song ID {}: {}", db_song_id_i64, e))?;
-
-        let mut stmt = tx.prepare("INSERT INTO fingerprints (hash, song_id, anchor_time_idx) VALUES (?1, ?2, ?3)")
-            .map_err(|e| format!("Failed to prepare fingerprint insert statement: {}", e))?;
-        for fp in fingerprints {
-            stmt.execute(params![fp.hash as i64, db_song_id_i64, fp.anchor_time_idx as i64])
-                .map_err(|e| format!("Failed to insert fingerprint for song ID {}: {}", db_song_id_i64, e))?;
+/// Multi-row "INSERT ... VALUES (?,?,?,?,?), (?,?,?,?,?), ..." for `rows` fingerprints.
+fn fingerprint_insert_sql(rows: usize) -> String {
+    let mut sql = String::from("INSERT INTO fingerprints (hash, song_id, anchor_time_idx, target_delta_frames, strength) VALUES ");
+    for i in 0..rows {
+        if i > 0 {
+            sql.push_str(", ");
         }
+        sql.push_str("(?, ?, ?, ?, ?)");
     }
-    tx.commit().map_err(|e| format!("Failed to commit fingerprint transaction: {}", e))?;
+    sql
+}
 
-    println!("Successfully enrolled song: DB ID={}, Name='{}'", song_id_u32, song_name);
-    Ok(song_id_u32)
+/// Inserts fingerprints using multi-row INSERTs of FINGERPRINT_INSERT_BATCH_SIZE rows, which
+/// is far fewer statement executions than one INSERT per fingerprint.
+pub(crate) fn insert_fingerprint_batches(
+    conn: &Connection,
+    song_id: i64,
+    fingerprints: &[Fingerprint],
+    progress: Option<&dyn Fn(EnrollStage, f32)>,
+) -> SqlResult<()> {
+    let mut full_batch_stmt = conn.prepare_cached(&fingerprint_insert_sql(FINGERPRINT_INSERT_BATCH_SIZE))?;
+    let mut values: Vec<Option<i64>> = Vec::with_capacity(FINGERPRINT_INSERT_BATCH_SIZE * 5);
+    let num_batches = fingerprints.len().div_ceil(FINGERPRINT_INSERT_BATCH_SIZE);
+
+    if let Some(progress) = progress {
+        progress(EnrollStage::Inserting, 0.0);
+    }
+    for (batch_idx, chunk) in fingerprints.chunks(FINGERPRINT_INSERT_BATCH_SIZE).enumerate() {
+        values.clear();
+        for fp in chunk {
+            values.extend_from_slice(&[
+                Some(fp.hash as i64),
+                Some(song_id),
+                Some(fp.anchor_time_idx as i64),
+                Some(fp.target_delta_frames as i64),
+                // Unknown strengths (0) are stored as NULL.
+                (fp.strength > 0).then_some(fp.strength as i64),
+            ]);
+        }
+        if chunk.len() == FINGERPRINT_INSERT_BATCH_SIZE {
+            full_batch_stmt.execute(rusqlite::params_from_iter(values.iter()))?;
+        } else {
+            // Only the final chunk can be short.
+            conn.prepare(&fingerprint_insert_sql(chunk.len()))?
+                .execute(rusqlite::params_from_iter(values.iter()))?;
+        }
+        if let Some(progress) = progress {
+            progress(EnrollStage::Inserting, (batch_idx + 1) as f32 / num_batches as f32);
+        }
+    }
+    Ok(())
 }
 
+/// Looks for an already-enrolled song whose content matches `fingerprints` strongly enough to be
+/// the same recording (score >= DEFAULT_MIN_MATCH_SCORE and confidence >= DUPLICATE_MIN_CONFIDENCE).
+/// The song stored under `song_file_path` itself is ignored, since re-enrolling a path is an update.
+pub fn find_content_duplicate(
+    conn: &Connection,
+    fingerprints: &[Fingerprint],
+    song_file_path: Option<&str>,
+    frame_duration_seconds: f32,
+) -> SqlResult<Option<MatchResult>> {
+    let same_path_song_id: Option<SongId> = match song_file_path {
+        Some(path) => conn.query_row(
+            "SELECT song_id FROM songs WHERE file_path = ?1",
+            params![path],
+            |row| row.get::<_, i64>(0),
+        ).optional()?.map(|id| id as SongId),
+        None => None,
+    };
+    // Two candidates, so a hit on the same-path song doesn't hide a second copy.
+    let options = QueryOptions { frame_duration_seconds, ..QueryOptions::default() };
+    let duplicate = query_db_and_match_topn(conn, fingerprints, 2, &options)
+        .into_iter()
+        .filter(|c| Some(c.song_id) != same_path_song_id)
+        .find(|c| c.confidence >= DUPLICATE_MIN_CONFIDENCE);
+    Ok(duplicate)
+}
+
+/// Other songs `find_duplicate_songs` considers per song (the song itself always comes first).
+const DUPLICATE_CANDIDATES_PER_SONG: usize = 8;
+
+/// Finds enrolled songs that are probably the same recording under different names, from the
+/// stored fingerprints alone: each song's fingerprints are matched against the rest of the
+/// main database, and a pair is reported when each song matches the other with a score of at
+/// least `min_score`. Sorted by descending score. Takes one full-song query per song.
+pub fn find_duplicate_songs(conn: &Connection, min_score: usize) -> SqlResult<Vec<DuplicatePair>> {
+    let song_ids: Vec<SongId> = conn
+        .prepare("SELECT song_id FROM songs ORDER BY song_id")?
+        .query_map([], |row| Ok(row.get::<_, i64>(0)? as SongId))?
+        .collect::<SqlResult<_>>()?;
 
-#[allow(clippy::too_many_lines)]
+    // Score and confidence of each directed match, keyed by (query song, matched song).
+    let mut directed: HashMap<(SongId, SongId), (usize, f32)> = HashMap::new();
+    // Only scores are used, so frame times don't matter.
+    let options = QueryOptions { min_score, frame_duration_seconds: 0.0, ..QueryOptions::default() };
+    for &song_id in &song_ids {
+        let fingerprints = get_song_fingerprints(conn, song_id)?;
+        let candidates = query_db_and_match_topn(conn, &fingerprints, DUPLICATE_CANDIDATES_PER_SONG + 1, &options);
+        for candidate in candidates.into_iter().filter(|c| c.source_db == 0 && c.song_id != song_id) {
+            directed.insert((song_id, candidate.song_id), (candidate.score, candidate.confidence));
+        }
+    }
+
+    let mut pairs: Vec<DuplicatePair> = directed
+        .iter()
+        .filter(|((song_id, other_song_id), _)| song_id < other_song_id)
+        .filter_map(|(&(song_id, other_song_id), &(score, confidence))| {
+            let &(back_score, back_confidence) = directed.get(&(other_song_id, song_id))?;
+            Some(DuplicatePair {
+                song_id,
+                other_song_id,
+                score: score.min(back_score),
+                confidence: confidence.min(back_confidence),
+            })
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.score.cmp(&a.score).then(a.song_id.cmp(&b.song_id)).then(a.other_song_id.cmp(&b.other_song_id)));
+    log::debug!("find_duplicate_songs - {} songs, {} duplicate pairs.", song_ids.len(), pairs.len());
+    Ok(pairs)
+}
+
+/// Groups duplicate pairs into sets of songs linked by them, directly or through other songs.
+/// Each group is sorted by song ID, and the groups by their first song.
+pub fn duplicate_groups(pairs: &[DuplicatePair]) -> Vec<Vec<SongId>> {
+    // Union-find with each set represented by its smallest song ID.
+    let mut parent: BTreeMap<SongId, SongId> = BTreeMap::new();
+    fn root(parent: &mut BTreeMap<SongId, SongId>, song_id: SongId) -> SongId {
+        let mut current = song_id;
+        while let Some(&next) = parent.get(&current) {
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+        parent.insert(song_id, current);
+        current
+    }
+    for pair in pairs {
+        parent.entry(pair.song_id).or_insert(pair.song_id);
+        parent.entry(pair.other_song_id).or_insert(pair.other_song_id);
+        let (a, b) = (root(&mut parent, pair.song_id), root(&mut parent, pair.other_song_id));
+        parent.insert(a.max(b), a.min(b));
+    }
+
+    let mut groups: BTreeMap<SongId, Vec<SongId>> = BTreeMap::new();
+    let song_ids: Vec<SongId> = parent.keys().copied().collect();
+    for song_id in song_ids {
+        let group_root = root(&mut parent, song_id);
+        groups.entry(group_root).or_default().push(song_id);
+    }
+    groups.into_values().collect()
+}
+
+/// Returns the single best match for the query, if any scores at or above
+/// `options.min_score`. With `options.min_query_coverage`, no match is returned when fewer
+/// than that share of the query's fingerprints hit anything, however tall the winner's peak.
+/// With `options.verify_min_fraction`, the winner must also pass `verify_match` (allowing
+/// the offset tolerance, if larger than its own).
 pub fn query_db_and_match(
     conn: &Connection, // Querying only needs &Connection
     query_fingerprints: &[Fingerprint],
+    options: &QueryOptions,
 ) -> Option<MatchResult> {
-    // ... (rest of query_db_and_match remains the same as your previous version, it was correct)
-    if query_fingerprints.is_empty() {
-        println!("Debug: query_db - Query has no fingerprints.");
+    let best = query_db_and_match_topn(conn, query_fingerprints, 1, options)
+        .into_iter()
+        .next()?;
+    if let Some(min_coverage) = options.min_query_coverage
+        && best.query_coverage < min_coverage
+    {
+        log::debug!(
+            "query_db - Only {:.1}% of query fingerprints hit the database (need {:.1}%); treating as unknown.",
+            best.query_coverage * 100.0, min_coverage * 100.0
+        );
         return None;
     }
+    let Some(min_fraction) = options.verify_min_fraction else { return Some(best) };
+    let time_tolerance = VERIFY_TIME_TOLERANCE_FRAMES.max(options.offset_tolerance_frames as isize);
+    match verify_alignment(conn, query_fingerprints, &best, min_fraction, time_tolerance) {
+        Ok(true) => Some(best),
+        Ok(false) => None,
+        Err(e) => {
+            log::error!("Error verifying match for song ID {}: {}", best.song_id, e);
+            None
+        }
+    }
+}
 
-    println!("Debug: query_db - Querying with {} fingerprints.", query_fingerprints.len());
+/// Alignment check for a candidate: shifts every query fingerprint by the candidate's offset
+/// and counts how many find the same hash at (within VERIFY_TIME_TOLERANCE_FRAMES of) that
+/// anchor time in the song. Confirms the match if at least `min_fraction` of the query does.
+pub fn verify_match(
+    conn: &Connection,
+    query_fingerprints: &[Fingerprint],
+    candidate: &MatchResult,
+    min_fraction: f32,
+) -> SqlResult<bool> {
+    verify_alignment(conn, query_fingerprints, candidate, min_fraction, VERIFY_TIME_TOLERANCE_FRAMES)
+}
 
-    let mut offset_histograms: HashMap<SongId, HashMap<isize, usize>> = HashMap::new();
+// `verify_match` with anchor times allowed to differ by up to `time_tolerance` frames.
+fn verify_alignment(
+    conn: &Connection,
+    query_fingerprints: &[Fingerprint],
+    candidate: &MatchResult,
+    min_fraction: f32,
+    time_tolerance: isize,
+) -> SqlResult<bool> {
+    if query_fingerprints.is_empty() {
+        return Ok(false);
+    }
+    let aligned = aligned_query_fingerprints(conn, query_fingerprints, candidate, time_tolerance)?
+        .into_iter()
+        .filter(|&aligned| aligned)
+        .count();
+    let fraction = aligned as f32 / query_fingerprints.len() as f32;
+    log::debug!(
+        "verify_match - Song ID {}: {} of {} query fingerprints align at offset {} ({:.1}%, need {:.1}%).",
+        candidate.song_id, aligned, query_fingerprints.len(), candidate.time_offset_in_song_frames, fraction * 100.0, min_fraction * 100.0
+    );
+    Ok(fraction >= min_fraction)
+}
 
-    let mut stmt = match conn.prepare("SELECT song_id, anchor_time_idx FROM fingerprints WHERE hash = ?1") {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error preparing fingerprint query statement: {}", e);
-            return None;
-        }
-    };
+// For each query fingerprint, whether the candidate's song holds the same hash at (within
+// `time_tolerance` frames of) its anchor time shifted by the candidate's offset.
+fn aligned_query_fingerprints(
+    conn: &Connection,
+    query_fingerprints: &[Fingerprint],
+    candidate: &MatchResult,
+    time_tolerance: isize,
+) -> SqlResult<Vec<bool>> {
+    let offset = candidate.time_offset_in_song_frames;
+    let first_query_time = query_fingerprints.iter().map(|fp| fp.anchor_time_idx).min().unwrap_or(0) as isize;
+    let last_query_time = query_fingerprints.iter().map(|fp| fp.anchor_time_idx).max().unwrap_or(0) as isize;
 
-    for q_fp in query_fingerprints {
-        let hash_i64 = q_fp.hash as i64;
-        match stmt.query_map(params![hash_i64], |row| {
-            Ok((row.get::<_, i64>(0)? as SongId, row.get::<_, i64>(1)? as usize))
-        }) {
-            Ok(db_entries_iter) => {
-                for db_entry_result in db_entries_iter {
-                    match db_entry_result {
-                        Ok((db_song_id, db_anchor_time_idx)) => {
-                            let time_offset_delta = (db_anchor_time_idx as isize) - (q_fp.anchor_time_idx as isize);
-                            let song_histogram = offset_histograms.entry(db_song_id).or_insert_with(HashMap::new);
-                            *song_histogram.entry(time_offset_delta).or_insert(0) += 1;
-                        }
-                        Err(e) => {
-                            eprintln!("Error processing row from fingerprint query: {}", e);
-                        }
-                    }
-                }
-            }
+    // Only the part of the song the query overlaps at this offset is needed.
+    let schema = source_schema(conn, candidate.source_db)?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT hash, anchor_time_idx FROM {}.fingerprints
+         WHERE song_id = ?1 AND anchor_time_idx BETWEEN ?2 AND ?3",
+        schema
+    ))?;
+    let song_entries: std::collections::HashSet<(u64, isize)> = stmt.query_map(
+        params![
+            candidate.song_id as i64,
+            (first_query_time + offset - time_tolerance) as i64,
+            (last_query_time + offset + time_tolerance) as i64,
+        ],
+        |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as isize)),
+    )?.collect::<SqlResult<_>>()?;
+
+    Ok(query_fingerprints.iter()
+        .map(|fp| {
+            let song_time = fp.anchor_time_idx as isize + offset;
+            (-time_tolerance..=time_tolerance)
+                .any(|dt| song_entries.contains(&(fp.hash, song_time + dt)))
+        })
+        .collect())
+}
+
+/// Finds up to `max_matches` songs that overlap in one query, such as both sides of a DJ
+/// crossfade, whose split hits keep either song from standing out in a single pass. After
+/// each match, the query fingerprints aligned with it (as `verify_match` counts them) are
+/// removed and the remainder is matched again, until nothing scores `options.min_score` or,
+/// with `options.verify_min_fraction`, until a winner aligns with less than that share of
+/// the remainder. `options.min_query_coverage` is not applied. Matches come in the order
+/// found; a later match's confidence and coverage refer to the fingerprints left at that point.
+pub fn query_db_and_match_peeling(
+    conn: &Connection,
+    query_fingerprints: &[Fingerprint],
+    max_matches: usize,
+    options: &QueryOptions,
+) -> Vec<MatchResult> {
+    let time_tolerance = VERIFY_TIME_TOLERANCE_FRAMES.max(options.offset_tolerance_frames as isize);
+    let mut remaining = query_fingerprints.to_vec();
+    let mut matches = Vec::new();
+    while matches.len() < max_matches {
+        let Some(best) = query_db_and_match_topn(conn, &remaining, 1, options)
+            .into_iter()
+            .next()
+        else {
+            break;
+        };
+        let aligned = match aligned_query_fingerprints(conn, &remaining, &best, time_tolerance) {
+            Ok(aligned) => aligned,
             Err(e) => {
-                eprintln!("Error executing fingerprint query for hash {}: {}", hash_i64, e);
+                log::error!("Error aligning the query with song ID {}: {}", best.song_id, e);
+                break;
             }
+        };
+        let aligned_count = aligned.iter().filter(|&&aligned| aligned).count();
+        let fraction = aligned_count as f32 / remaining.len() as f32;
+        if aligned_count == 0 || options.verify_min_fraction.is_some_and(|min_fraction| fraction < min_fraction) {
+            log::debug!(
+                "query_db_peeling - Song ID {} aligns with only {} of {} remaining fingerprints; stopping.",
+                best.song_id, aligned_count, remaining.len()
+            );
+            break;
+        }
+        log::debug!(
+            "query_db_peeling - Match {}: song ID {} at offset {} (score {}); peeling {} of {} fingerprints.",
+            matches.len() + 1, best.song_id, best.time_offset_in_song_frames, best.score, aligned_count, remaining.len()
+        );
+        let mut aligned = aligned.into_iter();
+        remaining.retain(|_| !aligned.next().unwrap_or(false));
+        matches.push(best);
+        if remaining.is_empty() {
+            break;
         }
     }
+    matches
+}
 
-    if offset_histograms.is_empty() {
-        println!("Debug: query_db - No matching hashes found in DB for any query fingerprint.");
-        return None;
-    }
+/// Diagnostics for a query that found no match: the best offset of up to `n` songs sharing
+/// any aligned hash, by descending score, with no score threshold or verification. These
+/// are not match decisions; they show how close the query came.
+pub fn query_db_closest_candidates(
+    conn: &Connection,
+    query_fingerprints: &[Fingerprint],
+    n: usize,
+    frame_duration_seconds: f32,
+    max_hash_popularity: Option<usize>,
+) -> Vec<MatchResult> {
+    let options = QueryOptions {
+        min_score: 1,
+        frame_duration_seconds,
+        max_hash_popularity,
+        ..QueryOptions::default()
+    };
+    query_db_and_match_topn(conn, query_fingerprints, n, &options)
+}
 
-    println!("\nDebug: Offset Histograms (Song ID -> <Offset Delta -> Count>):");
-    for (song_id, histogram) in &offset_histograms {
-        println!("  Song ID {}:", song_id);
-        if histogram.is_empty() { println!("    (No matching offsets for this song)"); continue; }
-        let mut sorted_histogram: Vec<_> = histogram.iter().collect();
-        sorted_histogram.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
-        println!("    Top {} matching offsets:", sorted_histogram.len().min(5));
-        for (delta, count) in sorted_histogram.iter().take(5) {
-            println!("      Delta: {: >4}, Count: {}", delta, count);
-        }
-        if sorted_histogram.len() > 5 { println!("      ... and {} more.", sorted_histogram.len() - 5); }
+/// Returns up to `n` candidate matches (best offset per song), sorted by descending score,
+/// without coverage checks or verification. Candidates scoring below `options.min_score` are
+/// discarded. Hashes stored more than `options.max_hash_popularity` times across the
+/// database (typically percussive or near-silent landmarks shared by many songs) are
+/// ignored: they cost the most to look up and add noise. The scoring itself is
+/// `matching::OffsetHistogram`, fed with the rows SQLite returns. `options.weight_by_magnitude`
+/// also reads each row's stored strength, which the covering hash index doesn't hold, so
+/// the lookup is slower.
+pub fn query_db_and_match_topn(
+    conn: &Connection,
+    query_fingerprints: &[Fingerprint],
+    n: usize,
+    options: &QueryOptions,
+) -> Vec<MatchResult> {
+    let QueryOptions {
+        min_score, frame_duration_seconds, max_hash_popularity, weight_by_magnitude, offset_tolerance_frames, ..
+    } = *options;
+    if query_fingerprints.is_empty() {
+        log::debug!("query_db - Query has no fingerprints.");
+        return Vec::new();
     }
-    println!("--- END DEBUGGING CODE ---");
-
-    let mut best_match_overall: Option<MatchResult> = None;
-    for (song_id, histogram) in &offset_histograms {
-        if let Some((best_delta_for_song, &score_for_song)) = histogram.iter().max_by_key(|entry| entry.1) {
-            println!("Debug: query_db - For Song ID {}: Best offset_delta {} has score {}.", song_id, best_delta_for_song, score_for_song);
-            if best_match_overall.as_ref().map_or(true, |current_best| score_for_song > current_best.score) {
-                best_match_overall = Some(MatchResult {
-                    song_id: *song_id,
-                    score: score_for_song,
-                    time_offset_in_song_frames: *best_delta_for_song,
-                });
+
+    log::debug!("query_db - Querying with {} fingerprints.", query_fingerprints.len());
+
+    let databases = fingerprint_databases(conn).unwrap_or_else(|e| {
+        log::error!("Error listing attached databases; searching only the main one: {}", e);
+        vec!["main".to_string()]
+    });
+
+    // Each distinct query hash is looked up only once (per database).
+    let mut histogram = OffsetHistogram::new(query_fingerprints)
+        .with_magnitude_weighting(weight_by_magnitude)
+        .with_offset_tolerance(offset_tolerance_frames);
+    let mut distinct_hashes = histogram.distinct_hashes();
+    if let Some(max_popularity) = max_hash_popularity {
+        match count_hash_occurrences(conn, &distinct_hashes) {
+            Ok(counts) => {
+                let before = distinct_hashes.len();
+                distinct_hashes.retain(|h| counts.get(h).copied().unwrap_or(0) <= max_popularity);
+                log::debug!(
+                    "query_db - Skipping {} hashes stored more than {} times.",
+                    before - distinct_hashes.len(), max_popularity
+                );
             }
+            Err(e) => log::error!("Error counting hash popularity; using all hashes: {}", e),
         }
     }
+    log::debug!("query_db - {} distinct hashes to look up.", distinct_hashes.len());
+
+    for hash_chunk in distinct_hashes.chunks(HASH_LOOKUP_CHUNK_SIZE) {
+        // Numbered placeholders, so every database's branch of the UNION binds the same chunk.
+        let placeholders = (1..=hash_chunk.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ");
+        // Without weighting, strength stays NULL so the lookup is answered from the index alone.
+        let strength_column = if weight_by_magnitude { "strength" } else { "NULL" };
+        let sql = databases.iter().enumerate()
+            .map(|(source_db, schema)| format!(
+                "SELECT hash, song_id, anchor_time_idx, target_delta_frames, {}, {} FROM {}.fingerprints WHERE hash IN ({})",
+                source_db, strength_column, quote_schema(schema), placeholders
+            ))
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+        let mut stmt = match conn.prepare_cached(&sql) {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("Error preparing fingerprint query statement: {}", e);
+                return Vec::new();
+            }
+        };
 
-    if let Some(ref result) = best_match_overall {
-        const MIN_MATCH_SCORE: usize = 100;
-        if result.score < MIN_MATCH_SCORE {
-            println!("Debug: query_db - Best match score {} for Song ID {} is below threshold {}. Discarding.", result.score, result.song_id, MIN_MATCH_SCORE);
-            return None;
+        let rows = stmt.query_map(rusqlite::params_from_iter(hash_chunk.iter().map(|&h| h as i64)), |row| {
+            Ok((
+                row.get::<_, i64>(4)? as usize,
+                row.get::<_, Option<i64>>(5)?.map(|s| s.clamp(0, u8::MAX as i64) as u8),
+                (
+                    row.get::<_, i64>(0)? as u64,
+                    row.get::<_, i64>(1)? as SongId,
+                    row.get::<_, i64>(2)? as usize,
+                    row.get::<_, Option<i64>>(3)?.map(|d| d as usize),
+                ),
+            ))
+        });
+        let db_entries_iter = match rows {
+            Ok(iter) => iter,
+            Err(e) => {
+                log::error!("Error executing fingerprint query for {} hashes: {}", hash_chunk.len(), e);
+                continue;
+            }
+        };
+
+        for db_entry_result in db_entries_iter {
+            match db_entry_result {
+                Ok((source_db, strength, entry)) => histogram.add_with_strength(source_db, entry, strength),
+                Err(e) => log::error!("Error processing row from fingerprint query: {}", e),
+            }
         }
     }
 
-    if best_match_overall.is_some() {
-        println!("Debug: query_db - Found best overall match: {:?}", best_match_overall.as_ref().unwrap());
-    } else {
-        println!("Debug: query_db - No suitable match found after analyzing histograms.");
+    histogram.into_candidates(n, min_score, frame_duration_seconds)
+}
+
+/// Number of stored fingerprints for each of `hashes` (hashes that aren't stored are absent),
+/// summed over all searched databases. Answered from the covering hash index without
+/// touching the table.
+pub fn count_hash_occurrences(conn: &Connection, hashes: &[u64]) -> SqlResult<HashMap<u64, usize>> {
+    let mut counts = HashMap::with_capacity(hashes.len());
+    for schema in fingerprint_databases(conn)? {
+        for hash_chunk in hashes.chunks(HASH_LOOKUP_CHUNK_SIZE) {
+            let placeholders = vec!["?"; hash_chunk.len()].join(", ");
+            let sql = format!(
+                "SELECT hash, COUNT(*) FROM {}.fingerprints WHERE hash IN ({}) GROUP BY hash",
+                quote_schema(&schema), placeholders
+            );
+            let mut stmt = conn.prepare_cached(&sql)?;
+            let rows = stmt.query_map(rusqlite::params_from_iter(hash_chunk.iter().map(|&h| h as i64)), |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as usize))
+            })?;
+            for row in rows {
+                let (hash, count) = row?;
+                *counts.entry(hash).or_insert(0) += count;
+            }
+        }
     }
-    best_match_overall
+    Ok(counts)
 }
 
 pub fn get_song_info(conn: &Connection, song_id: SongId) -> SqlResult<Option<Song>> {
+    get_song_info_in(conn, 0, song_id)
+}
+
+/// `get_song_info` for a song of the `source_db`-th searched database, e.g. a match's
+/// `MatchResult::source_db`.
+pub fn get_song_info_in(conn: &Connection, source_db: usize, song_id: SongId) -> SqlResult<Option<Song>> {
+    let schema = source_schema(conn, source_db)?;
     conn.query_row(
-        "SELECT song_id, name, file_path FROM songs WHERE song_id = ?1",
+        &format!("SELECT song_id, name, file_path, duration_seconds, artist, album FROM {}.songs WHERE song_id = ?1", schema),
         params![song_id as i64],
         |row| {
             Ok(Song {
                 id: row.get::<_, i64>(0)? as SongId,
                 name: row.get(1)?,
                 file_path: row.get(2)?,
+                duration_seconds: row.get(3)?,
+                artist: row.get(4)?,
+                album: row.get(5)?,
             })
         },
     ).optional()
-}
\ No newline at end of file
+}
+
+/// The fingerprints stored for `song_id`, in insertion order (empty if the song doesn't exist).
+/// Unknown target deltas and strengths (rows from older versions) read as 0.
+pub fn get_song_fingerprints(conn: &Connection, song_id: SongId) -> SqlResult<Vec<Fingerprint>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT hash, anchor_time_idx, target_delta_frames, strength FROM fingerprints WHERE song_id = ?1 ORDER BY rowid",
+    )?;
+    let rows = stmt.query_map(params![song_id as i64], |row| {
+        Ok(Fingerprint {
+            hash: row.get::<_, i64>(0)? as u64,
+            anchor_time_idx: row.get::<_, i64>(1)? as usize,
+            target_delta_frames: row.get::<_, Option<i64>>(2)?.unwrap_or(0) as usize,
+            strength: row.get::<_, Option<i64>>(3)?.unwrap_or(0).clamp(0, u8::MAX as i64) as u8,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Number of fingerprints stored for `song_id` (0 if the song doesn't exist).
+pub fn get_song_fingerprint_count(conn: &Connection, song_id: SongId) -> SqlResult<usize> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM fingerprints WHERE song_id = ?1",
+        params![song_id as i64],
+        |row| row.get(0),
+    )?;
+    Ok(count as usize)
+}
+
+/// Gathers row counts and on-disk size information for the database.
+pub fn get_db_stats(conn: &Connection) -> SqlResult<DbStats> {
+    let song_count: i64 = conn.query_row("SELECT COUNT(*) FROM songs", [], |row| row.get(0))?;
+    let fingerprint_count: i64 = conn.query_row("SELECT COUNT(*) FROM fingerprints", [], |row| row.get(0))?;
+
+    let avg_fingerprints_per_song = if song_count > 0 {
+        fingerprint_count as f64 / song_count as f64
+    } else {
+        0.0
+    };
+
+    let db_path = conn.path().unwrap_or(DEFAULT_DB_FILE_NAME);
+    let db_file_size_bytes = std::fs::metadata(db_path).map(|m| m.len()).unwrap_or(0);
+    let wal_file_size_bytes = std::fs::metadata(format!("{}-wal", db_path)).map(|m| m.len()).ok();
+
+    Ok(DbStats {
+        song_count: song_count as usize,
+        fingerprint_count: fingerprint_count as usize,
+        avg_fingerprints_per_song,
+        db_file_size_bytes,
+        wal_file_size_bytes,
+    })
+}
+
+/// Refreshes query planner statistics (`PRAGMA optimize`), rebuilds every index (`REINDEX`)
+/// and rewrites the file without free pages (`VACUUM`), then truncates the WAL so the
+/// reclaimed space shows on disk. Keeps all data; worthwhile after large deletes or bulk
+/// enrollments.
+pub fn optimize_db(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch("PRAGMA optimize; REINDEX; VACUUM;")?;
+    checkpoint_wal(conn)?;
+    Ok(())
+}
+
+/// Copies everything in the `-wal` file into the database and truncates the WAL to zero
+/// bytes (`PRAGMA wal_checkpoint(TRUNCATE)`). SQLite only does this on its own when the
+/// last connection closes cleanly, so the WAL can otherwise grow large during bulk writes.
+/// Returns false if another connection's reads or writes kept it from finishing; the
+/// database is consistent either way. A no-op outside WAL mode.
+pub fn checkpoint_wal(conn: &Connection) -> SqlResult<bool> {
+    let busy: i64 = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+    if busy != 0 {
+        log::warn!("checkpoint_wal - Another connection is using the database; the WAL was not fully checkpointed.");
+    }
+    Ok(busy == 0)
+}
+
+/// Removes every fingerprint, song and stored parameter (in one transaction), then VACUUMs to
+/// reclaim disk space. The next enrollment may use different parameters.
+pub fn clear_db(conn: &mut Connection) -> SqlResult<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM fingerprints", [])?;
+    tx.execute("DELETE FROM songs", [])?;
+    tx.execute("DELETE FROM params", [])?;
+    tx.commit()?;
+    // VACUUM cannot run inside a transaction.
+    conn.execute_batch("VACUUM;")?;
+    Ok(())
+}
+
+/// Lists enrolled songs ordered by name, optionally filtered by a name substring and paginated.
+pub fn list_songs(
+    conn: &Connection,
+    filter: Option<&str>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> SqlResult<Vec<Song>> {
+    // SQLite treats a negative LIMIT as "no limit", which lets OFFSET work on its own.
+    let limit_i64 = limit.map_or(-1, |l| l as i64);
+    let offset_i64 = offset.unwrap_or(0) as i64;
+
+    let mut stmt = conn.prepare(
+        "SELECT song_id, name, file_path, duration_seconds, artist, album FROM songs
+         WHERE ?1 IS NULL OR name LIKE '%' || ?1 || '%'
+         ORDER BY name ASC
+         LIMIT ?2 OFFSET ?3",
+    )?;
+    let song_iter = stmt.query_map(params![filter, limit_i64, offset_i64], |row| {
+        Ok(Song {
+            id: row.get::<_, i64>(0)? as SongId,
+            name: row.get(1)?,
+            file_path: row.get(2)?,
+            duration_seconds: row.get(3)?,
+            artist: row.get(4)?,
+            album: row.get(5)?,
+        })
+    })?;
+    song_iter.collect()
+}
+
+/// Records the decoded audio duration of an enrolled song.
+pub fn set_song_duration(conn: &Connection, song_id: SongId, duration_seconds: f64) -> SqlResult<bool> {
+    let rows = conn.execute(
+        "UPDATE songs SET duration_seconds = ?1 WHERE song_id = ?2",
+        params![duration_seconds, song_id as i64],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Reads every stored fingerprinting parameter (see `store_params`) as key/value pairs.
+pub fn load_params(conn: &Connection) -> SqlResult<HashMap<String, String>> {
+    let mut stmt = conn.prepare("SELECT key, value FROM params")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Records the parameters the stored fingerprints were generated with. Keys that are already
+/// present keep their original value, so the first enrollment defines the database's settings.
+pub fn store_params(conn: &Connection, params: &[(&str, String)]) -> SqlResult<()> {
+    let mut stmt = conn.prepare_cached("INSERT OR IGNORE INTO params (key, value) VALUES (?1, ?2)")?;
+    for (key, value) in params {
+        stmt.execute(params![key, value])?;
+    }
+    Ok(())
+}
+
+/// Size and modification time of an enrolled file, used to skip re-enrolling files that
+/// have not changed since (see `find_unchanged_song`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size_bytes: u64,
+    /// Nanoseconds since the Unix epoch (negative before it).
+    pub modified_unix_ns: i64,
+}
+
+impl FileStamp {
+    /// Reads the stamp of the file at `path` from its metadata.
+    pub fn of(path: &Path) -> std::io::Result<FileStamp> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        let modified_unix_ns = match modified.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => after.as_nanos() as i64,
+            Err(before) => -(before.duration().as_nanos() as i64),
+        };
+        Ok(FileStamp { size_bytes: metadata.len(), modified_unix_ns })
+    }
+}
+
+/// Records the stamp of the file song `song_id` was enrolled from. Returns Ok(false) if no
+/// song with the given ID exists.
+pub fn set_song_file_stamp(conn: &Connection, song_id: SongId, stamp: FileStamp) -> SqlResult<bool> {
+    let rows = conn.execute(
+        "UPDATE songs SET file_size = ?1, file_mtime_ns = ?2 WHERE song_id = ?3",
+        params![stamp.size_bytes as i64, stamp.modified_unix_ns, song_id as i64],
+    )?;
+    Ok(rows > 0)
+}
+
+/// The song enrolled from `file_path`, if its recorded stamp equals `stamp`, i.e. the file
+/// has (most likely) not changed since. Songs enrolled without a stamp never match.
+pub fn find_unchanged_song(conn: &Connection, file_path: &str, stamp: FileStamp) -> SqlResult<Option<SongId>> {
+    conn.query_row(
+        "SELECT song_id FROM songs WHERE file_path = ?1 AND file_size = ?2 AND file_mtime_ns = ?3",
+        params![file_path, stamp.size_bytes as i64, stamp.modified_unix_ns],
+        |row| row.get::<_, i64>(0),
+    ).optional().map(|id| id.map(|id| id as SongId))
+}
+
+/// Updates a song's name and/or file path without touching its fingerprints. `None` leaves
+/// that field unchanged. Returns Ok(false) if no song with the given ID exists.
+/// A new path already used by another song fails with a UNIQUE constraint violation.
+pub fn update_song_metadata(
+    conn: &Connection,
+    song_id: SongId,
+    new_name: Option<&str>,
+    new_path: Option<&str>,
+) -> SqlResult<bool> {
+    let rows = conn.execute(
+        // A new path invalidates the stored file stamp.
+        "UPDATE songs SET name = COALESCE(?1, name), file_path = COALESCE(?2, file_path),
+             file_size = IIF(?2 IS NULL, file_size, NULL), file_mtime_ns = IIF(?2 IS NULL, file_mtime_ns, NULL)
+         WHERE song_id = ?3",
+        params![new_name, new_path, song_id as i64],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Deletes a song and (via ON DELETE CASCADE) all of its fingerprints.
+/// Returns Ok(false) if no song with the given ID existed.
+pub fn delete_song(conn: &mut Connection, song_id: SongId) -> Result<bool, SivanaError> {
+    let tx = conn.transaction().map_err(|e| SivanaError::sqlite("Failed to start delete transaction", e))?;
+    let rows_deleted = tx.execute("DELETE FROM songs WHERE song_id = ?1", params![song_id as i64])
+        .map_err(|e| SivanaError::sqlite(format!("Failed to delete song ID {}", song_id), e))?;
+    tx.commit().map_err(|e| SivanaError::sqlite("Failed to commit delete transaction", e))?;
+
+    if rows_deleted == 0 {
+        log::debug!("delete_song - No song found with ID {}.", song_id);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Records a query and its outcome (`None` if nothing matched) in the query log.
+/// Returns the new entry's ID. Song IDs from attached databases mean nothing in this one,
+/// so a match there is logged with its score but no song.
+pub fn log_query(conn: &Connection, snippet_path: Option<&str>, result: Option<&MatchResult>) -> SqlResult<i64> {
+    conn.execute(
+        "INSERT INTO query_log (snippet_path, song_id, score, confidence) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            snippet_path,
+            result.filter(|m| m.source_db == 0).map(|m| m.song_id as i64),
+            result.map(|m| m.score as i64),
+            result.map(|m| m.confidence as f64),
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// The `limit` most recent query log entries, newest first.
+pub fn recent_queries(conn: &Connection, limit: usize) -> SqlResult<Vec<QueryLogEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT q.query_id, q.queried_at, q.snippet_path, q.song_id, s.name, q.score, q.confidence
+         FROM query_log q LEFT JOIN songs s ON s.song_id = q.song_id
+         ORDER BY q.query_id DESC LIMIT ?1",
+    )?;
+    let entries = stmt.query_map(params![limit as i64], |row| {
+        Ok(QueryLogEntry {
+            query_id: row.get(0)?,
+            queried_at: row.get(1)?,
+            snippet_path: row.get(2)?,
+            song_id: row.get::<_, Option<i64>>(3)?.map(|id| id as SongId),
+            song_name: row.get(4)?,
+            score: row.get::<_, Option<i64>>(5)?.map(|s| s as usize),
+            confidence: row.get::<_, Option<f64>>(6)?.map(|c| c as f32),
+        })
+    })?;
+    entries.collect()
+}
@@ -0,0 +1,99 @@
+// src/error.rs
+use std::io;
+use std::path::PathBuf;
+
+use crate::database::SongId;
+
+/// Error type of the library's public API. Each variant carries enough context for its
+/// `Display` message to stand on its own, so callers can match on the cause or just print it.
+#[derive(Debug, thiserror::Error)]
+pub enum SivanaError {
+    /// Reading or writing a file failed.
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    /// Audio could not be loaded; the `LoadError` says why.
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    /// A database operation failed.
+    #[error("{context}: {source}")]
+    Sqlite {
+        context: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// The audio is shorter than one FFT window, so there is nothing to analyze.
+    #[error("Failed to generate spectrogram for song '{song_name}' (audio shorter than one FFT window?)")]
+    NoSpectrogram { song_name: String },
+    #[error("No peaks found for song '{song_name}'")]
+    NoPeaks { song_name: String },
+    #[error("No fingerprints generated for song '{song_name}'")]
+    NoFingerprints { song_name: String },
+    /// The audio is already enrolled (under a different file path); nothing was written.
+    #[error("The audio is already enrolled as song ID {existing_song_id}")]
+    DuplicateDetected { existing_song_id: SongId },
+    /// The database was built with other fingerprinting settings; one entry per differing setting.
+    #[error(
+        "Fingerprinting parameters do not match the ones this database was built with ({}). \
+         Hashes would never match; rerun with the database's settings.",
+        mismatches.join("; ")
+    )]
+    ParamMismatch { mismatches: Vec<String> },
+    #[error("No song found with ID {0}.")]
+    SongNotFound(SongId),
+    /// A sled fingerprint store operation failed.
+    #[cfg(feature = "sled")]
+    #[error("{context}: {source}")]
+    Sled {
+        context: String,
+        #[source]
+        source: sled::Error,
+    },
+    /// A fingerprint export file is malformed.
+    #[error("{0}")]
+    InvalidFormat(String),
+    /// An argument or setting is out of range (channel index, hash layout, ...).
+    #[error("{0}")]
+    InvalidInput(String),
+    /// Opening or running an audio capture device failed.
+    #[error("{0}")]
+    AudioDevice(String),
+}
+
+/// Why audio could not be turned into samples, so callers can tell a file to convert from a
+/// damaged one without parsing messages.
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Audio file '{}' does not exist", path.display())]
+    FileNotFound { path: PathBuf },
+    /// The container or codec isn't one Symphonia can read.
+    #[error("Unsupported audio format: {0}")]
+    UnsupportedFormat(String),
+    /// The container holds no decodable audio track (e.g. only video or metadata).
+    #[error("No compatible audio track found")]
+    NoAudioTrack,
+    /// The data is corrupt or truncated.
+    #[error("Failed to decode audio: {0}")]
+    DecodeFailed(String),
+    #[error("Failed to resample audio: {0}")]
+    ResampleFailed(String),
+    /// Decoding succeeded but yielded no samples.
+    #[error("No audio samples were decoded")]
+    Empty,
+}
+
+impl SivanaError {
+    pub(crate) fn io(context: impl Into<String>, source: io::Error) -> Self {
+        SivanaError::Io { context: context.into(), source }
+    }
+
+    pub(crate) fn sqlite(context: impl Into<String>, source: rusqlite::Error) -> Self {
+        SivanaError::Sqlite { context: context.into(), source }
+    }
+}
+
+/// Shorthand for results carrying a `SivanaError`.
+pub type Result<T> = std::result::Result<T, SivanaError>;
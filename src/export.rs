@@ -0,0 +1,195 @@
+// src/export.rs
+//! Moving one song's fingerprints between databases without the original audio.
+//!
+//! The file is plain text: `# key: value` header lines (song metadata plus the
+//! fingerprinting parameters, prefixed `param.`), then a CSV section of
+//! `hash,anchor_time_idx,target_delta_frames,strength` rows. Files from before the strength
+//! column was added are still imported, with unknown strengths.
+
+use rusqlite::{params, Connection};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::audio_loader::AudioTags;
+use crate::error::SivanaError;
+use crate::database::{
+    enroll_fingerprints, enroll_fingerprints_with_id, get_song_info, load_params, set_song_duration, store_params, SongId,
+};
+use crate::hashing::Fingerprint;
+
+const EXPORT_FORMAT_HEADER: &str = "# sivana-fingerprints v1";
+const CSV_HEADER: &str = "hash,anchor_time_idx,target_delta_frames,strength";
+const CSV_HEADER_WITHOUT_STRENGTH: &str = "hash,anchor_time_idx,target_delta_frames";
+const PARAM_KEY_PREFIX: &str = "param.";
+
+/// Writes song `song_id`'s metadata, the database's fingerprinting parameters and all of
+/// the song's fingerprints to `out`. Returns the number of fingerprints written.
+pub fn export_fingerprints(conn: &Connection, song_id: SongId, out: &Path) -> Result<usize, SivanaError> {
+    let song = get_song_info(conn, song_id)
+        .map_err(|e| SivanaError::sqlite(format!("Failed to read song ID {}", song_id), e))?
+        .ok_or(SivanaError::SongNotFound(song_id))?;
+    let mut stored_params: Vec<(String, String)> = load_params(conn)
+        .map_err(|e| SivanaError::sqlite("Failed to read fingerprinting parameters", e))?
+        .into_iter()
+        .collect();
+    stored_params.sort();
+
+    let file = File::create(out).map_err(|e| SivanaError::io(format!("Failed to create '{}'", out.display()), e))?;
+    let mut writer = BufWriter::new(file);
+    let write_err = |e: std::io::Error| SivanaError::io(format!("Failed to write '{}'", out.display()), e);
+
+    writeln!(writer, "{}", EXPORT_FORMAT_HEADER).map_err(write_err)?;
+    let mut header: Vec<(String, String)> = vec![
+        ("song_id".to_string(), song.id.to_string()),
+        ("name".to_string(), song.name.clone()),
+    ];
+    header.extend(song.file_path.map(|v| ("file_path".to_string(), v)));
+    header.extend(song.artist.map(|v| ("artist".to_string(), v)));
+    header.extend(song.album.map(|v| ("album".to_string(), v)));
+    header.extend(song.duration_seconds.map(|v| ("duration_seconds".to_string(), v.to_string())));
+    header.extend(stored_params.into_iter().map(|(k, v)| (format!("{}{}", PARAM_KEY_PREFIX, k), v)));
+    for (key, value) in &header {
+        // A newline inside a value would end its header line early.
+        writeln!(writer, "# {}: {}", key, value.replace(['\n', '\r'], " ")).map_err(write_err)?;
+    }
+    writeln!(writer, "{}", CSV_HEADER).map_err(write_err)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT hash, anchor_time_idx, target_delta_frames, strength FROM fingerprints WHERE song_id = ?1 ORDER BY rowid",
+    ).map_err(|e| SivanaError::sqlite(format!("Failed to read fingerprints for song ID {}", song_id), e))?;
+    let rows = stmt.query_map(params![song_id as i64], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<i64>>(2)?, row.get::<_, Option<i64>>(3)?))
+    }).map_err(|e| SivanaError::sqlite(format!("Failed to read fingerprints for song ID {}", song_id), e))?;
+
+    let mut count = 0;
+    for row in rows {
+        let (hash, anchor_time_idx, target_delta, strength) = row
+            .map_err(|e| SivanaError::sqlite(format!("Failed to read fingerprint row for song ID {}", song_id), e))?;
+        let target_delta = target_delta.map(|d| d.to_string()).unwrap_or_default();
+        let strength = strength.map(|s| s.to_string()).unwrap_or_default();
+        writeln!(writer, "{},{},{},{}", hash as u64, anchor_time_idx, target_delta, strength).map_err(write_err)?;
+        count += 1;
+    }
+    writer.flush().map_err(write_err)?;
+    log::info!("Exported {} fingerprints of song ID {} to '{}'.", count, song_id, out.display());
+    Ok(count)
+}
+
+/// Recreates a song and its fingerprints from a file written by `export_fingerprints`.
+/// The file's fingerprinting parameters must match the database's (an empty database adopts
+/// them). As with enrollment, an existing song with the same file path is replaced.
+pub fn import_fingerprints(conn: &mut Connection, input: &Path) -> Result<SongId, SivanaError> {
+    import_fingerprints_from(conn, input, false)
+}
+
+/// `import_fingerprints` that stores the song under the ID it had in the exporting database
+/// (see `enroll_fingerprints_with_id`), replacing whatever holds that ID here. Importing the
+/// same file again changes nothing. Errors if the file records no ID.
+pub fn import_fingerprints_keeping_id(conn: &mut Connection, input: &Path) -> Result<SongId, SivanaError> {
+    import_fingerprints_from(conn, input, true)
+}
+
+fn import_fingerprints_from(conn: &mut Connection, input: &Path, keep_song_id: bool) -> Result<SongId, SivanaError> {
+    let file = File::open(input).map_err(|e| SivanaError::io(format!("Failed to open '{}'", input.display()), e))?;
+    let mut lines = BufReader::new(file).lines().enumerate();
+    let read_err = |e: std::io::Error| SivanaError::io(format!("Failed to read '{}'", input.display()), e);
+
+    let first_line = lines.next().map(|(_, line)| line).transpose().map_err(read_err)?;
+    if first_line.as_deref().map(str::trim) != Some(EXPORT_FORMAT_HEADER) {
+        return Err(SivanaError::InvalidFormat(format!("'{}' is not a Sivana fingerprint export.", input.display())));
+    }
+
+    let mut header: Vec<(String, String)> = Vec::new();
+    let mut fingerprints: Vec<Fingerprint> = Vec::new();
+    let mut in_rows = false;
+    for (line_idx, line) in lines {
+        let line = line.map_err(read_err)?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if !in_rows {
+            if let Some(entry) = line.strip_prefix("# ") {
+                let (key, value) = entry.split_once(": ")
+                    .ok_or_else(|| SivanaError::InvalidFormat(format!("Malformed header on line {}: '{}'", line_idx + 1, line)))?;
+                header.push((key.to_string(), value.to_string()));
+                continue;
+            }
+            if line == CSV_HEADER || line == CSV_HEADER_WITHOUT_STRENGTH {
+                in_rows = true;
+                continue;
+            }
+            return Err(SivanaError::InvalidFormat(format!("Unexpected line {} before the fingerprint rows: '{}'", line_idx + 1, line)));
+        }
+        fingerprints.push(
+            parse_fingerprint_row(line).map_err(|e| SivanaError::InvalidFormat(format!("Line {}: {}", line_idx + 1, e)))?,
+        );
+    }
+
+    let header_value = |key: &str| header.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+    let song_id = if keep_song_id {
+        let value = header_value("song_id")
+            .ok_or_else(|| SivanaError::InvalidFormat(format!("'{}' has no song_id header (exported by an older version).", input.display())))?;
+        let song_id = value.parse::<i64>().ok().and_then(|id| SongId::try_from(id).ok()).ok_or_else(|| {
+            SivanaError::InvalidFormat(format!("'{}' has song_id '{}', which is not in 0..={}.", input.display(), value, SongId::MAX))
+        })?;
+        Some(song_id)
+    } else {
+        None
+    };
+    let song_name = header_value("name")
+        .ok_or_else(|| SivanaError::InvalidFormat(format!("'{}' has no song name header.", input.display())))?;
+    let song_file_path = header_value("file_path");
+    let song_tags = AudioTags { title: None, artist: header_value("artist"), album: header_value("album") };
+    let duration_seconds = header_value("duration_seconds").and_then(|v| v.parse::<f64>().ok());
+    if fingerprints.is_empty() {
+        return Err(SivanaError::InvalidFormat(format!("'{}' contains no fingerprints.", input.display())));
+    }
+
+    let file_params: Vec<(&str, String)> = header.iter()
+        .filter_map(|(k, v)| k.strip_prefix(PARAM_KEY_PREFIX).map(|k| (k, v.clone())))
+        .collect();
+    let db_params = load_params(conn).map_err(|e| SivanaError::sqlite("Failed to read fingerprinting parameters", e))?;
+    let mismatches: Vec<String> = file_params.iter()
+        .filter_map(|(key, value)| match db_params.get(*key) {
+            Some(db_value) if db_value != value => Some(format!("{}: database uses {}, '{}' uses {}", key, db_value, input.display(), value)),
+            _ => None,
+        })
+        .collect();
+    if !mismatches.is_empty() {
+        return Err(SivanaError::ParamMismatch { mismatches });
+    }
+
+    let song_id = match song_id {
+        Some(song_id) => enroll_fingerprints_with_id(conn, song_id, &song_name, song_file_path.as_deref(), &song_tags, &fingerprints)?,
+        None => enroll_fingerprints(conn, &song_name, song_file_path.as_deref(), &song_tags, &fingerprints)?,
+    };
+    store_params(conn, &file_params).map_err(|e| SivanaError::sqlite("Failed to store fingerprinting parameters", e))?;
+    if let Some(duration) = duration_seconds {
+        set_song_duration(conn, song_id, duration)
+            .map_err(|e| SivanaError::sqlite(format!("Failed to store duration for song ID {}", song_id), e))?;
+    }
+    log::info!("Imported {} fingerprints from '{}' as song ID {}.", fingerprints.len(), input.display(), song_id);
+    Ok(song_id)
+}
+
+fn parse_fingerprint_row(line: &str) -> Result<Fingerprint, String> {
+    let mut fields = line.split(',');
+    let mut next_field = |name: &str| fields.next().map(str::trim).ok_or_else(|| format!("missing {} in '{}'", name, line));
+    let hash = next_field("hash")?.parse::<u64>().map_err(|e| format!("bad hash in '{}': {}", line, e))?;
+    let anchor_time_idx = next_field("anchor_time_idx")?.parse::<usize>()
+        .map_err(|e| format!("bad anchor_time_idx in '{}': {}", line, e))?;
+    let target_delta = next_field("target_delta_frames")?;
+    if target_delta.is_empty() {
+        return Err("fingerprint has no target_delta_frames (exported from an older database); re-enroll the song from audio instead".to_string());
+    }
+    let target_delta_frames = target_delta.parse::<usize>()
+        .map_err(|e| format!("bad target_delta_frames in '{}': {}", line, e))?;
+    // Empty or missing for fingerprints whose strength was never recorded.
+    let strength = match fields.next().map(str::trim) {
+        None | Some("") => 0,
+        Some(s) => s.parse::<u8>().map_err(|e| format!("bad strength in '{}': {}", line, e))?,
+    };
+    Ok(Fingerprint { hash, anchor_time_idx, target_delta_frames, strength })
+}
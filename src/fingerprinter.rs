@@ -0,0 +1,530 @@
+// src/fingerprinter.rs
+use rusqlite::Connection;
+use std::borrow::Cow;
+
+use crate::audio_loader::{AudioStream, AudioTags};
+use crate::database::{
+    enroll_fingerprint_stream, enroll_fingerprints_with_progress, enroll_song_with_progress, fingerprint_samples,
+    find_content_duplicate, load_params, query_db_and_match, query_db_and_match_peeling, store_params, EnrollStage, MatchResult, QueryOptions, SongId,
+};
+use crate::error::SivanaError;
+use crate::matching::{match_fingerprints, OffsetMatch};
+use crate::hashing::{create_hashes, create_hashes_with_stats, fingerprints_in_window, Fingerprint, HashStats, StreamingHasher, HashConfig, TargetZone};
+use crate::peaks::{find_peaks, Peak, PeakParams, StreamingPeakFinder};
+use crate::spectrogram::{bin_to_hz, MagnitudeScale, SpectrogramBuilder, StreamingSpectrogram, WindowType};
+use crate::store::{enroll_in_store, match_in_store, FingerprintStore};
+
+// Default pipeline parameters (these match what the CLI has always used)
+pub const DEFAULT_SAMPLE_RATE: u32 = 22050;
+pub const DEFAULT_FFT_WINDOW_SIZE: usize = 2048;
+pub const DEFAULT_FFT_HOPSIZE: usize = 1024;
+
+/// Counts from one `Fingerprinter::fingerprint_with_stats` run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FingerprintStats {
+    /// Spectrogram frames.
+    pub frames: usize,
+    /// Peaks in the constellation.
+    pub peaks: usize,
+    pub hashes: HashStats,
+}
+
+/// Bundles the spectrogram / peak / hashing parameters so callers don't have to
+/// thread them through every enroll and query call themselves.
+#[derive(Debug, Clone)]
+pub struct Fingerprinter {
+    pub sample_rate: u32,
+    pub window_size: usize,
+    pub hop_size: usize,
+    pub window_type: WindowType,
+    /// When switching to decibels or power, `peak_params.min_magnitude` must be on the same
+    /// scale.
+    pub magnitude_scale: MagnitudeScale,
+    /// Zero-pad the audio after the last full window into one more frame, so short clips
+    /// (even shorter than a window) and trailing samples are fingerprinted.
+    pub pad_final_frame: bool,
+    /// Center frame `i` on sample `i * hop_size` (reflect-padding both ends by half a window)
+    /// instead of starting it there. Frame times (`frames_to_seconds`) then refer to frame
+    /// centers. Changes every hash, so it is stored with the other parameters.
+    pub centered_frames: bool,
+    /// Divide FFT magnitudes by the window's sum (see
+    /// `SpectrogramBuilder::with_normalized_magnitudes`), so `peak_params.min_magnitude` means
+    /// the same at every window size; it must then be on that much smaller scale (about half a
+    /// sinusoid's amplitude), and so must `peak_params.min_frame_energy` (see
+    /// `scale_peak_params_for_normalization`). Changes every hash, so it is stored with the
+    /// other parameters.
+    pub normalized_magnitudes: bool,
+    pub peak_params: PeakParams,
+    pub target_zone: TargetZone,
+    pub hash_config: HashConfig,
+    /// Pool each frame into this many mel bands before peak picking (speech-oriented; see the
+    /// `mel` module). Peaks and hashes then refer to mel band indices.
+    #[cfg(feature = "mel")]
+    pub mel_bands: Option<usize>,
+    // Planned once for `window_size` and reused for every call.
+    spectrogram_builder: SpectrogramBuilder,
+}
+
+impl Default for Fingerprinter {
+    fn default() -> Self {
+        Fingerprinter::new(DEFAULT_SAMPLE_RATE, DEFAULT_FFT_WINDOW_SIZE, DEFAULT_FFT_HOPSIZE)
+    }
+}
+
+impl Fingerprinter {
+    /// Creates a fingerprinter with the default peak and hash parameters.
+    pub fn new(sample_rate: u32, window_size: usize, hop_size: usize) -> Self {
+        Fingerprinter {
+            sample_rate,
+            window_size,
+            hop_size,
+            window_type: WindowType::default(),
+            magnitude_scale: MagnitudeScale::default(),
+            pad_final_frame: false,
+            centered_frames: false,
+            normalized_magnitudes: false,
+            peak_params: PeakParams::default(),
+            target_zone: TargetZone::default(),
+            hash_config: HashConfig::default(),
+            #[cfg(feature = "mel")]
+            mel_bands: None,
+            spectrogram_builder: SpectrogramBuilder::new(window_size),
+        }
+    }
+
+    /// Returns the cached spectrogram builder, or a fresh one if the spectrogram
+    /// settings were changed after construction.
+    fn spectrogram_builder(&self) -> Cow<'_, SpectrogramBuilder> {
+        if self.spectrogram_builder.window_size() == self.window_size
+            && self.spectrogram_builder.window_type() == self.window_type
+            && self.spectrogram_builder.magnitude_scale() == self.magnitude_scale
+            && self.spectrogram_builder.pad_final_frame() == self.pad_final_frame
+            && self.spectrogram_builder.center() == self.centered_frames
+            && self.spectrogram_builder.normalized_magnitudes() == self.normalized_magnitudes
+            && self.spectrogram_builder.mel_bands() == self.mel_bands()
+        {
+            Cow::Borrowed(&self.spectrogram_builder)
+        } else {
+            let builder = SpectrogramBuilder::with_window(self.window_size, self.window_type)
+                .with_magnitude_scale(self.magnitude_scale)
+                .with_pad_final_frame(self.pad_final_frame)
+                .with_center(self.centered_frames)
+                .with_normalized_magnitudes(self.normalized_magnitudes);
+            #[cfg(feature = "mel")]
+            let builder = match self.mel_bands {
+                Some(num_bands) => builder.with_mel_bands(self.sample_rate, num_bands),
+                None => builder,
+            };
+            Cow::Owned(builder)
+        }
+    }
+
+    /// Mel bands per frame, or `None` for FFT bins (always `None` without the `mel` feature).
+    pub fn mel_bands(&self) -> Option<usize> {
+        #[cfg(feature = "mel")]
+        {
+            self.mel_bands
+        }
+        #[cfg(not(feature = "mel"))]
+        {
+            None
+        }
+    }
+
+    /// The spectrogram of mono samples already at `self.sample_rate` (first pipeline stage).
+    pub fn spectrogram(&self, samples: &[f32]) -> Vec<Vec<f32>> {
+        self.spectrogram_builder().build(samples, self.hop_size)
+    }
+
+    /// The constellation of peaks in a spectrogram from `spectrogram` (second pipeline stage).
+    pub fn peaks(&self, spectrogram: &[Vec<f32>]) -> Vec<Peak> {
+        find_peaks(spectrogram, self.peak_params)
+    }
+
+    /// Runs spectrogram -> peaks (the constellation) on mono samples already at
+    /// `self.sample_rate`, returning both.
+    pub fn spectrogram_and_peaks(&self, samples: &[f32]) -> (Vec<Vec<f32>>, Vec<Peak>) {
+        let spectrogram = self.spectrogram(samples);
+        let peaks = self.peaks(&spectrogram);
+        (spectrogram, peaks)
+    }
+
+    /// Runs spectrogram -> peaks -> hashes on mono samples already at `self.sample_rate`.
+    pub fn fingerprint(&self, samples: &[f32]) -> Vec<Fingerprint> {
+        let (_, peaks) = self.spectrogram_and_peaks(samples);
+        create_hashes(&peaks, self.target_zone, self.hash_config)
+    }
+
+    /// `fingerprint`, also reporting how many frames and peaks the fingerprints came from
+    /// (hashing always runs single-threaded here; the fingerprints are the same).
+    pub fn fingerprint_with_stats(&self, samples: &[f32]) -> (Vec<Fingerprint>, FingerprintStats) {
+        let (spectrogram, peaks) = self.spectrogram_and_peaks(samples);
+        let (fingerprints, hashes) = create_hashes_with_stats(&peaks, self.target_zone, self.hash_config);
+        (fingerprints, FingerprintStats { frames: spectrogram.len(), peaks: peaks.len(), hashes })
+    }
+
+    /// Starts an incremental fingerprinting pass; see `FingerprintStream`.
+    pub fn fingerprint_stream(&self) -> FingerprintStream {
+        FingerprintStream {
+            spectrogram: StreamingSpectrogram::new(self.spectrogram_builder().into_owned(), self.hop_size),
+            peak_finder: StreamingPeakFinder::new(self.peak_params),
+            hasher: StreamingHasher::new(self.target_zone, self.hash_config),
+        }
+    }
+
+    /// Decodes, fingerprints and stores `audio` chunk by chunk, so neither the decoded samples
+    /// nor the full fingerprint list are ever held in memory. Decoding stops after
+    /// `max_samples` samples when given. Unlike `enroll_unique` there is no duplicate check,
+    /// as that needs every fingerprint up front. Since all stages run per chunk, `progress`
+    /// only sees `EnrollStage::Decoding` (when the file declares its length, or `max_samples`
+    /// bounds it). Returns the song ID and the number of samples fingerprinted (for the
+    /// song's duration).
+    #[allow(clippy::too_many_arguments)]
+    pub fn enroll_stream(
+        &self,
+        conn: &mut Connection,
+        song_name: &str,
+        song_file_path: Option<&str>,
+        song_tags: &AudioTags,
+        audio: &mut AudioStream,
+        max_samples: Option<usize>,
+        progress: Option<&dyn Fn(EnrollStage, f32)>,
+    ) -> Result<(SongId, usize), SivanaError> {
+        let mut stream = Some(self.fingerprint_stream());
+        let mut samples_seen = 0usize;
+        // Whole percents already reported, so a callback isn't invoked for every packet.
+        let mut reported_percent = None;
+        let next_batch = || -> Result<Option<Vec<Fingerprint>>, SivanaError> {
+            while let Some(fingerprint_stream) = stream.as_mut() {
+                let remaining = max_samples.map_or(usize::MAX, |max| max.saturating_sub(samples_seen));
+                let chunk = if remaining == 0 { None } else { audio.next_chunk()? };
+                let fingerprints = match chunk {
+                    Some(chunk) => {
+                        let chunk = &chunk[..chunk.len().min(remaining)];
+                        samples_seen += chunk.len();
+                        if let Some(progress) = progress {
+                            let by_limit = max_samples.map(|max| samples_seen as f32 / max.max(1) as f32);
+                            let fraction = match (audio.fraction_decoded(), by_limit) {
+                                (Some(a), Some(b)) => Some(a.max(b)),
+                                (a, b) => a.or(b),
+                            };
+                            if let Some(fraction) = fraction {
+                                let percent = (fraction.min(1.0) * 100.0) as u32;
+                                if reported_percent != Some(percent) {
+                                    reported_percent = Some(percent);
+                                    progress(EnrollStage::Decoding, fraction.min(1.0));
+                                }
+                            }
+                        }
+                        fingerprint_stream.push_samples(chunk)
+                    }
+                    None => stream.take().map(FingerprintStream::finish).unwrap_or_default(),
+                };
+                if !fingerprints.is_empty() {
+                    return Ok(Some(fingerprints));
+                }
+            }
+            Ok(None)
+        };
+        let (song_id, fingerprint_count) = enroll_fingerprint_stream(conn, song_name, song_file_path, song_tags, next_batch)?;
+        log::info!("Streamed {} fingerprints from {} samples for song '{}'", fingerprint_count, samples_seen, song_name);
+        Ok((song_id, samples_seen))
+    }
+
+    /// Fingerprints `samples` and stores them under a new (or existing, by file path) song.
+    /// `samples` are mono at `self.sample_rate`, from `audio_loader` or any other decoder;
+    /// `song_file_path` is only recorded, never read.
+    pub fn enroll(
+        &self,
+        conn: &mut Connection,
+        song_name: &str,
+        song_file_path: Option<&str>,
+        song_tags: &AudioTags,
+        samples: &[f32],
+    ) -> Result<SongId, SivanaError> {
+        self.enroll_with_progress(conn, song_name, song_file_path, song_tags, samples, None)
+    }
+
+    /// `enroll`, reporting each stage to `progress` (see `enroll_song_with_progress`).
+    pub fn enroll_with_progress(
+        &self,
+        conn: &mut Connection,
+        song_name: &str,
+        song_file_path: Option<&str>,
+        song_tags: &AudioTags,
+        samples: &[f32],
+        progress: Option<&dyn Fn(EnrollStage, f32)>,
+    ) -> Result<SongId, SivanaError> {
+        enroll_song_with_progress(
+            conn,
+            song_name,
+            song_file_path,
+            song_tags,
+            samples,
+            &self.spectrogram_builder(), self.hop_size,
+            self.peak_params, self.target_zone, self.hash_config,
+            progress,
+        )
+    }
+
+    /// Like `enroll`, but first checks whether the same audio is already enrolled under another
+    /// path and, if so, fails with `SivanaError::DuplicateDetected` without writing anything.
+    pub fn enroll_unique(
+        &self,
+        conn: &mut Connection,
+        song_name: &str,
+        song_file_path: Option<&str>,
+        song_tags: &AudioTags,
+        samples: &[f32],
+    ) -> Result<SongId, SivanaError> {
+        self.enroll_unique_with_progress(conn, song_name, song_file_path, song_tags, samples, None)
+    }
+
+    /// `enroll_unique`, reporting each stage to `progress`.
+    pub fn enroll_unique_with_progress(
+        &self,
+        conn: &mut Connection,
+        song_name: &str,
+        song_file_path: Option<&str>,
+        song_tags: &AudioTags,
+        samples: &[f32],
+        progress: Option<&dyn Fn(EnrollStage, f32)>,
+    ) -> Result<SongId, SivanaError> {
+        let fingerprints = fingerprint_samples(
+            song_name, samples, &self.spectrogram_builder(), self.hop_size,
+            self.peak_params, self.target_zone, self.hash_config, progress,
+        )?;
+        self.store_fingerprints(conn, song_name, song_file_path, song_tags, &fingerprints, true, progress)
+    }
+
+    /// The fingerprints `enroll` would store for `samples`, computed without a connection so
+    /// several files can be fingerprinted in parallel and then stored one at a time with
+    /// `enroll_fingerprinted`. Fails like `enroll` when a stage produces nothing.
+    pub fn fingerprint_for_enrollment(&self, song_name: &str, samples: &[f32]) -> Result<Vec<Fingerprint>, SivanaError> {
+        fingerprint_samples(
+            song_name, samples, &self.spectrogram_builder(), self.hop_size,
+            self.peak_params, self.target_zone, self.hash_config, None,
+        )
+    }
+
+    /// Stores fingerprints from `fingerprint_for_enrollment`. With `unique`, first runs the
+    /// duplicate check of `enroll_unique`.
+    pub fn enroll_fingerprinted(
+        &self,
+        conn: &mut Connection,
+        song_name: &str,
+        song_file_path: Option<&str>,
+        song_tags: &AudioTags,
+        fingerprints: &[Fingerprint],
+        unique: bool,
+    ) -> Result<SongId, SivanaError> {
+        self.store_fingerprints(conn, song_name, song_file_path, song_tags, fingerprints, unique, None)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn store_fingerprints(
+        &self,
+        conn: &mut Connection,
+        song_name: &str,
+        song_file_path: Option<&str>,
+        song_tags: &AudioTags,
+        fingerprints: &[Fingerprint],
+        unique: bool,
+        progress: Option<&dyn Fn(EnrollStage, f32)>,
+    ) -> Result<SongId, SivanaError> {
+        if !unique {
+            return enroll_fingerprints_with_progress(conn, song_name, song_file_path, song_tags, fingerprints, progress);
+        }
+        let duplicate = find_content_duplicate(conn, fingerprints, song_file_path, self.frame_duration_seconds())
+            .map_err(|e| SivanaError::sqlite(format!("Failed to check for duplicates of '{}'", song_name), e))?;
+        if let Some(existing) = duplicate {
+            log::info!(
+                "Duplicate check: '{}' matches song ID {} (score {}, confidence {:.1}%).",
+                song_name, existing.song_id, existing.score, existing.confidence * 100.0
+            );
+            return Err(SivanaError::DuplicateDetected { existing_song_id: existing.song_id });
+        }
+
+        enroll_fingerprints_with_progress(conn, song_name, song_file_path, song_tags, fingerprints, progress)
+    }
+
+    /// Every setting that affects which hashes get generated, as stored in the `params` table.
+    pub fn params(&self) -> Vec<(&'static str, String)> {
+        let PeakParams { time_radius, freq_radius, min_magnitude, max_peaks_per_frame, min_frame_energy } = self.peak_params;
+        vec![
+            ("sample_rate", self.sample_rate.to_string()),
+            ("window_size", self.window_size.to_string()),
+            ("hop_size", self.hop_size.to_string()),
+            ("window_type", format!("{:?}", self.window_type)),
+            ("magnitude_scale", format!("{:?}", self.magnitude_scale)),
+            ("centered_frames", self.centered_frames.to_string()),
+            ("normalized_magnitudes", self.normalized_magnitudes.to_string()),
+            ("mel_bands", self.mel_bands().map_or_else(|| "none".to_string(), |n| n.to_string())),
+            ("peak_time_radius", time_radius.to_string()),
+            ("peak_freq_radius", freq_radius.to_string()),
+            ("peak_min_magnitude", min_magnitude.to_string()),
+            ("max_peaks_per_frame", max_peaks_per_frame.map_or_else(|| "none".to_string(), |n| n.to_string())),
+            ("peak_min_frame_energy", min_frame_energy.to_string()),
+            ("target_zone_dt_min_frames", self.target_zone.dt_min_frames().to_string()),
+            ("target_zone_dt_max_frames", self.target_zone.dt_max_frames().to_string()),
+            ("target_zone_df_abs_max_bins", self.target_zone.df_abs_max_bins().to_string()),
+            ("max_pairs_per_anchor", self.target_zone.max_pairs_per_anchor().to_string()),
+            ("hash_freq_bits", self.hash_config.freq_bits().to_string()),
+            ("hash_delta_time_bits", self.hash_config.delta_time_bits().to_string()),
+        ]
+    }
+
+    /// Records this fingerprinter's settings in the database (first writer wins).
+    pub fn store_params(&self, conn: &Connection) -> Result<(), SivanaError> {
+        store_params(conn, &self.params()).map_err(|e| SivanaError::sqlite("Failed to store fingerprinting parameters", e))
+    }
+
+    /// Errors, naming every differing setting, if the database was enrolled with other settings.
+    /// A database with no stored parameters (empty, or created by an older version) always passes.
+    pub fn check_params(&self, conn: &Connection) -> Result<(), SivanaError> {
+        let stored = load_params(conn).map_err(|e| SivanaError::sqlite("Failed to read stored fingerprinting parameters", e))?;
+        let mismatches: Vec<String> = self.params().into_iter()
+            .filter_map(|(key, value)| match stored.get(key) {
+                Some(stored_value) if *stored_value != value => {
+                    Some(format!("{}: database uses {}, current setting is {}", key, stored_value, value))
+                }
+                _ => None,
+            })
+            .collect();
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(SivanaError::ParamMismatch { mismatches })
+        }
+    }
+
+    /// Fingerprints `samples` and returns the best database match scoring at least `min_score`
+    /// that also passes the alignment check (`verify_match` at DEFAULT_VERIFY_MIN_FRACTION).
+    /// Queries where under DEFAULT_MIN_QUERY_COVERAGE of the fingerprints hit anything
+    /// are treated as unknown audio.
+    pub fn identify(&self, conn: &Connection, samples: &[f32], min_score: usize) -> Option<MatchResult> {
+        let fingerprints = self.fingerprint(samples);
+        query_db_and_match(conn, &fingerprints, &self.query_options(min_score))
+    }
+
+    /// Like `identify`, but finds up to `max_songs` songs overlapping in `samples` (e.g. across
+    /// a DJ crossfade) by peeling off each match's fingerprints; see `query_db_and_match_peeling`.
+    pub fn identify_overlapping(&self, conn: &Connection, samples: &[f32], max_songs: usize, min_score: usize) -> Vec<MatchResult> {
+        let fingerprints = self.fingerprint(samples);
+        query_db_and_match_peeling(conn, &fingerprints, max_songs, &self.query_options(min_score))
+    }
+
+    /// `identify` on the part of already computed `fingerprints` (e.g. of a growing live
+    /// buffer) anchored in frames `start_frame..end_frame`, without fingerprinting again.
+    /// Offsets and match times refer to `start_frame`.
+    pub fn identify_window(
+        &self,
+        conn: &Connection,
+        fingerprints: &[Fingerprint],
+        start_frame: usize,
+        end_frame: usize,
+        min_score: usize,
+    ) -> Option<MatchResult> {
+        query_db_and_match(conn, &fingerprints_in_window(fingerprints, start_frame, end_frame), &self.query_options(min_score))
+    }
+
+    /// Fingerprints `samples` and adds them as a new song to any `FingerprintStore`.
+    pub fn enroll_in_store<S: FingerprintStore + ?Sized>(
+        &self,
+        store: &mut S,
+        song_name: &str,
+        song_file_path: Option<&str>,
+        song_tags: &AudioTags,
+        samples: &[f32],
+    ) -> Result<SongId, SivanaError> {
+        enroll_in_store(store, song_name, song_file_path, song_tags, &self.fingerprint(samples))
+    }
+
+    /// Fingerprints `samples` and returns the best match in `store` scoring at least
+    /// `min_score` (no alignment check, unlike `identify`).
+    pub fn identify_in_store<S: FingerprintStore + ?Sized>(
+        &self,
+        store: &S,
+        samples: &[f32],
+        min_score: usize,
+    ) -> Result<Option<MatchResult>, SivanaError> {
+        let matches = match_in_store(store, &self.fingerprint(samples), 1, min_score, self.frame_duration_seconds())?;
+        Ok(matches.into_iter().next())
+    }
+
+    /// Fingerprints both recordings and finds where `probe` best aligns inside `reference`,
+    /// without touching a database.
+    pub fn compare(&self, reference: &[f32], probe: &[f32]) -> Option<OffsetMatch> {
+        match_fingerprints(&self.fingerprint(reference), &self.fingerprint(probe))
+    }
+
+    /// Converts a spectrogram frame offset into seconds.
+    pub fn frames_to_seconds(&self, frames: isize) -> f32 {
+        (frames as f32 * self.hop_size as f32) / self.sample_rate as f32
+    }
+
+    /// Rescales `peak_params` chosen for raw magnitudes to normalized ones (see
+    /// `normalized_magnitudes`) from windows summing to `window_sum`: the magnitude threshold
+    /// is divided by `window_sum` and the frame energy floor, a sum of squares, by its square
+    /// (with `MagnitudeScale::Power`, by the square and fourth power). Set
+    /// `magnitude_scale` first.
+    pub fn scale_peak_params_for_normalization(&mut self, window_sum: f32) {
+        let magnitude_scale = if self.magnitude_scale == MagnitudeScale::Power { window_sum * window_sum } else { window_sum };
+        self.peak_params.min_magnitude /= magnitude_scale;
+        self.peak_params.min_frame_energy /= magnitude_scale * magnitude_scale;
+    }
+
+    /// Seconds between successive spectrogram frames (one hop).
+    pub fn frame_duration_seconds(&self) -> f32 {
+        self.frames_to_seconds(1)
+    }
+
+    /// The default `QueryOptions` for this fingerprinter's frames, accepting matches that
+    /// score at least `min_score`.
+    pub fn query_options(&self, min_score: usize) -> QueryOptions {
+        QueryOptions { min_score, frame_duration_seconds: self.frame_duration_seconds(), ..QueryOptions::default() }
+    }
+
+    /// Frequency of a peak's `freq_bin_idx` in Hz, or `None` with mel bands (band indices
+    /// are not FFT bins).
+    pub fn bin_to_hz(&self, bin: usize) -> Option<f32> {
+        self.mel_bands().is_none().then(|| bin_to_hz(bin, self.sample_rate, self.window_size))
+    }
+}
+
+/// Incremental spectrogram -> peaks -> hashes for audio fed in consecutive chunks of any
+/// size. Each stage only buffers the overlap it needs (window/hop samples, the peak time
+/// radius, the target zone), and the fingerprints from all `push_samples` calls plus
+/// `finish` are exactly those `Fingerprinter::fingerprint` returns for the whole signal.
+#[derive(Debug, Clone)]
+pub struct FingerprintStream {
+    spectrogram: StreamingSpectrogram,
+    peak_finder: StreamingPeakFinder,
+    hasher: StreamingHasher,
+}
+
+impl FingerprintStream {
+    /// Feeds the next mono samples (at the fingerprinter's sample rate) and returns the
+    /// fingerprints that are now final.
+    pub fn push_samples(&mut self, samples: &[f32]) -> Vec<Fingerprint> {
+        let mut fingerprints = Vec::new();
+        for frame in self.spectrogram.push(samples) {
+            let peaks = self.peak_finder.push(frame);
+            fingerprints.extend(self.hasher.push(&peaks));
+        }
+        fingerprints
+    }
+
+    /// Ends the stream and returns the fingerprints that were still held back.
+    pub fn finish(mut self) -> Vec<Fingerprint> {
+        let mut fingerprints = Vec::new();
+        for frame in self.spectrogram.finish() {
+            let peaks = self.peak_finder.push(frame);
+            fingerprints.extend(self.hasher.push(&peaks));
+        }
+        let peaks = self.peak_finder.finish();
+        fingerprints.extend(self.hasher.push(&peaks));
+        fingerprints.extend(self.hasher.finish());
+        fingerprints
+    }
+}
@@ -1,4 +1,5 @@
 // src/hashing.rs
+use crate::error::SivanaError;
 use crate::peaks::Peak; // Import Peak from our peaks module
 
 // Parameters for landmark hashing
@@ -9,67 +10,366 @@ pub const MAX_PAIRS_PER_ANCHOR: usize = 5;
 pub const HASH_FREQ_BITS: u32 = 10;
 pub const HASH_DELTA_TIME_BITS: u32 = 8;
 
-#[derive(Debug, Clone, Copy)]
+/// Bit layout of a landmark hash: `[f1: freq_bits][f2: freq_bits][dt: delta_time_bits]`.
+/// Frequency bins at or above `2^freq_bits` wrap around, so `freq_bits` should cover
+/// `window_size / 2 + 1` bins to avoid collisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashConfig {
+    freq_bits: u32,
+    delta_time_bits: u32,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        HashConfig { freq_bits: HASH_FREQ_BITS, delta_time_bits: HASH_DELTA_TIME_BITS }
+    }
+}
+
+impl HashConfig {
+    /// Validates that both fields are non-zero and that the packed hash fits in a `u64`.
+    pub fn new(freq_bits: u32, delta_time_bits: u32) -> Result<Self, SivanaError> {
+        if freq_bits == 0 || delta_time_bits == 0 {
+            return Err(SivanaError::InvalidInput(format!(
+                "Hash bit widths must be non-zero (freq_bits={}, delta_time_bits={}).",
+                freq_bits, delta_time_bits
+            )));
+        }
+        // Checked, so widths near u32::MAX are rejected instead of wrapping to a small total.
+        let total_bits = freq_bits.checked_mul(2).and_then(|bits| bits.checked_add(delta_time_bits));
+        if total_bits.is_none_or(|bits| bits > u64::BITS) {
+            return Err(SivanaError::InvalidInput(format!(
+                "Hash layout needs 2 x {} freq + {} delta time bits but only {} fit in a u64.",
+                freq_bits, delta_time_bits, u64::BITS
+            )));
+        }
+        Ok(HashConfig { freq_bits, delta_time_bits })
+    }
+
+    pub fn freq_bits(&self) -> u32 {
+        self.freq_bits
+    }
+
+    pub fn delta_time_bits(&self) -> u32 {
+        self.delta_time_bits
+    }
+}
+
+/// Which later peaks an anchor is paired with: those `dt_min_frames..=dt_max_frames` frames
+/// after it and at most `df_abs_max_bins` bins away, up to `max_pairs_per_anchor` of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetZone {
+    dt_min_frames: usize,
+    dt_max_frames: usize,
+    df_abs_max_bins: usize,
+    max_pairs_per_anchor: usize,
+}
+
+impl Default for TargetZone {
+    fn default() -> Self {
+        TargetZone {
+            dt_min_frames: TARGET_ZONE_DT_MIN_FRAMES,
+            dt_max_frames: TARGET_ZONE_DT_MAX_FRAMES,
+            df_abs_max_bins: TARGET_ZONE_DF_ABS_MAX_BINS,
+            max_pairs_per_anchor: MAX_PAIRS_PER_ANCHOR,
+        }
+    }
+}
+
+impl TargetZone {
+    /// Validates that the time range is non-empty (`dt_min_frames <= dt_max_frames`) and that
+    /// anchors may form at least one pair; either mistake would yield no fingerprints at all.
+    pub fn new(
+        dt_min_frames: usize,
+        dt_max_frames: usize,
+        df_abs_max_bins: usize,
+        max_pairs_per_anchor: usize,
+    ) -> Result<Self, SivanaError> {
+        if dt_min_frames > dt_max_frames {
+            return Err(SivanaError::InvalidInput(format!(
+                "Target zone time range is empty (dt_min_frames={} > dt_max_frames={}).",
+                dt_min_frames, dt_max_frames
+            )));
+        }
+        if max_pairs_per_anchor == 0 {
+            return Err(SivanaError::InvalidInput("Target zone max_pairs_per_anchor must be non-zero.".to_string()));
+        }
+        Ok(TargetZone { dt_min_frames, dt_max_frames, df_abs_max_bins, max_pairs_per_anchor })
+    }
+
+    pub fn dt_min_frames(&self) -> usize {
+        self.dt_min_frames
+    }
+
+    pub fn dt_max_frames(&self) -> usize {
+        self.dt_max_frames
+    }
+
+    pub fn df_abs_max_bins(&self) -> usize {
+        self.df_abs_max_bins
+    }
+
+    pub fn max_pairs_per_anchor(&self) -> usize {
+        self.max_pairs_per_anchor
+    }
+}
+
+// Mask with the low `bits` bits set (bits is at most 64 thanks to HashConfig validation).
+fn low_bits_mask(bits: u32) -> u64 {
+    if bits >= u64::BITS { u64::MAX } else { (1u64 << bits) - 1 }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Fingerprint { // Made public
     pub hash: u64,          // Fields public
     pub anchor_time_idx: usize,
+    /// Frames between anchor and target peak (unmasked), used to verify hash hits during matching.
+    pub target_delta_frames: usize,
+    /// Quantized magnitude of the pair's weaker peak (see `pair_strength`), used to weight
+    /// hits when matching with magnitude weighting; 0 if unknown.
+    pub strength: u8,
+}
+
+/// The fingerprints anchored in frames `start_frame..end_frame`, rebased so `start_frame`
+/// becomes frame 0. Matching the result reports the song position of `start_frame`, so a
+/// live buffer can be fingerprinted once and then matched on just its most recent part.
+pub fn fingerprints_in_window(fingerprints: &[Fingerprint], start_frame: usize, end_frame: usize) -> Vec<Fingerprint> {
+    fingerprints
+        .iter()
+        .filter(|fp| (start_frame..end_frame).contains(&fp.anchor_time_idx))
+        .map(|fp| Fingerprint { anchor_time_idx: fp.anchor_time_idx - start_frame, ..*fp })
+        .collect()
 }
 
+/// At most `max_fingerprints` of `fingerprints`, picked at a uniform stride so the kept ones
+/// still span the whole query (all of them if there are few enough). Anchors are unchanged,
+/// so offsets stay correct; scores shrink roughly in proportion. For bounding the cost of
+/// matching very long queries.
+pub fn thin_fingerprints(fingerprints: &[Fingerprint], max_fingerprints: usize) -> Vec<Fingerprint> {
+    if fingerprints.len() <= max_fingerprints {
+        return fingerprints.to_vec();
+    }
+    (0..max_fingerprints)
+        .map(|i| fingerprints[i * fingerprints.len() / max_fingerprints])
+        .collect()
+}
+
+/// Steps of `pair_strength` per doubling of magnitude (about 1.5 dB each).
+const STRENGTH_STEPS_PER_OCTAVE: f32 = 4.0;
+
+/// Strength of an anchor-target pair from the weaker peak's magnitude, on a log scale of
+/// STRENGTH_STEPS_PER_OCTAVE steps per doubling, clamped to 1..=255. Magnitudes of 1 or
+/// less (including non-positive dB values) get the minimum, 1.
+pub fn pair_strength(anchor: &Peak, target: &Peak) -> u8 {
+    let weaker = anchor.magnitude.min(target.magnitude);
+    if weaker.is_nan() || weaker <= 1.0 {
+        return 1;
+    }
+    (1.0 + STRENGTH_STEPS_PER_OCTAVE * weaker.log2()).clamp(1.0, 255.0) as u8
+}
+
+/// Pairs each anchor peak with up to `max_pairs_per_anchor` later peaks inside the target zone.
+/// With the `rayon` feature, anchors are processed in parallel; the output is identical to
+/// `create_hashes_serial` either way.
 pub fn create_hashes( // Made public
                       peaks: &[Peak],
-                      dt_min_frames: usize,
-                      dt_max_frames: usize,
-                      df_abs_max_bins: usize,
-                      max_pairs_per_anchor: usize,
+                      target_zone: TargetZone,
+                      hash_config: HashConfig,
 ) -> Vec<Fingerprint> {
+    #[cfg(feature = "rayon")]
+    {
+        create_hashes_parallel(peaks, target_zone, hash_config)
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        create_hashes_serial(peaks, target_zone, hash_config)
+    }
+}
+
+/// Single-threaded `create_hashes`, available regardless of features (e.g. for comparisons).
+pub fn create_hashes_serial(
+    peaks: &[Peak],
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+) -> Vec<Fingerprint> {
+    create_hashes_with_stats(peaks, target_zone, hash_config).0
+}
+
+/// Counters describing one `create_hashes_with_stats` run, for tuning the target zone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HashStats {
+    /// Peaks tried as anchors (every peak, if there are at least two).
+    pub anchors_considered: usize,
+    /// Fingerprints produced.
+    pub pairs_emitted: usize,
+    /// Anchors that stopped pairing because they reached `max_pairs_per_anchor`. A large
+    /// share means the cap, rather than the target zone, limits the fingerprint count.
+    pub anchors_hitting_cap: usize,
+}
+
+/// `create_hashes` (always single-threaded) that also returns `HashStats`. The fingerprints
+/// are identical to `create_hashes`.
+pub fn create_hashes_with_stats(
+    peaks: &[Peak],
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+) -> (Vec<Fingerprint>, HashStats) {
     let mut fingerprints: Vec<Fingerprint> = Vec::new();
+    let mut stats = HashStats::default();
 
     if peaks.len() < 2 {
-        println!("Debug: create_hashes - Not enough peaks to form pairs (need at least 2).");
-        return fingerprints;
+        log::debug!("create_hashes - Not enough peaks to form pairs (need at least 2).");
+        return (fingerprints, stats);
     }
 
-    println!(
-        "Debug: create_hashes - Processing {} peaks. Target zone: dt=[{}-{}], df_abs_max={}, max_pairs={}",
-        peaks.len(), dt_min_frames, dt_max_frames, df_abs_max_bins, max_pairs_per_anchor
+    log::debug!(
+        "create_hashes - Processing {} peaks. {:?}, {:?}",
+        peaks.len(), target_zone, hash_config
     );
 
-    for i in 0..peaks.len() {
-        let anchor_peak = &peaks[i];
-        let mut pairs_found_for_this_anchor = 0;
+    for anchor_idx in 0..peaks.len() {
+        let pairs = push_anchor_hashes(
+            &mut fingerprints, peaks, anchor_idx, target_zone, hash_config,
+        );
+        stats.anchors_considered += 1;
+        if pairs >= target_zone.max_pairs_per_anchor {
+            stats.anchors_hitting_cap += 1;
+        }
+    }
+    stats.pairs_emitted = fingerprints.len();
+    log::debug!("create_hashes - Generated {} fingerprints; {:?}", fingerprints.len(), stats);
+    (fingerprints, stats)
+}
 
-        for j in (i + 1)..peaks.len() {
-            if pairs_found_for_this_anchor >= max_pairs_per_anchor {
-                break;
-            }
-            let target_peak = &peaks[j];
-            let delta_time_frames = target_peak.time_idx.saturating_sub(anchor_peak.time_idx);
+/// `create_hashes` with the anchors spread over rayon's thread pool. Per-anchor results are
+/// concatenated in anchor order, so the output matches the serial version exactly.
+#[cfg(feature = "rayon")]
+pub fn create_hashes_parallel(
+    peaks: &[Peak],
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+) -> Vec<Fingerprint> {
+    use rayon::prelude::*;
 
-            if delta_time_frames < dt_min_frames { continue; }
-            if delta_time_frames > dt_max_frames { continue; }
+    if peaks.len() < 2 {
+        log::debug!("create_hashes - Not enough peaks to form pairs (need at least 2).");
+        return Vec::new();
+    }
 
-            let delta_freq_bins_abs = (target_peak.freq_bin_idx as isize - anchor_peak.freq_bin_idx as isize).abs() as usize;
-            if delta_freq_bins_abs > df_abs_max_bins { continue; }
+    log::debug!(
+        "create_hashes - Processing {} peaks in parallel. {:?}, {:?}",
+        peaks.len(), target_zone, hash_config
+    );
 
-            let f1 = anchor_peak.freq_bin_idx as u64;
-            let f2 = target_peak.freq_bin_idx as u64;
-            let dt = delta_time_frames as u64;
+    let per_anchor: Vec<Vec<Fingerprint>> = (0..peaks.len())
+        .into_par_iter()
+        .map(|anchor_idx| {
+            let mut anchor_fingerprints = Vec::with_capacity(target_zone.max_pairs_per_anchor);
+            push_anchor_hashes(&mut anchor_fingerprints, peaks, anchor_idx, target_zone, hash_config);
+            anchor_fingerprints
+        })
+        .collect();
+    let fingerprints = per_anchor.concat();
+    log::debug!("create_hashes - Generated {} fingerprints.", fingerprints.len());
+    fingerprints
+}
 
-            let f1_masked = f1 & ((1 << HASH_FREQ_BITS) - 1);
-            let f2_masked = f2 & ((1 << HASH_FREQ_BITS) - 1);
-            let dt_masked = dt & ((1 << HASH_DELTA_TIME_BITS) - 1);
+/// `create_hashes` for peaks that arrive incrementally in (time, frequency) order, as
+/// `StreamingPeakFinder` produces them. An anchor is hashed as soon as a peak more than
+/// `dt_max_frames` after it has arrived (no later peak can fall in its target zone), so only
+/// the last `dt_max_frames` frames of peaks are buffered. The output matches `create_hashes`.
+#[derive(Debug, Clone)]
+pub struct StreamingHasher {
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+    // Peaks that are still anchors-to-be or possible targets.
+    peaks: Vec<Peak>,
+}
+
+impl StreamingHasher {
+    pub fn new(target_zone: TargetZone, hash_config: HashConfig) -> Self {
+        StreamingHasher { target_zone, hash_config, peaks: Vec::new() }
+    }
+
+    /// Adds `peaks` (which must not precede earlier ones) and returns the fingerprints of
+    /// every anchor whose target zone is now complete.
+    pub fn push(&mut self, peaks: &[Peak]) -> Vec<Fingerprint> {
+        self.peaks.extend_from_slice(peaks);
+        let Some(latest_time_idx) = self.peaks.last().map(|p| p.time_idx) else {
+            return Vec::new();
+        };
+        let settled = self.peaks
+            .iter()
+            .position(|p| p.time_idx.saturating_add(self.target_zone.dt_max_frames) >= latest_time_idx)
+            .unwrap_or(self.peaks.len());
+        self.hash_anchors(settled)
+    }
 
-            let robust_hash_val = (f1_masked << (HASH_FREQ_BITS + HASH_DELTA_TIME_BITS)) |
-                (f2_masked << HASH_DELTA_TIME_BITS) |
-                dt_masked;
+    /// Returns the fingerprints of the anchors still buffered at the end of the stream.
+    pub fn finish(mut self) -> Vec<Fingerprint> {
+        let remaining = self.peaks.len();
+        self.hash_anchors(remaining)
+    }
 
-            fingerprints.push(Fingerprint {
-                hash: robust_hash_val,
-                anchor_time_idx: anchor_peak.time_idx,
-            });
-            pairs_found_for_this_anchor += 1;
+    // Hashes the first `num_anchors` buffered peaks as anchors and drops them.
+    fn hash_anchors(&mut self, num_anchors: usize) -> Vec<Fingerprint> {
+        let mut fingerprints = Vec::new();
+        for anchor_idx in 0..num_anchors {
+            push_anchor_hashes(&mut fingerprints, &self.peaks, anchor_idx, self.target_zone, self.hash_config);
         }
+        self.peaks.drain(..num_anchors);
+        fingerprints
     }
-    println!("Debug: create_hashes - Generated {} fingerprints.", fingerprints.len());
-    fingerprints
-}
\ No newline at end of file
+}
+
+/// Appends the fingerprints formed by `peaks[anchor_idx]` and the peaks after it, and
+/// returns how many were appended.
+fn push_anchor_hashes(
+    fingerprints: &mut Vec<Fingerprint>,
+    peaks: &[Peak],
+    anchor_idx: usize,
+    target_zone: TargetZone,
+    hash_config: HashConfig,
+) -> usize {
+    let TargetZone { dt_min_frames, dt_max_frames, df_abs_max_bins, max_pairs_per_anchor } = target_zone;
+    let freq_bits = hash_config.freq_bits;
+    let delta_time_bits = hash_config.delta_time_bits;
+    let freq_mask = low_bits_mask(freq_bits);
+    let delta_time_mask = low_bits_mask(delta_time_bits);
+
+    let anchor_peak = &peaks[anchor_idx];
+    let mut pairs_found_for_this_anchor = 0;
+
+    for target_peak in &peaks[(anchor_idx + 1)..] {
+        if pairs_found_for_this_anchor >= max_pairs_per_anchor {
+            break;
+        }
+        let delta_time_frames = target_peak.time_idx.saturating_sub(anchor_peak.time_idx);
+
+        if delta_time_frames < dt_min_frames { continue; }
+        if delta_time_frames > dt_max_frames { continue; }
+
+        let delta_freq_bins_abs = (target_peak.freq_bin_idx as isize - anchor_peak.freq_bin_idx as isize).unsigned_abs();
+        if delta_freq_bins_abs > df_abs_max_bins { continue; }
+
+        let f1 = anchor_peak.freq_bin_idx as u64;
+        let f2 = target_peak.freq_bin_idx as u64;
+        let dt = delta_time_frames as u64;
+
+        let f1_masked = f1 & freq_mask;
+        let f2_masked = f2 & freq_mask;
+        let dt_masked = dt & delta_time_mask;
+
+        let robust_hash_val = (f1_masked << (freq_bits + delta_time_bits)) |
+            (f2_masked << delta_time_bits) |
+            dt_masked;
+
+        fingerprints.push(Fingerprint {
+            hash: robust_hash_val,
+            anchor_time_idx: anchor_peak.time_idx,
+            target_delta_frames: delta_time_frames,
+            strength: pair_strength(anchor_peak, target_peak),
+        });
+        pairs_found_for_this_anchor += 1;
+    }
+    pairs_found_for_this_anchor
+}
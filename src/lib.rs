@@ -0,0 +1,25 @@
+// src/lib.rs
+//! Sivana: landmark-based audio fingerprinting backed by SQLite.
+//!
+//! The CLI in `main.rs` is a thin wrapper over this library; other binaries can
+//! depend on it directly and use [`Fingerprinter`] to enroll and identify audio.
+
+pub mod spectrogram;
+#[cfg(feature = "mel")]
+pub mod mel;
+pub mod peaks;
+pub mod hashing;
+pub mod database;
+pub mod store;
+pub mod matching;
+pub mod stream_matcher;
+pub mod audio_loader;
+pub mod fingerprinter;
+pub mod export;
+pub mod selftest;
+pub mod error;
+#[cfg(feature = "microphone")]
+pub mod microphone;
+
+pub use crate::error::{LoadError, SivanaError};
+pub use crate::fingerprinter::Fingerprinter;
@@ -1,36 +1,109 @@
 // src/main.rs
 
-// Declare modules
-mod spectrogram;
-mod peaks;
-mod hashing;
-mod database;
-mod audio_loader;
-
 // --- IMPORTS ---
-use crate::audio_loader::load_audio_file;
-use crate::database::{
-    open_db_connection, init_db, enroll_song, query_db_and_match, get_song_info,
-    SongId, // MatchResult is used internally by query_db_and_match
+use sivana::audio_loader::{
+    load_audio_file_with_info, load_audio_from_reader_with_info, normalize_rms, slice_seconds, trim_silence, AudioStream, AudioTags, LoadOptions, LoadedAudio, ResampleQuality,
+    DEFAULT_SILENCE_THRESHOLD, DEFAULT_TARGET_RMS,
+};
+use sivana::database::{
+    open_db_connection, open_bulk_db_connection, init_db, query_db_and_match, get_song_info, delete_song, list_songs,
+    query_db_and_match_topn, query_db_closest_candidates, DEFAULT_MIN_MATCH_SCORE, DEFAULT_MIN_QUERY_COVERAGE, DEFAULT_VERIFY_MIN_FRACTION, get_db_stats, DEFAULT_DB_FILE_NAME,
+    open_in_memory_connection, clear_db, set_song_duration, update_song_metadata, EnrollStage,
+    MatchResult, QueryOptions, SongId, FileStamp, find_unchanged_song, set_song_file_stamp, optimize_db, checkpoint_wal,
+    attach_database, fingerprint_databases, database_file, get_song_info_in, log_query, recent_queries,
+    get_song_fingerprint_count, LOW_FINGERPRINT_COUNT, query_db_and_match_peeling, find_duplicate_songs, duplicate_groups, ensure_indexes,
 };
-use crate::hashing::{create_hashes, MAX_PAIRS_PER_ANCHOR, TARGET_ZONE_DF_ABS_MAX_BINS, TARGET_ZONE_DT_MAX_FRAMES, TARGET_ZONE_DT_MIN_FRAMES};
-use crate::peaks::find_peaks;
-use crate::spectrogram::create_spectrogram;
+use sivana::export::{export_fingerprints, import_fingerprints, import_fingerprints_keeping_id};
+use sivana::hashing::{thin_fingerprints, Fingerprint};
+use sivana::matching::{aggregate_matches, refine_offset, RefinedOffset};
+use sivana::peaks::Peak;
+use sivana::selftest::{run_selftest, SelftestReport, SelftestWorkload, StageTiming};
+use sivana::spectrogram::{window_sum, MagnitudeScale, WindowType};
+use sivana::fingerprinter::{FingerprintStats, DEFAULT_FFT_HOPSIZE, DEFAULT_FFT_WINDOW_SIZE, DEFAULT_SAMPLE_RATE};
+use sivana::{Fingerprinter, LoadError, SivanaError};
 
-use std::path::PathBuf; // For path arguments from clap
+use rusqlite::Connection;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr; // For path arguments from clap
+use std::sync::{Mutex, PoisonError};
 use clap::Parser;     // For CLI argument parsing
 
-// --- GLOBAL CONSTANTS ---
-const SAMPLE_RATE: u32 = 22050;
-const FFT_WINDOW_SIZE: usize = 2048;
-const FFT_HOPSIZE: usize = 1024;
-
 // --- Define CLI Arguments and Subcommands ---
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Sivana Audio Fingerprinter", long_about = None)]
 #[command(propagate_version = true)]
 struct Cli {
+    /// Path to the fingerprint database file (created if it doesn't exist). Query accepts it
+    /// several times to search several databases together; the extra ones must already exist.
+    #[arg(long, global = true, value_name = "PATH", default_value = DEFAULT_DB_FILE_NAME, action = clap::ArgAction::Append)]
+    db: Vec<PathBuf>,
+
+    /// Use a temporary in-memory database instead of a file (nothing is persisted)
+    #[arg(long, global = true, conflicts_with = "db")]
+    in_memory: bool,
+
+    /// Resampler quality when the audio isn't already at the target rate: fast, balanced or high
+    #[arg(long, global = true, value_name = "QUALITY", default_value = "balanced")]
+    resample_quality: ResampleQuality,
+
+    /// Subtract the mean from decoded audio, removing any DC offset before fingerprinting
+    #[arg(long, global = true)]
+    remove_dc: bool,
+
+    /// Rate (Hz) audio is resampled to before fingerprinting, e.g. 44100 for high-fidelity
+    /// music or 11025 for speed; must match the value the database was enrolled with
+    #[arg(long, global = true, value_name = "HZ", default_value_t = DEFAULT_SAMPLE_RATE, value_parser = clap::value_parser!(u32).range(MIN_SAMPLE_RATE as i64..=MAX_SAMPLE_RATE as i64))]
+    sample_rate: u32,
+
+    /// FFT window length in samples; must match the value the database was enrolled with
+    #[arg(long, global = true, value_name = "SAMPLES", default_value_t = DEFAULT_FFT_WINDOW_SIZE)]
+    window_size: usize,
+
+    /// Samples between successive FFT frames; must match the value the database was enrolled with
+    #[arg(long, global = true, value_name = "SAMPLES", default_value_t = DEFAULT_FFT_HOPSIZE)]
+    hop_size: usize,
+
+    /// Round --window-size up to the next power of two, where the FFT is fastest
+    #[arg(long, global = true)]
+    round_window: bool,
+
+    /// Zero-pad audio after the last full window into one more frame, so clips shorter than a
+    /// window (jingles, sound effects, very short snippets) still produce fingerprints
+    #[arg(long, global = true)]
+    pad_final_frame: bool,
+
+    /// Center each FFT frame on its hop position (reflect-padding the audio ends) instead of
+    /// starting it there; must match the setting the database was enrolled with
+    #[arg(long, global = true)]
+    center_frames: bool,
+
+    /// Divide FFT magnitudes by the window's sum so peak thresholds mean the same at every
+    /// --window-size (the default threshold and silent-frame floor are rescaled to match their
+    /// values at the default window); must match the setting the database was enrolled with
+    #[arg(long, global = true)]
+    normalize_magnitudes: bool,
+
+    /// Use a power spectrogram (squared magnitudes), which suppresses low-energy high
+    /// frequencies; must match the setting the database was enrolled with
+    #[arg(long, global = true)]
+    power_spectrum: bool,
+
+    /// Pool each spectrogram frame into this many mel bands (speech-oriented; usually less
+    /// accurate for music); must match the setting the database was enrolled with
+    #[cfg(feature = "mel")]
+    #[arg(long, global = true, value_name = "BANDS", value_parser = clap::value_parser!(u16).range(4..=512))]
+    mel_bands: Option<u16>,
+
+    /// Print Query and List results as JSON on stdout
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Show progress messages (-v) and detailed matching diagnostics (-vv) on stderr
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -39,83 +112,1737 @@ struct Cli {
 enum Commands {
     /// Enroll a new song into the fingerprint database
     Enroll {
-        /// Path to the audio file to enroll
-        #[arg(value_name = "FILE_PATH")]
-        file_path: PathBuf,
+        /// Path to the audio file to enroll, or - to read the audio from stdin (needs --format).
+        /// Several files, or a directory standing for the files directly inside it, are
+        /// fingerprinted in parallel (with the rayon feature) and stored in the order given.
+        #[arg(value_name = "FILE_PATH", required = true)]
+        file_paths: Vec<PathBuf>,
+
+        /// Container format of audio read from stdin, as a file extension (wav, mp3, flac, ...)
+        #[arg(long, value_name = "EXT")]
+        format: Option<String>,
 
         /// Optional display name/title for the song. If not provided, filename is used.
         #[arg(long, short)]
         title: Option<String>,
+
+        /// Normalize loudness to a fixed RMS level before fingerprinting (use on both Enroll and Query)
+        #[arg(long)]
+        normalize: bool,
+
+        /// Only fingerprint the first SECONDS of the file (useful for very long recordings)
+        #[arg(long, value_name = "SECONDS")]
+        max_duration: Option<f32>,
+
+        /// Only enroll the audio from SECONDS into the file on (e.g. one track of a DJ mix);
+        /// matches then report offsets within this segment
+        #[arg(long, value_name = "SECONDS", conflicts_with = "stream")]
+        start: Option<f32>,
+
+        /// Only enroll the audio up to SECONDS into the file (at most its duration)
+        #[arg(long, value_name = "SECONDS", conflicts_with_all = ["stream", "max_duration"])]
+        end: Option<f32>,
+
+        /// Enroll even if the file is unchanged since it was last enrolled, or the same audio
+        /// is already in the database under another path
+        #[arg(long)]
+        force: bool,
+
+        /// Decode, fingerprint and store the file in chunks instead of loading it whole, keeping
+        /// memory bounded for very long recordings. Skips the duplicate check (as with --force).
+        #[arg(long, conflicts_with = "normalize")]
+        stream: bool,
+
+        /// Speed up database writes (synchronous=NORMAL, larger cache); the last commits may
+        /// be lost on a power failure or OS crash, so only use it for re-runnable imports
+        #[arg(long)]
+        bulk: bool,
+
+        /// Run the pipeline up to hashing and print peak/fingerprint counts without writing
+        /// anything to the database; with several files, end with the ones too sparse to match
+        #[arg(long, conflicts_with = "stream")]
+        dry_run: bool,
+
+        /// Fingerprint up to N files at once when enrolling several (default: one per CPU core;
+        /// needs the rayon feature)
+        #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: Option<usize>,
     },
     /// Query the database with an audio snippet to identify a song
     Query {
         /// Path to the audio snippet file
         #[arg(value_name = "SNIPPET_PATH")]
         snippet_path: PathBuf,
+
+        /// Print up to N ranked candidate matches instead of only the best one
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+
+        /// Minimum histogram score a candidate needs to be reported as a match
+        #[arg(long, default_value_t = DEFAULT_MIN_MATCH_SCORE)]
+        min_score: usize,
+
+        /// Normalize loudness to a fixed RMS level before fingerprinting (use on both Enroll and Query)
+        #[arg(long)]
+        normalize: bool,
+
+        /// Strip leading/trailing silence from the snippet before fingerprinting
+        #[arg(long)]
+        trim_silence: bool,
+
+        /// Amplitude (0.0-1.0) below which audio counts as silence for --trim-silence
+        #[arg(long, default_value_t = DEFAULT_SILENCE_THRESHOLD, requires = "trim_silence")]
+        silence_threshold: f32,
+
+        /// Query even if the database was built with different fingerprinting parameters
+        #[arg(long)]
+        force: bool,
+
+        /// Report no match when fewer than this share (0.0-1.0) of the query's fingerprints hit
+        /// anything in the database, whatever the best score (0 disables; ignored with --top
+        /// and --peel)
+        #[arg(long, value_name = "FRACTION", default_value_t = DEFAULT_MIN_QUERY_COVERAGE)]
+        min_coverage: f32,
+
+        /// Skip the alignment check that confirms the best match (ignored with --top)
+        #[arg(long)]
+        no_verify: bool,
+
+        /// Ignore hashes stored more than N times in the database (speeds up and sharpens large-DB queries)
+        #[arg(long, value_name = "N")]
+        max_hash_popularity: Option<usize>,
+
+        /// Rank candidates by hits weighted with their peaks' strength instead of counting every
+        /// hit once (--min-score still applies to the plain hit count; slower lookup)
+        #[arg(long)]
+        weight_magnitude: bool,
+
+        /// Also count hits up to N frames from a candidate offset towards it, for snippets
+        /// recorded with a slightly fast or slow clock (0 matches exact offsets only)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        offset_tolerance: usize,
+
+        /// Match with at most N of the snippet's fingerprints, spread evenly over it, to bound
+        /// the cost of long snippets (scores shrink in proportion; enrollment is unaffected)
+        #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        max_query_fingerprints: Option<usize>,
+
+        /// Warn when the snippet is shorter than this; short snippets rarely reach --min-score
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_MIN_QUERY_SECONDS)]
+        min_duration: f32,
+
+        /// When nothing matches, also show the closest (unconfirmed) candidate and its score
+        #[arg(long)]
+        explain: bool,
+
+        /// Record the snippet and its result (or the miss) in the database's query log
+        #[arg(long)]
+        log_queries: bool,
+
+        /// Refine the match's offset to the sample by cross-correlating the snippet with the
+        /// matched song's audio, which must still be at its enrolled file path
+        #[arg(long)]
+        refine_offset: bool,
+
+        /// Report up to N songs that overlap in the snippet, such as both sides of a DJ
+        /// crossfade: each match's fingerprints are removed and the rest matched again
+        #[arg(long, value_name = "N", conflicts_with_all = ["top", "explain", "refine_offset"])]
+        peel: Option<usize>,
+
+        /// Output format: text, json (the same as --json) or csv
+        #[arg(long, value_name = "FORMAT", default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Query several snippets (files, or directories of files) and print one result per snippet
+    QueryBatch {
+        /// Snippet files; a directory stands for the files directly inside it, in name order
+        #[arg(value_name = "PATHS", required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Minimum histogram score a candidate needs to be reported as a match
+        #[arg(long, default_value_t = DEFAULT_MIN_MATCH_SCORE)]
+        min_score: usize,
+
+        /// Normalize loudness to a fixed RMS level before fingerprinting (use on both Enroll and Query)
+        #[arg(long)]
+        normalize: bool,
+
+        /// Strip leading/trailing silence from each snippet before fingerprinting
+        #[arg(long)]
+        trim_silence: bool,
+
+        /// Amplitude (0.0-1.0) below which audio counts as silence for --trim-silence
+        #[arg(long, default_value_t = DEFAULT_SILENCE_THRESHOLD, requires = "trim_silence")]
+        silence_threshold: f32,
+
+        /// Query even if the database was built with different fingerprinting parameters
+        #[arg(long)]
+        force: bool,
+
+        /// Report no match when fewer than this share (0.0-1.0) of a snippet's fingerprints hit
+        /// anything in the database, whatever the best score (0 disables)
+        #[arg(long, value_name = "FRACTION", default_value_t = DEFAULT_MIN_QUERY_COVERAGE)]
+        min_coverage: f32,
+
+        /// Skip the alignment check that confirms each snippet's match
+        #[arg(long)]
+        no_verify: bool,
+
+        /// Ignore hashes stored more than N times in the database (speeds up and sharpens large-DB queries)
+        #[arg(long, value_name = "N")]
+        max_hash_popularity: Option<usize>,
+
+        /// Rank candidates by hits weighted with their peaks' strength instead of counting every
+        /// hit once (--min-score still applies to the plain hit count; slower lookup)
+        #[arg(long)]
+        weight_magnitude: bool,
+
+        /// Also count hits up to N frames from a candidate offset towards it, for snippets
+        /// recorded with a slightly fast or slow clock (0 matches exact offsets only)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        offset_tolerance: usize,
+
+        /// Match with at most N of the snippet's fingerprints, spread evenly over it, to bound
+        /// the cost of long snippets (scores shrink in proportion; enrollment is unaffected)
+        #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        max_query_fingerprints: Option<usize>,
+
+        /// Warn about snippets shorter than this; short snippets rarely reach --min-score
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_MIN_QUERY_SECONDS)]
+        min_duration: f32,
+
+        /// Combine the snippets' matches into a single most likely song
+        #[arg(long)]
+        aggregate: bool,
+
+        /// Start-to-start spacing of the snippets within the recording they were cut from;
+        /// with --aggregate, only matches at consistent song offsets count towards confidence
+        #[arg(long, value_name = "SECONDS", requires = "aggregate")]
+        spacing: Option<f32>,
+    },
+    /// Show the most recent queries recorded with --log-queries
+    History {
+        /// Maximum number of entries to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
     },
     /// List all songs currently enrolled in the database
-    List,
-    // TODO: Consider adding DeleteSong, DbInfo, ClearDb commands later
+    List {
+        /// Only list songs whose name contains this substring
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Maximum number of songs to list
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Number of songs to skip before listing (for pagination)
+        #[arg(long)]
+        offset: Option<usize>,
+
+        /// Output format: text, json (the same as --json) or csv
+        #[arg(long, value_name = "FORMAT", default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Find songs enrolled more than once (e.g. under different names) by matching every
+    /// song's stored fingerprints against the others; nothing is deleted
+    Dedup {
+        /// Minimum score each song of a pair must reach when matched against the other
+        #[arg(long, default_value_t = DEFAULT_MIN_MATCH_SCORE)]
+        min_score: usize,
+    },
+    /// Delete an enrolled song and all of its fingerprints
+    Delete {
+        /// Database ID of the song to delete (see `List`)
+        #[arg(value_name = "SONG_ID")]
+        song_id: SongId,
+    },
+    /// Change the name (and optionally the stored file path) of an enrolled song
+    Rename {
+        /// Database ID of the song to rename (see `List`)
+        #[arg(value_name = "SONG_ID")]
+        song_id: SongId,
+
+        /// New display name
+        #[arg(value_name = "NAME")]
+        name: String,
+
+        /// Also update the stored file path (e.g. after moving the file)
+        #[arg(long, value_name = "PATH")]
+        path: Option<String>,
+    },
+    /// Record from the default microphone and identify what's playing
+    #[cfg(feature = "microphone")]
+    Listen {
+        /// How many seconds to record
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+
+        /// Print up to N ranked candidate matches instead of only the best one
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+
+        /// Minimum histogram score a candidate needs to be reported as a match
+        #[arg(long, default_value_t = DEFAULT_MIN_MATCH_SCORE)]
+        min_score: usize,
+
+        /// Query even if the database was built with different fingerprinting parameters
+        #[arg(long)]
+        force: bool,
+
+        /// Report no match when fewer than this share (0.0-1.0) of the query's fingerprints hit
+        /// anything in the database, whatever the best score (0 disables; ignored with --top)
+        #[arg(long, value_name = "FRACTION", default_value_t = DEFAULT_MIN_QUERY_COVERAGE)]
+        min_coverage: f32,
+
+        /// Skip the alignment check that confirms the best match (ignored with --top)
+        #[arg(long)]
+        no_verify: bool,
+
+        /// Ignore hashes stored more than N times in the database (speeds up and sharpens large-DB queries)
+        #[arg(long, value_name = "N")]
+        max_hash_popularity: Option<usize>,
+
+        /// Also count hits up to N frames from a candidate offset towards it, for snippets
+        /// recorded with a slightly fast or slow clock (0 matches exact offsets only)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        offset_tolerance: usize,
+    },
+    /// Check whether PROBE occurs inside REFERENCE, without using the database
+    Compare {
+        /// The longer recording to search in
+        #[arg(value_name = "REFERENCE")]
+        reference: PathBuf,
+
+        /// The recording to look for
+        #[arg(value_name = "PROBE")]
+        probe: PathBuf,
+    },
+    /// Print the constellation peaks of an audio file with their time and frequency (for
+    /// checking peak settings; does not use the database)
+    ShowPeaks {
+        /// Audio file to analyse
+        #[arg(value_name = "FILE_PATH")]
+        file_path: PathBuf,
+
+        /// Only print the first N peaks
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+    },
+    /// Write the constellation peaks of an audio file to a CSV of
+    /// time_seconds,frequency_hz,magnitude for plotting (does not use the database)
+    Peaks {
+        /// Audio file to analyse
+        #[arg(value_name = "FILE_PATH")]
+        file_path: PathBuf,
+
+        /// CSV file to write
+        #[arg(long, short, value_name = "OUT")]
+        out: PathBuf,
+    },
+    /// Write one song's fingerprints and metadata to a portable text file
+    Export {
+        /// Database ID of the song to export (see `List`)
+        #[arg(value_name = "SONG_ID")]
+        song_id: SongId,
+
+        /// File to write
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+    },
+    /// Add a song from a file written by `Export`, without needing its audio
+    Import {
+        /// File written by `Export`
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Speed up database writes as with `Enroll --bulk` (same durability trade-off)
+        #[arg(long)]
+        bulk: bool,
+
+        /// Keep the song ID it had in the exporting database, replacing any song that has it here
+        #[arg(long)]
+        keep_id: bool,
+    },
+    /// Show song/fingerprint counts and on-disk size of the database
+    DbInfo,
+    /// Rebuild indexes, refresh statistics and VACUUM the database to reclaim space (keeps all data)
+    Optimize,
+    /// Fold the write-ahead log (the `-wal` file) back into the database and truncate it
+    Checkpoint,
+    /// Check that the fingerprint indexes exist and build any that are missing (opening a
+    /// database for any command already does this; this reports the result)
+    EnsureIndexes,
+    /// Time every pipeline stage on a fixed synthetic workload and check that it matches,
+    /// using a throwaway in-memory database (--db is not touched)
+    Selftest,
+    /// Delete ALL songs and fingerprints from the database
+    ClearDb {
+        /// Skip the interactive confirmation prompt
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+/// Cargo features this binary was built with that affect speed or storage.
+fn enabled_features() -> Vec<&'static str> {
+    [("rayon", cfg!(feature = "rayon")), ("mel", cfg!(feature = "mel")), ("sled", cfg!(feature = "sled")), ("ndarray", cfg!(feature = "ndarray"))]
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+}
+
+fn print_selftest_report(report: &SelftestReport, json: bool) {
+    let workload = report.workload;
+    let stage_json = |stage: &StageTiming| serde_json::json!({
+        "items": stage.items, "seconds": stage.elapsed.as_secs_f64(), "per_second": stage.per_second(),
+    });
+    if json {
+        println!("{}", serde_json::json!({
+            "workload": {
+                "songs": workload.songs, "song_seconds": workload.song_seconds,
+                "queries": workload.queries, "query_seconds": workload.query_seconds,
+            },
+            "features": enabled_features(),
+            "spectrogram_frames": stage_json(&report.spectrogram),
+            "peak_frames": stage_json(&report.peaks),
+            "hashed_fingerprints": stage_json(&report.hashing),
+            "enrolled_fingerprints": stage_json(&report.enrollment),
+            "enrollment_realtime_factor": report.enrollment_realtime_factor(),
+            "queries": stage_json(&report.queries),
+            "queries_matched": report.queries_matched,
+            "passed": report.passed(),
+        }));
+        return;
+    }
+    let features = enabled_features();
+    println!(
+        "Workload: {} songs x {} s, {} queries x {} s (features: {}).",
+        workload.songs, workload.song_seconds, workload.queries, workload.query_seconds,
+        if features.is_empty() { "none".to_string() } else { features.join(", ") }
+    );
+    let line = |label: &str, stage: &StageTiming, unit: &str| {
+        println!("{:<12} {:>8} {} in {:>7.3} s  ({:.0} {}/s)", label, stage.items, unit, stage.elapsed.as_secs_f64(), stage.per_second(), unit);
+    };
+    line("Spectrogram:", &report.spectrogram, "frames");
+    line("Peaks:", &report.peaks, "frames");
+    line("Hashing:", &report.hashing, "fingerprints");
+    line("Enrollment:", &report.enrollment, "fingerprints");
+    println!("{:<12} {:>8.0}x real time", "Realtime:", report.enrollment_realtime_factor());
+    line("Query:", &report.queries, "queries");
+    println!("Matched {} of {} queries: {}.", report.queries_matched, workload.queries, if report.passed() { "PASS" } else { "FAIL" });
+}
+
+/// How Query and List print their results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    /// A header row, then one row per result (see `csv_record`).
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            other => Err(format!("Unknown output format '{}' (expected text, json or csv)", other)),
+        }
+    }
+}
+
+/// `format`, or JSON with the global --json (which only conflicts with csv).
+fn output_format(format: OutputFormat, json: bool) -> Result<OutputFormat, String> {
+    match (format, json) {
+        (OutputFormat::Csv, true) => Err("--json cannot be combined with --format csv.".to_string()),
+        (_, true) => Ok(OutputFormat::Json),
+        (format, false) => Ok(format),
+    }
+}
+
+/// Records the enrolled file's stamp; failing that only means it is re-enrolled next time.
+fn store_file_stamp(conn: &Connection, song_id: SongId, stamp: Option<FileStamp>) {
+    if let Some(stamp) = stamp
+        && let Err(e) = set_song_file_stamp(conn, song_id, stamp)
+    {
+        log::warn!("Failed to store file size/modification time for song ID {}: {}", song_id, e);
+    }
+}
+
+/// Prints what `Enroll --dry-run` would store for `samples`, flagging audio whose
+/// fingerprints could never reach the default query score.
+fn report_dry_run(fingerprinter: &Fingerprinter, song_name: &str, samples: &[f32]) {
+    let (fingerprints, stats) = fingerprinter.fingerprint_with_stats(samples);
+    let duration_seconds = samples.len() as f32 / fingerprinter.sample_rate as f32;
+    print_dry_run(song_name, duration_seconds, fingerprints.len(), &stats);
+}
+
+/// The body of `report_dry_run`, for fingerprints already counted.
+fn print_dry_run(song_name: &str, duration_seconds: f32, num_fingerprints: usize, stats: &FingerprintStats) {
+    println!("Dry run for '{}' ({:.2} seconds); nothing was written.", song_name, duration_seconds);
+    println!("  Spectrogram frames: {}", stats.frames);
+    println!("  Peaks:              {}", stats.peaks);
+    println!(
+        "  Fingerprints:       {} ({:.1} per second, {} anchors capped)",
+        num_fingerprints,
+        num_fingerprints as f32 / duration_seconds.max(f32::EPSILON),
+        stats.hashes.anchors_hitting_cap
+    );
+    // One row in `songs` plus one per fingerprint.
+    println!("  Estimated DB rows:  {}", num_fingerprints + 1);
+    if num_fingerprints < DEFAULT_MIN_MATCH_SCORE {
+        println!(
+            "  Warning: fewer fingerprints than the default --min-score ({}); this song is unlikely to be matchable.",
+            DEFAULT_MIN_MATCH_SCORE
+        );
+    }
+}
+
+/// Writes one `time_seconds,frequency_hz,magnitude` row per peak (magnitudes on the
+/// spectrogram's scale). With mel bands the frequency column holds the band index instead.
+fn write_constellation_csv(fingerprinter: &Fingerprinter, spectrogram: &[Vec<f32>], peaks: &[Peak], out: &Path) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(out)?);
+    let frequency_column = if fingerprinter.mel_bands().is_some() { "mel_band" } else { "frequency_hz" };
+    writeln!(writer, "time_seconds,{},magnitude", frequency_column)?;
+    for peak in peaks {
+        let frequency = fingerprinter.bin_to_hz(peak.freq_bin_idx).unwrap_or(peak.freq_bin_idx as f32);
+        writeln!(
+            writer,
+            "{:.4},{:.2},{}",
+            fingerprinter.frames_to_seconds(peak.time_idx as isize), frequency, spectrogram[peak.time_idx][peak.freq_bin_idx]
+        )?;
+    }
+    writer.flush()
+}
+
+/// One CSV record: fields holding a comma, quote or line break are quoted, with quotes
+/// doubled (RFC 4180). JSON nulls become empty fields.
+fn csv_record<'a>(fields: impl IntoIterator<Item = &'a serde_json::Value>) -> String {
+    fields
+        .into_iter()
+        .map(|field| {
+            let text = match field {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            if text.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            } else {
+                text
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Prints `rows` (JSON objects) as CSV with the given columns; missing fields are empty.
+fn print_csv(columns: &[&str], rows: &[serde_json::Value]) {
+    println!("{}", columns.join(","));
+    for row in rows {
+        println!("{}", csv_record(columns.iter().map(|&column| &row[column])));
+    }
+}
+
+/// Columns of `Query --format csv`: the single row describes the (best) match.
+const QUERY_CSV_COLUMNS: &[&str] = &[
+    "matched", "song_id", "name", "artist", "score", "weighted_score", "distinct_hash_score", "confidence", "query_coverage",
+    "offset_seconds", "match_start_seconds", "match_end_seconds", "query_match_start_seconds", "query_match_end_seconds",
+    "source_db", "refined_offset_ms", "refined_correlation",
+];
+
+/// Columns of `List --format csv`.
+const LIST_CSV_COLUMNS: &[&str] = &["song_id", "name", "file_path", "fingerprint_count"];
+
+/// JSON description of one match candidate (song info is looked up best-effort).
+fn match_to_json(conn: &Connection, fingerprinter: &Fingerprinter, m: &MatchResult) -> serde_json::Value {
+    let song = get_song_info_in(conn, m.source_db, m.song_id).ok().flatten();
+    serde_json::json!({
+        "song_id": m.song_id,
+        "name": song.as_ref().map(|s| s.name.clone()),
+        "artist": song.as_ref().and_then(|s| s.artist.clone()),
+        "score": m.score,
+        "weighted_score": m.weighted_score,
+        "distinct_hash_score": m.distinct_hash_score,
+        "confidence": m.confidence,
+        "query_coverage": m.query_coverage,
+        "offset_seconds": fingerprinter.frames_to_seconds(m.time_offset_in_song_frames),
+        "match_start_seconds": m.match_start_seconds,
+        "match_end_seconds": m.match_end_seconds,
+        "query_match_start_seconds": m.query_match_start_seconds,
+        "query_match_end_seconds": m.query_match_end_seconds,
+        "source_db": database_file(conn, m.source_db).ok().flatten(),
+    })
+}
+
+/// The Query JSON object: the best match's fields flattened at the top level, plus
+/// every returned candidate when `--top` was used.
+fn query_result_json(
+    conn: &Connection,
+    fingerprinter: &Fingerprinter,
+    candidates: &[MatchResult],
+    include_candidates: bool,
+) -> serde_json::Value {
+    let mut result = match candidates.first() {
+        Some(best) => match_to_json(conn, fingerprinter, best),
+        None => serde_json::json!({
+            "song_id": null, "name": null, "artist": null, "score": null, "confidence": null, "offset_seconds": null,
+        }),
+    };
+    result["matched"] = serde_json::Value::Bool(!candidates.is_empty());
+    if include_candidates {
+        result["candidates"] = candidates.iter().map(|c| match_to_json(conn, fingerprinter, c)).collect();
+    }
+    result
+}
+
+/// Display name for an enrolled file: the embedded title tag wins over --title, which wins
+/// over the file name. Artist and album tags are logged along the way.
+fn enroll_song_name(tags: &AudioTags, title: Option<String>, file_path: &Path) -> String {
+    if let Some(artist) = &tags.artist {
+        log::info!("Artist tag: {}", artist);
+    }
+    if let Some(album) = &tags.album {
+        log::info!("Album tag: {}", album);
+    }
+    tags.title.clone().or(title).unwrap_or_else(|| {
+        file_path.file_stem()
+            .unwrap_or_default() // Use empty string if no stem
+            .to_string_lossy()
+            .into_owned()
+    })
+}
+
+/// Path stored for the segment of `path` enrolled with --start/--end, in the Media Fragments
+/// `#t=start,end` form, so each segment of a file is a song of its own.
+fn segment_file_path(path: &str, start: f32, end: Option<f32>) -> String {
+    match end {
+        Some(end) => format!("{}#t={},{}", path, start, end),
+        None => format!("{}#t={}", path, start),
+    }
+}
+
+/// Splits a path from `segment_file_path` back into the file and the segment's bounds.
+fn split_segment_file_path(path: &str) -> Option<(&str, f32, Option<f32>)> {
+    let (file, fragment) = path.rsplit_once("#t=")?;
+    let (start, end) = match fragment.split_once(',') {
+        Some((start, end)) => (start, Some(end.parse().ok()?)),
+        None => (fragment, None),
+    };
+    Some((file, start.parse().ok()?, end))
+}
+
+// Width of the bar drawn by draw_enroll_progress, plus room for the stage name and percentage.
+const PROGRESS_BAR_WIDTH: usize = 30;
+const PROGRESS_LINE_WIDTH: usize = PROGRESS_BAR_WIDTH + 60;
+/// File names longer than this are cut short on the multi-file Enroll progress line.
+const PROGRESS_FILE_NAME_WIDTH: usize = 24;
+
+/// Redraws a one-line enrollment progress bar on stderr (only used when stderr is a terminal).
+fn draw_enroll_progress(stage: EnrollStage, fraction: f32) {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let filled = (fraction * PROGRESS_BAR_WIDTH as f32).round() as usize;
+    eprint!(
+        "\r{:<12} [{}{}] {:>3.0}%",
+        format!("{:?}", stage), "#".repeat(filled), "-".repeat(PROGRESS_BAR_WIDTH - filled), fraction * 100.0
+    );
+    let _ = io::stderr().flush();
+}
+
+/// Redraws the progress line of a multi-file Enroll on stderr: files stored so far, percent
+/// done, an ETA from the average time per file, and the file waited on next.
+fn draw_files_progress(done: usize, total: usize, elapsed: std::time::Duration, next: Option<&Path>) {
+    let fraction = done as f32 / total.max(1) as f32;
+    let filled = (fraction * PROGRESS_BAR_WIDTH as f32).round() as usize;
+    let eta = if done == 0 {
+        "-:--".to_string()
+    } else {
+        let seconds = elapsed.as_secs_f64() / done as f64 * (total - done) as f64;
+        format!("{}:{:02}", (seconds / 60.0) as u64, (seconds % 60.0) as u64)
+    };
+    let name: String = next
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().chars().take(PROGRESS_FILE_NAME_WIDTH).collect())
+        .unwrap_or_default();
+    let line = format!(
+        "\r{}/{} [{}{}] {:>3.0}% ETA {} {:<width$}",
+        done, total, "#".repeat(filled), "-".repeat(PROGRESS_BAR_WIDTH - filled), fraction * 100.0, eta, name,
+        width = PROGRESS_FILE_NAME_WIDTH
+    );
+    let mut shown = FILES_PROGRESS.lock().unwrap_or_else(PoisonError::into_inner);
+    eprint!("{}", line);
+    let _ = io::stderr().flush();
+    *shown = Some(line);
+}
+
+/// The multi-file Enroll progress line on screen, if any. Messages and log records (which
+/// worker threads write at any time) are printed above it instead of through it.
+static FILES_PROGRESS: Mutex<Option<String>> = Mutex::new(None);
+
+/// Runs `print` with the multi-file progress line (if shown) cleared, then redraws it.
+fn print_above_files_progress(print: impl FnOnce()) {
+    let shown = FILES_PROGRESS.lock().unwrap_or_else(PoisonError::into_inner);
+    if shown.is_some() {
+        clear_progress_line();
+    }
+    print();
+    if let Some(line) = shown.as_deref() {
+        eprint!("{}", line);
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Clears the multi-file progress line for good.
+fn finish_files_progress() {
+    if FILES_PROGRESS.lock().unwrap_or_else(PoisonError::into_inner).take().is_some() {
+        clear_progress_line();
+    }
+}
+
+/// env_logger, printing each record above the multi-file progress line.
+struct ProgressAwareLogger(env_logger::Logger);
+
+impl log::Log for ProgressAwareLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.0.matches(record) {
+            print_above_files_progress(|| self.0.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+/// Blanks the progress line so the result is printed on a clean line.
+fn clear_progress_line() {
+    eprint!("\r{:width$}\r", "", width = PROGRESS_LINE_WIDTH);
+    let _ = io::stderr().flush();
+}
+
+/// "Artist - Name" (or just the name) of a song for result listings.
+fn display_song_name(conn: &Connection, source_db: usize, song_id: SongId) -> String {
+    match get_song_info_in(conn, source_db, song_id) {
+        Ok(Some(song_info)) => match song_info.artist {
+            Some(artist) => format!("{} - {}", artist, song_info.name),
+            None => song_info.name,
+        },
+        Ok(None) => "(metadata not found)".to_string(),
+        Err(e) => format!("(error fetching info: {})", e),
+    }
+}
+
+/// What the user can do about audio that failed to load, appended to the error message.
+fn load_error_hint(e: &SivanaError) -> &'static str {
+    match e {
+        SivanaError::Load(LoadError::FileNotFound { .. }) => " (check the path)",
+        SivanaError::Load(LoadError::UnsupportedFormat(_)) => " (convert it to WAV, FLAC, MP3 or Ogg Vorbis, e.g. with ffmpeg, and try again)",
+        SivanaError::Load(LoadError::NoAudioTrack) => " (the file has no audio stream; extract one first)",
+        SivanaError::Load(LoadError::DecodeFailed(_)) => " (the file looks corrupt or truncated; try a fresh copy)",
+        SivanaError::Load(LoadError::Empty) => " (the file contains no audio)",
+        SivanaError::Load(LoadError::ResampleFailed(_)) => " (try another --resample-quality)",
+        _ => "",
+    }
+}
+
+/// Loads a query snippet at the fingerprinter's rate, optionally trimming silence below
+/// `trim_threshold` and normalizing loudness.
+fn load_query_samples(
+    snippet_path: &Path,
+    fingerprinter: &Fingerprinter,
+    load_options: &LoadOptions,
+    trim_threshold: Option<f32>,
+    normalize: bool,
+) -> Result<Vec<f32>, String> {
+    if !snippet_path.exists() {
+        return Err(format!("Query error: Snippet file not found at '{}'", snippet_path.display()));
+    }
+    let LoadedAudio { samples: mut query_samples, .. } = load_audio_file_with_info(snippet_path, fingerprinter.sample_rate, load_options)
+        .map_err(|e| format!("Error loading audio snippet '{}': {}{}", snippet_path.display(), e, load_error_hint(&e)))?;
+    if query_samples.is_empty() {
+        return Err(format!("No audio samples loaded from snippet '{}'.", snippet_path.display()));
+    }
+    log::info!("Loaded {} samples for query snippet.", query_samples.len());
+    if let Some(silence_threshold) = trim_threshold {
+        // Ignore silent runs shorter than 100 ms; those are part of the music.
+        let min_run = fingerprinter.sample_rate as usize / 10;
+        let before = query_samples.len();
+        query_samples = trim_silence(&query_samples, silence_threshold, min_run);
+        log::info!("Trimmed silence: {} -> {} samples.", before, query_samples.len());
+        if query_samples.is_empty() {
+            return Err(format!("Snippet '{}' is entirely silent at threshold {}.", snippet_path.display(), silence_threshold));
+        }
+    }
+    if normalize {
+        let gain = normalize_rms(&mut query_samples, DEFAULT_TARGET_RMS);
+        log::info!("Normalized loudness (gain {:.2}x).", gain);
+    }
+    Ok(query_samples)
+}
+
+/// A file fingerprinted by `enroll_files`, waiting to be stored.
+struct PreparedSong {
+    name: String,
+    tags: AudioTags,
+    fingerprints: Vec<Fingerprint>,
+    duration_seconds: f64,
+    /// Set with --dry-run, where the song is reported instead of stored.
+    stats: Option<FingerprintStats>,
+}
+
+/// Loads, truncates, normalizes and fingerprints one of `enroll_files`' files; needs no
+/// connection, so it runs on the thread pool. With `dry_run`, audio too sparse to enroll
+/// still succeeds so it can be reported.
+fn prepare_enroll_file(
+    fingerprinter: &Fingerprinter,
+    load_options: &LoadOptions,
+    file_path: &Path,
+    max_duration: Option<f32>,
+    normalize: bool,
+    dry_run: bool,
+) -> Result<PreparedSong, String> {
+    let mut audio = load_audio_file_with_info(file_path, fingerprinter.sample_rate, load_options)
+        .map_err(|e| format!("Error loading audio from '{}': {}{}", file_path.display(), e, load_error_hint(&e)))?;
+    if audio.samples.is_empty() {
+        return Err(format!("No audio samples loaded from '{}'. File might be empty, unsupported, or corrupted.", file_path.display()));
+    }
+    if let Some(max_seconds) = max_duration {
+        audio.samples.truncate((max_seconds.max(0.0) * fingerprinter.sample_rate as f32) as usize);
+    }
+    let duration_seconds = audio.duration_seconds();
+    if normalize {
+        normalize_rms(&mut audio.samples, DEFAULT_TARGET_RMS);
+    }
+    let name = enroll_song_name(&audio.tags, None, file_path);
+    let (fingerprints, stats) = if dry_run {
+        let (fingerprints, stats) = fingerprinter.fingerprint_with_stats(&audio.samples);
+        (fingerprints, Some(stats))
+    } else {
+        let fingerprints = fingerprinter
+            .fingerprint_for_enrollment(&name, &audio.samples)
+            .map_err(|e| format!("Error during enrollment process for '{}': {}", name, e))?;
+        (fingerprints, None)
+    };
+    Ok(PreparedSong { name, tags: audio.tags, fingerprints, duration_seconds, stats })
+}
+
+/// Stores a song from `prepare_enroll_file` under `path`, as single-file Enroll does.
+fn store_prepared_song(
+    conn: &mut Connection,
+    fingerprinter: &Fingerprinter,
+    song: &PreparedSong,
+    path: &str,
+    file_stamp: Option<FileStamp>,
+    force: bool,
+) -> Result<(), String> {
+    let db_song_id = match fingerprinter.enroll_fingerprinted(conn, &song.name, Some(path), &song.tags, &song.fingerprints, !force) {
+        Err(SivanaError::DuplicateDetected { existing_song_id }) => {
+            return Err(format!(
+                "'{}' appears to already be enrolled as song ID {}. Use --force to enroll it anyway.",
+                path, existing_song_id
+            ));
+        }
+        Err(e) => return Err(format!("Error during enrollment process for '{}': {}", song.name, e)),
+        Ok(db_song_id) => db_song_id,
+    };
+    if let Err(e) = set_song_duration(conn, db_song_id, song.duration_seconds) {
+        log::warn!("Failed to store duration for song ID {}: {}", db_song_id, e);
+    }
+    store_file_stamp(conn, db_song_id, file_stamp);
+    print_above_files_progress(|| println!("Successfully enrolled '{}' with DB Song ID: {}.", song.name, db_song_id));
+    Ok(())
+}
+
+/// Runs `prepare` on every item, on a pool of `jobs` threads (all cores if `None`) with the
+/// `rayon` feature, and hands the results to `consume` on this thread in input order. At most
+/// twice as many items as threads are started ahead of the one `consume` is waiting for, so a
+/// slow item holds back only that many finished results.
+fn for_each_prepared<T: Sync, R: Send>(
+    items: &[T],
+    jobs: Option<usize>,
+    prepare: impl Fn(&T) -> R + Sync,
+    mut consume: impl FnMut(usize, R),
+) -> Result<(), String> {
+    #[cfg(feature = "rayon")]
+    {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.unwrap_or(0))
+            .build()
+            .map_err(|e| format!("Failed to start the worker threads: {}", e))?;
+        let max_in_flight = 2 * pool.current_num_threads();
+        let prepare = &prepare;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        pool.in_place_scope(|scope| {
+            // Dropped once every item is started, so a panicking worker ends the loop below
+            // instead of leaving it waiting.
+            let mut sender = Some(sender);
+            let mut started = 0;
+            let mut next = 0;
+            // Results finished ahead of an earlier one wait here until it arrives.
+            let mut finished = std::collections::BTreeMap::new();
+            while next < items.len() {
+                while started < items.len().min(next + max_in_flight) {
+                    let (index, item) = (started, &items[started]);
+                    if let Some(sender) = sender.clone() {
+                        scope.spawn(move |_| {
+                            let _ = sender.send((index, prepare(item)));
+                        });
+                    }
+                    started += 1;
+                }
+                if started == items.len() {
+                    sender = None;
+                }
+                let Ok((index, result)) = receiver.recv() else { break };
+                finished.insert(index, result);
+                while let Some(result) = finished.remove(&next) {
+                    consume(next, result);
+                    next += 1;
+                }
+            }
+        });
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        if jobs.is_some_and(|jobs| jobs > 1) {
+            log::warn!("--jobs needs the rayon feature; enrolling one file at a time.");
+        }
+        for (index, item) in items.iter().enumerate() {
+            consume(index, prepare(item));
+        }
+    }
+    Ok(())
+}
+
+/// Enroll with several files: skips unchanged ones (unless `force` or `dry_run`), fingerprints the rest in
+/// parallel on `jobs` threads and stores them one at a time in the order given, so song IDs don't depend on
+/// which file finished first. A file that fails is reported and the others still enrolled.
+/// With `show_progress`, a progress bar with an ETA stays below the per-file messages. With
+/// `dry_run`, each file is reported instead of stored, followed by the files too sparse to match.
+#[allow(clippy::too_many_arguments)]
+fn enroll_files(
+    conn: &mut Connection,
+    fingerprinter: &Fingerprinter,
+    load_options: &LoadOptions,
+    files: &[PathBuf],
+    normalize: bool,
+    max_duration: Option<f32>,
+    force: bool,
+    dry_run: bool,
+    jobs: Option<usize>,
+    show_progress: bool,
+) -> Result<(), String> {
+    fingerprinter.check_params(conn).map_err(|e| e.to_string())?;
+    let mut pending = Vec::with_capacity(files.len());
+    for file_path in files {
+        let path = file_path.to_str().ok_or_else(|| format!("Invalid file path string for: {}", file_path.display()))?;
+        let file_stamp = FileStamp::of(file_path)
+            .inspect_err(|e| log::warn!("Could not read size/modification time of '{}': {}", file_path.display(), e))
+            .ok();
+        if !force
+            && !dry_run
+            && let Some(stamp) = file_stamp
+            && let Some(existing_song_id) = find_unchanged_song(conn, path, stamp)
+                .map_err(|e| format!("Failed to look up '{}' in the database: {}", path, e))?
+        {
+            println!(
+                "'{}' is unchanged since it was enrolled as song ID {}; skipping (use --force to re-enroll).",
+                file_path.display(), existing_song_id
+            );
+            continue;
+        }
+        pending.push((file_path, path, file_stamp));
+    }
+    log::info!("Enrolling {} of {} files.", pending.len(), files.len());
+
+    let started = std::time::Instant::now();
+    let next_path = |index: usize| pending.get(index).map(|(file_path, _, _)| file_path.as_path());
+    if show_progress {
+        draw_files_progress(0, pending.len(), started.elapsed(), next_path(0));
+    }
+    let mut failed = 0;
+    // Dry run: files whose fingerprints could never reach the default query score.
+    let mut sparse = Vec::new();
+    let outcome = for_each_prepared(
+        &pending,
+        jobs,
+        |(file_path, _, _)| prepare_enroll_file(fingerprinter, load_options, file_path, max_duration, normalize, dry_run),
+        |index, prepared| {
+            let (file_path, path, file_stamp) = pending[index];
+            let result = prepared.and_then(|song| match &song.stats {
+                Some(stats) => {
+                    print_above_files_progress(|| print_dry_run(&song.name, song.duration_seconds as f32, song.fingerprints.len(), stats));
+                    if song.fingerprints.len() < DEFAULT_MIN_MATCH_SCORE {
+                        sparse.push((file_path, song.fingerprints.len()));
+                    }
+                    Ok(())
+                }
+                None => store_prepared_song(conn, fingerprinter, &song, path, file_stamp, force),
+            });
+            if let Err(e) = result {
+                print_above_files_progress(|| eprintln!("{}", e));
+                failed += 1;
+            }
+            if show_progress {
+                draw_files_progress(index + 1, pending.len(), started.elapsed(), next_path(index + 1));
+            }
+        },
+    );
+    finish_files_progress();
+    outcome?;
+    let action = if dry_run { "fingerprint" } else { "enroll" };
+    if !pending.is_empty() {
+        let elapsed = started.elapsed().as_secs_f64();
+        println!(
+            "{} {} of {} files in {:.1} s ({:.2} files/sec).",
+            if dry_run { "Fingerprinted" } else { "Enrolled" }, pending.len() - failed, pending.len(), elapsed,
+            pending.len() as f64 / elapsed
+        );
+    }
+    if dry_run {
+        if sparse.is_empty() {
+            println!("Dry run; nothing was written. No file has fewer fingerprints than the default --min-score ({}).", DEFAULT_MIN_MATCH_SCORE);
+        } else {
+            println!(
+                "Dry run; nothing was written. {} file(s) have fewer fingerprints than the default --min-score ({}) and are unlikely to be matchable:",
+                sparse.len(), DEFAULT_MIN_MATCH_SCORE
+            );
+            for (file_path, num_fingerprints) in &sparse {
+                println!("  {} ({} fingerprints)", file_path.display(), num_fingerprints);
+            }
+        }
+    } else if failed < pending.len() {
+        fingerprinter.store_params(conn).map_err(|e| e.to_string())?;
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} files failed to {}.", failed, pending.len(), action));
+    }
+    Ok(())
+}
+
+/// Checkpoints the WAL after a large write (bulk or multi-file), logging how much it shrank.
+fn checkpoint_after_write(conn: &Connection) -> Result<(), String> {
+    let (before, after) = checkpoint_and_measure(conn)?;
+    log::info!(
+        "Checkpointed WAL after writing: {:.2} MiB -> {:.2} MiB.",
+        before as f64 / (1024.0 * 1024.0), after as f64 / (1024.0 * 1024.0)
+    );
+    Ok(())
+}
+
+/// Expands directories among QueryBatch's (and Enroll's) paths into the files directly inside them, sorted by name.
+fn expand_snippet_paths(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut snippets = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+                .map_err(|e| format!("Failed to read directory '{}': {}", path.display(), e))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|p| p.is_file())
+                .collect();
+            files.sort();
+            snippets.extend(files);
+        } else {
+            snippets.push(path.clone());
+        }
+    }
+    Ok(snippets)
+}
+
+/// Snippets shorter than this (in seconds) get a warning by default.
+const DEFAULT_MIN_QUERY_SECONDS: f32 = 5.0;
+
+/// Bounds accepted for --sample-rate.
+const MIN_SAMPLE_RATE: u32 = 1000;
+const MAX_SAMPLE_RATE: u32 = 384_000;
+/// --sample-rate values below this get a warning (telephone audio is 8 kHz).
+const LOW_SAMPLE_RATE_WARNING: u32 = 8000;
+
+/// Thins a query's fingerprints to at most `max_query_fingerprints` (see `thin_fingerprints`).
+fn cap_query_fingerprints(fingerprints: Vec<Fingerprint>, max_query_fingerprints: Option<usize>) -> Vec<Fingerprint> {
+    match max_query_fingerprints {
+        Some(max) if fingerprints.len() > max => {
+            log::info!("Matching with {} of the query's {} fingerprints (--max-query-fingerprints).", max, fingerprints.len());
+            thin_fingerprints(&fingerprints, max)
+        }
+        _ => fingerprints,
+    }
+}
+
+/// Warns when a query is unlikely to match because it is too short: below `min_duration`
+/// seconds, or with fewer fingerprints than `min_score` (a score counts agreeing query
+/// fingerprints, so it can't exceed that number). A "no match" is then not the database's fault.
+fn warn_if_query_too_short(fingerprinter: &Fingerprinter, num_samples: usize, num_fingerprints: usize, min_score: usize, min_duration: f32) {
+    let duration = num_samples as f32 / fingerprinter.sample_rate as f32;
+    if duration < min_duration {
+        log::warn!(
+            "The snippet is only {:.1}s long (recommended: at least {:.1}s); short snippets rarely produce enough fingerprints to match.",
+            duration, min_duration
+        );
+    }
+    if num_fingerprints < min_score {
+        log::warn!(
+            "Only {} fingerprints were generated, fewer than --min-score {}, so no song can reach the score needed to match. \
+             Use a longer snippet or lower --min-score.",
+            num_fingerprints, min_score
+        );
+    }
+}
+
+/// Refuses to query a database built with other fingerprinting parameters, unless `force`
+/// is set, in which case the mismatch is only logged.
+fn check_query_params(conn: &Connection, fingerprinter: &Fingerprinter, force: bool) -> Result<(), String> {
+    match fingerprinter.check_params(conn) {
+        Ok(()) => Ok(()),
+        Err(e) if force => {
+            log::warn!("{} Querying anyway because of --force.", e);
+            Ok(())
+        }
+        Err(e) => Err(format!("{} Pass --force to query anyway.", e)),
+    }
+}
+
+/// Attaches the extra `--db` databases of a Query to `conn`, each checked for matching
+/// fingerprinting parameters like the main one.
+fn attach_query_databases(conn: &Connection, fingerprinter: &Fingerprinter, paths: &[PathBuf], force: bool) -> Result<(), String> {
+    for path in paths {
+        attach_database(conn, path).map_err(|e| e.to_string())?;
+        let other = open_db_connection(path)
+            .map_err(|e| format!("Failed to open database '{}': {}", path.display(), e))?;
+        check_query_params(&other, fingerprinter, force).map_err(|e| format!("{}: {}", path.display(), e))?;
+        log::info!("Also searching database '{}'.", path.display());
+    }
+    Ok(())
+}
+
+/// The file a match was found in, when the query searches more than one database.
+fn source_db_label(conn: &Connection, source_db: usize) -> Option<String> {
+    if fingerprint_databases(conn).map_or(true, |dbs| dbs.len() < 2) {
+        return None;
+    }
+    database_file(conn, source_db).ok().flatten()
+}
+
+// Seconds of query audio, from the start of the matched span, cross-correlated by `refine_match_offset`.
+const REFINE_EXCERPT_SECONDS: f32 = 2.0;
+
+/// Loads the matched song from its enrolled file and refines `m`'s offset to the sample with
+/// `refine_offset`, searching one hop either side of the frame-level offset.
+fn refine_match_offset(
+    conn: &Connection,
+    fingerprinter: &Fingerprinter,
+    load_options: &LoadOptions,
+    query_samples: &[f32],
+    m: &MatchResult,
+) -> Result<RefinedOffset, String> {
+    let song = get_song_info_in(conn, m.source_db, m.song_id)
+        .map_err(|e| format!("failed to look up song ID {}: {}", m.song_id, e))?
+        .ok_or_else(|| format!("song ID {} not found", m.song_id))?;
+    let song_path = song.file_path.ok_or_else(|| format!("song ID {} has no file path", m.song_id))?;
+    // Songs enrolled from a segment of a file are matched against that segment only.
+    let (load_path, segment) = match split_segment_file_path(&song_path) {
+        Some((file, start, end)) if !Path::new(&song_path).exists() => (file, Some((start, end))),
+        _ => (song_path.as_str(), None),
+    };
+    let LoadedAudio { samples: mut song_samples, .. } = load_audio_file_with_info(Path::new(load_path), fingerprinter.sample_rate, load_options)
+        .map_err(|e| format!("failed to load '{}': {}{}", load_path, e, load_error_hint(&e)))?;
+    if let Some((start, end)) = segment {
+        song_samples = slice_seconds(&song_samples, fingerprinter.sample_rate, start, end)
+            .map_err(|e| format!("failed to cut the enrolled segment from '{}': {}", load_path, e))?
+            .to_vec();
+    }
+
+    let sample_rate = fingerprinter.sample_rate as f32;
+    let excerpt_start = ((m.query_match_start_seconds * sample_rate) as usize).min(query_samples.len());
+    let excerpt_end = (excerpt_start + (REFINE_EXCERPT_SECONDS * sample_rate) as usize).min(query_samples.len());
+    let coarse_offset = m.time_offset_in_song_frames * fingerprinter.hop_size as isize + excerpt_start as isize;
+    let refined = refine_offset(&song_samples, &query_samples[excerpt_start..excerpt_end], coarse_offset, fingerprinter.hop_size)
+        .ok_or_else(|| "the matched region is silent or outside the song's audio".to_string())?;
+    Ok(RefinedOffset { offset_samples: refined.offset_samples - excerpt_start as isize, ..refined })
+}
+
+fn samples_to_ms(fingerprinter: &Fingerprinter, samples: isize) -> f64 {
+    samples as f64 * 1000.0 / fingerprinter.sample_rate as f64
+}
+
+/// Text output for `refine_match_offset`; failures only warn, the match itself stands.
+fn print_refined_offset(conn: &Connection, fingerprinter: &Fingerprinter, load_options: &LoadOptions, query_samples: &[f32], m: &MatchResult) {
+    match refine_match_offset(conn, fingerprinter, load_options, query_samples, m) {
+        Ok(refined) => println!(
+            "Refined offset in song: {:.1} ms (correlation {:.2})",
+            samples_to_ms(fingerprinter, refined.offset_samples), refined.correlation
+        ),
+        Err(e) => log::warn!("Could not refine the offset: {}", e),
+    }
+}
+
+/// Runs `checkpoint_wal` and returns the WAL file's size in bytes before and after.
+fn checkpoint_and_measure(conn: &Connection) -> Result<(u64, u64), String> {
+    let wal_size = |conn: &Connection| {
+        get_db_stats(conn)
+            .map(|stats| stats.wal_file_size_bytes.unwrap_or(0))
+            .map_err(|e| format!("Failed to gather database stats: {}", e))
+    };
+    let before = wal_size(conn)?;
+    checkpoint_wal(conn).map_err(|e| format!("Failed to checkpoint the WAL: {}", e))?;
+    Ok((before, wal_size(conn)?))
+}
+
+/// `match_and_report` for `Query --peel`: finds up to `max_songs` songs overlapping in the
+/// query with `query_db_and_match_peeling` and prints them in the order found, in `format`.
+/// Returns the first (strongest) match, if any.
+#[allow(clippy::too_many_arguments)]
+fn report_overlapping_matches(
+    conn: &Connection,
+    fingerprinter: &Fingerprinter,
+    query_samples: &[f32],
+    max_songs: usize,
+    min_score: usize,
+    verify: bool,
+    max_hash_popularity: Option<usize>,
+    weight_by_magnitude: bool,
+    offset_tolerance_frames: usize,
+    max_query_fingerprints: Option<usize>,
+    min_duration: f32,
+    format: OutputFormat,
+) -> Option<MatchResult> {
+    let query_fingerprints = fingerprinter.fingerprint(query_samples);
+    log::info!("Generated {} fingerprints for query snippet.", query_fingerprints.len());
+    let query_fingerprints = cap_query_fingerprints(query_fingerprints, max_query_fingerprints);
+    warn_if_query_too_short(fingerprinter, query_samples.len(), query_fingerprints.len(), min_score, min_duration);
+    let options = QueryOptions {
+        verify_min_fraction: verify.then_some(DEFAULT_VERIFY_MIN_FRACTION),
+        max_hash_popularity,
+        weight_by_magnitude,
+        offset_tolerance_frames,
+        ..fingerprinter.query_options(min_score)
+    };
+    let matches = query_db_and_match_peeling(conn, &query_fingerprints, max_songs, &options);
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::json!({
+            "matched": !matches.is_empty(),
+            "matches": matches.iter().map(|m| match_to_json(conn, fingerprinter, m)).collect::<Vec<_>>(),
+        })),
+        OutputFormat::Csv => {
+            let rows: Vec<serde_json::Value> = if matches.is_empty() {
+                vec![query_result_json(conn, fingerprinter, &[], false)]
+            } else {
+                matches.iter().map(|m| query_result_json(conn, fingerprinter, std::slice::from_ref(m), false)).collect()
+            };
+            print_csv(QUERY_CSV_COLUMNS, &rows);
+        }
+        OutputFormat::Text if matches.is_empty() => println!("\n======= NO MATCH FOUND ======="),
+        OutputFormat::Text => {
+            println!("\n======= {} OVERLAPPING SONG(S), STRONGEST FIRST =======", matches.len());
+            for (rank, m) in matches.iter().enumerate() {
+                println!(
+                    "#{:<2} | ID: {:<4} | Name: {:<40} | Score: {:<5} | Confidence: {:>5.1}% | Offset: {:.2}s | Query: {:.2}s-{:.2}s (song {:.2}s-{:.2}s)",
+                    rank + 1, m.song_id, display_song_name(conn, m.source_db, m.song_id), m.score, m.confidence * 100.0,
+                    fingerprinter.frames_to_seconds(m.time_offset_in_song_frames),
+                    m.query_match_start_seconds, m.query_match_end_seconds, m.match_start_seconds, m.match_end_seconds
+                );
+            }
+        }
+    }
+    matches.into_iter().next()
+}
+
+/// Fingerprints query samples (already at `fingerprinter.sample_rate`), matches them against the
+/// database and prints the result in `format`. Returns the reported match (the
+/// top candidate with `top`), if any. With `refine_with`, the reported match's offset is also
+/// refined to the sample against the song's file, loaded with those options.
+#[allow(clippy::too_many_arguments)]
+fn match_and_report(
+    conn: &Connection,
+    fingerprinter: &Fingerprinter,
+    query_samples: &[f32],
+    top: Option<usize>,
+    min_score: usize,
+    min_coverage: f32,
+    verify: bool,
+    max_hash_popularity: Option<usize>,
+    weight_by_magnitude: bool,
+    offset_tolerance_frames: usize,
+    max_query_fingerprints: Option<usize>,
+    min_duration: f32,
+    explain: bool,
+    refine_with: Option<&LoadOptions>,
+    format: OutputFormat,
+) -> Option<MatchResult> {
+    let options = QueryOptions {
+        min_query_coverage: (min_coverage > 0.0).then_some(min_coverage),
+        verify_min_fraction: verify.then_some(DEFAULT_VERIFY_MIN_FRACTION),
+        max_hash_popularity,
+        weight_by_magnitude,
+        offset_tolerance_frames,
+        ..fingerprinter.query_options(min_score)
+    };
+    // Same pipeline (window, magnitude scale, mel bands, ...) as enrollment used.
+    let query_fingerprints = fingerprinter.fingerprint(query_samples);
+    if query_fingerprints.is_empty() { log::warn!("No fingerprints generated for query snippet. This might lead to no match."); }
+    log::info!("Generated {} fingerprints for query snippet.", query_fingerprints.len());
+    let query_fingerprints = cap_query_fingerprints(query_fingerprints, max_query_fingerprints);
+    warn_if_query_too_short(fingerprinter, query_samples.len(), query_fingerprints.len(), min_score, min_duration);
+
+    if query_fingerprints.is_empty() {
+        if format == OutputFormat::Json {
+            println!("{}", query_result_json(conn, fingerprinter, &[], top.is_some()));
+        } else if format == OutputFormat::Csv {
+            print_csv(QUERY_CSV_COLUMNS, &[query_result_json(conn, fingerprinter, &[], false)]);
+        } else {
+            println!("\n======= NO FINGERPRINTS GENERATED FOR QUERY, CANNOT MATCH =======");
+        }
+        return None;
+    }
+
+    if format != OutputFormat::Text {
+        let candidates = match top {
+            Some(n) => query_db_and_match_topn(conn, &query_fingerprints, n, &options),
+            None => query_db_and_match(conn, &query_fingerprints, &options).into_iter().collect(),
+        };
+        let mut result = query_result_json(conn, fingerprinter, &candidates, top.is_some());
+        if let (Some(load_options), Some(best)) = (refine_with, candidates.first()) {
+            let refined = refine_match_offset(conn, fingerprinter, load_options, query_samples, best)
+                .inspect_err(|e| log::warn!("Could not refine the offset: {}", e))
+                .ok();
+            result["refined_offset_ms"] = refined.map_or(serde_json::Value::Null, |r| samples_to_ms(fingerprinter, r.offset_samples).into());
+            result["refined_correlation"] = refined.map_or(serde_json::Value::Null, |r| r.correlation.into());
+        }
+        if explain && candidates.is_empty() {
+            let closest = query_db_closest_candidates(conn, &query_fingerprints, 1, fingerprinter.frame_duration_seconds(), max_hash_popularity);
+            result["closest"] = closest.first().map_or(serde_json::Value::Null, |c| {
+                let mut value = match_to_json(conn, fingerprinter, c);
+                value["confirmed"] = serde_json::Value::Bool(false);
+                value
+            });
+        }
+        if format == OutputFormat::Csv {
+            print_csv(QUERY_CSV_COLUMNS, &[result]);
+        } else {
+            println!("{}", result);
+        }
+        candidates.into_iter().next()
+    } else if let Some(n) = top {
+        let candidates = query_db_and_match_topn(conn, &query_fingerprints, n, &options);
+        if candidates.is_empty() {
+            println!("\n======= NO MATCH FOUND =======");
+            if explain {
+                print_closest_candidate(conn, &query_fingerprints, fingerprinter, min_score, min_coverage, max_hash_popularity);
+            }
+            return None;
+        }
+
+        println!("\n======= TOP {} CANDIDATE MATCHES =======", candidates.len());
+        for (rank, candidate) in candidates.iter().enumerate() {
+            let song_name = display_song_name(conn, candidate.source_db, candidate.song_id);
+            let offset_seconds = fingerprinter.frames_to_seconds(candidate.time_offset_in_song_frames);
+            let source = source_db_label(conn, candidate.source_db).map(|file| format!(" | DB: {}", file)).unwrap_or_default();
+            println!(
+                "#{:<2} | ID: {:<4} | Name: {:<40} | Score: {:<5} | Confidence: {:>5.1}% | Offset: {:.2}s | Region: {:.2}s-{:.2}s (query {:.2}s-{:.2}s){}",
+                rank + 1, candidate.song_id, song_name, candidate.score,
+                candidate.confidence * 100.0, offset_seconds,
+                candidate.match_start_seconds, candidate.match_end_seconds,
+                candidate.query_match_start_seconds, candidate.query_match_end_seconds, source
+            );
+        }
+        if let Some(load_options) = refine_with {
+            print_refined_offset(conn, fingerprinter, load_options, query_samples, &candidates[0]);
+        }
+        candidates.into_iter().next()
+    } else if let Some(match_result) = query_db_and_match(conn, &query_fingerprints, &options) {
+        println!("\n======= MATCH FOUND! =======");
+
+        if let Some(file) = source_db_label(conn, match_result.source_db) {
+            println!("Database: {}", file);
+        }
+        // Fetch full song info for better display
+        match get_song_info_in(conn, match_result.source_db, match_result.song_id) {
+            Ok(Some(song_info)) => {
+                println!("Matched Song ID: {}", song_info.id);
+                println!("Matched Song Name: {}", song_info.name);
+                if let Some(artist) = &song_info.artist {
+                    println!("Artist: {}", artist);
+                }
+                if let Some(album) = &song_info.album {
+                    println!("Album: {}", album);
+                }
+                if let Some(path) = song_info.file_path {
+                    println!("Original File Path: {}", path);
+                }
+            }
+            Ok(None) => {
+                println!("Matched Song ID: {} (but metadata not found in 'songs' table!)", match_result.song_id);
+            }
+            Err(e) => {
+                println!("Matched Song ID: {} (error fetching full info: {})", match_result.song_id, e);
+            }
+        }
+
+        println!("Match Score: {}", match_result.score);
+        println!("Confidence: {:.1}%", match_result.confidence * 100.0);
+        println!("Calculated Time Offset in Song (frames): {}", match_result.time_offset_in_song_frames);
+        let offset_seconds = fingerprinter.frames_to_seconds(match_result.time_offset_in_song_frames);
+        println!("(Approx. offset in matched song: {:.2} seconds)", offset_seconds);
+        println!(
+            "Matched region in song: {:.2}s - {:.2}s",
+            match_result.match_start_seconds, match_result.match_end_seconds
+        );
+        println!(
+            "Matched region in query: {:.2}s - {:.2}s",
+            match_result.query_match_start_seconds, match_result.query_match_end_seconds
+        );
+        if let Some(load_options) = refine_with {
+            print_refined_offset(conn, fingerprinter, load_options, query_samples, &match_result);
+        }
+        Some(match_result)
+    } else {
+        println!("\n======= NO MATCH FOUND =======");
+        if explain {
+            print_closest_candidate(conn, &query_fingerprints, fingerprinter, min_score, min_coverage, max_hash_popularity);
+        }
+        None
+    }
+}
+
+/// `--explain` output for a query without a match: the best-scoring song anyway, marked
+/// as unconfirmed.
+fn print_closest_candidate(
+    conn: &Connection,
+    query_fingerprints: &[Fingerprint],
+    fingerprinter: &Fingerprinter,
+    min_score: usize,
+    min_coverage: f32,
+    max_hash_popularity: Option<usize>,
+) {
+    let closest = query_db_closest_candidates(conn, query_fingerprints, 1, fingerprinter.frame_duration_seconds(), max_hash_popularity);
+    match closest.first() {
+        Some(candidate) => {
+            // A candidate reaching the threshold was rejected by coverage or verification instead.
+            let reason = if candidate.score < min_score {
+                format!("below threshold {}", min_score)
+            } else if candidate.query_coverage < min_coverage {
+                format!("only {:.1}% of the query hit the database", candidate.query_coverage * 100.0)
+            } else {
+                "failed verification".to_string()
+            };
+            println!(
+                "Closest: {} (ID {}), score {} ({}), offset {:.2}s",
+                display_song_name(conn, candidate.source_db, candidate.song_id), candidate.song_id, candidate.score, reason,
+                fingerprinter.frames_to_seconds(candidate.time_offset_in_song_frames)
+            );
+        }
+        None => println!("Closest: no enrolled song shares an aligned fingerprint with the query."),
+    }
 }
 
 // --- MAIN FUNCTION ---
 fn main() -> Result<(), String> {
     let cli_args = Cli::parse();
+    let json = cli_args.json;
+    // A redrawn progress line would garble JSON consumers, log output and redirected stderr.
+    let show_progress = !json && cli_args.verbose == 0 && io::stderr().is_terminal();
+    let enroll_progress: Option<&dyn Fn(EnrollStage, f32)> = if show_progress { Some(&draw_enroll_progress) } else { None };
+
+    // -v only raises our own crates' levels (symphonia is chatty at debug); RUST_LOG, if set,
+    // refines the result.
+    let log_level = match cli_args.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    let logger = env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Warn)
+        .filter_module("sivana", log_level)
+        .filter_module(module_path!(), log_level)
+        .format_timestamp(None)
+        .parse_default_env()
+        .build();
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(ProgressAwareLogger(logger))).map_err(|e| format!("Failed to set up logging: {}", e))?;
+
+    // The first --db is the one every command works on; only Query searches the others too.
+    let (main_db, extra_dbs) = cli_args.db.split_first().ok_or("No database path given.")?;
+    if !extra_dbs.is_empty() && !matches!(cli_args.command, Commands::Query { .. }) {
+        return Err("--db can only be given several times for Query.".to_string());
+    }
 
     // --- Initialize Database Connection (common to most commands) ---
+    // Bulk writes (and multi-file Enroll) can leave a large WAL behind; it is checkpointed once the command is done.
+    let bulk = !cli_args.in_memory
+        && matches!(cli_args.command, Commands::Enroll { bulk: true, .. } | Commands::Import { bulk: true, .. });
     // Make conn mutable as enroll_song needs it
-    let mut conn = open_db_connection()
-        .map_err(|e| format!("Failed to open/create database: {}", e))?;
-
-    // init_db should be safe to call every time; it uses "IF NOT EXISTS"
-    init_db(&conn)
-        .map_err(|e| format!("Failed to initialize database tables: {}", e))?;
-
-    // --- Parameters (could be loaded from config or become CLI options later) ---
-    let spec_peak_params = (2, 5, 2.0f32); // (time_radius, freq_radius, min_magnitude_threshold)
-    let hashing_params = (
-        TARGET_ZONE_DT_MIN_FRAMES,
-        TARGET_ZONE_DT_MAX_FRAMES,
-        TARGET_ZONE_DF_ABS_MAX_BINS,
-        MAX_PAIRS_PER_ANCHOR,
-    );
+    let mut conn = if cli_args.in_memory || matches!(cli_args.command, Commands::Selftest) {
+        log::info!("Using in-memory database; nothing will be saved when this command exits.");
+        open_in_memory_connection()
+            .map_err(|e| format!("Failed to open in-memory database: {}", e))?
+    } else {
+        let conn = if bulk { open_bulk_db_connection(main_db) } else { open_db_connection(main_db) }
+            .map_err(|e| format!("Failed to open/create database: {}", e))?;
+
+        // init_db should be safe to call every time; it uses "IF NOT EXISTS"
+        init_db(&conn)
+            .map_err(|e| format!("Failed to initialize database tables: {}", e))?;
+        conn
+    };
+
+    // --- Parameters ---
+    let mut window_size = cli_args.window_size;
+    if !window_size.is_power_of_two() {
+        if cli_args.round_window {
+            window_size = window_size.next_power_of_two();
+            log::info!("Rounded window size {} up to {}.", cli_args.window_size, window_size);
+        } else {
+            log::warn!(
+                "Window size {} is not a power of two, which makes the FFT much slower (see --round-window).",
+                window_size
+            );
+        }
+    }
+    if window_size < 2 || cli_args.hop_size == 0 || cli_args.hop_size > window_size {
+        return Err(format!(
+            "Invalid FFT settings: window size {} and hop size {} (window must be >= 2, 1 <= hop <= window).",
+            window_size, cli_args.hop_size
+        ));
+    }
+    if cli_args.sample_rate < LOW_SAMPLE_RATE_WARNING {
+        log::warn!(
+            "Sample rate {} Hz only keeps content below {} Hz; expect far fewer peaks and less reliable matches.",
+            cli_args.sample_rate, cli_args.sample_rate / 2
+        );
+    }
+    let mut fingerprinter = Fingerprinter::new(cli_args.sample_rate, window_size, cli_args.hop_size);
+    if cli_args.power_spectrum {
+        fingerprinter.magnitude_scale = MagnitudeScale::Power;
+    }
+    fingerprinter.pad_final_frame = cli_args.pad_final_frame;
+    fingerprinter.centered_frames = cli_args.center_frames;
+    if cli_args.normalize_magnitudes {
+        fingerprinter.normalized_magnitudes = true;
+        fingerprinter.scale_peak_params_for_normalization(window_sum(WindowType::default(), DEFAULT_FFT_WINDOW_SIZE));
+    }
+    #[cfg(feature = "mel")]
+    {
+        fingerprinter.mel_bands = cli_args.mel_bands.map(usize::from);
+    }
+    let fingerprinter = fingerprinter;
+    let load_options = LoadOptions {
+        resample_quality: cli_args.resample_quality,
+        remove_dc: cli_args.remove_dc,
+        ..LoadOptions::default()
+    };
 
     // Match on the parsed subcommand
     match cli_args.command {
-        Commands::Enroll { file_path, title } => {
-            println!("Enroll command received for: {}", file_path.display());
+        Commands::Enroll { file_paths, format, title, normalize, max_duration, start, end, force, stream, bulk: _, dry_run, jobs }
+            if file_paths.len() > 1 || file_paths[0].is_dir() =>
+        {
+            let single_file_option = [
+                ("--format", format.is_some()), ("--title", title.is_some()), ("--start", start.is_some()),
+                ("--end", end.is_some()), ("--stream", stream),
+            ]
+            .into_iter()
+            .find_map(|(option, given)| given.then_some(option));
+            if let Some(option) = single_file_option {
+                return Err(format!("{} only applies when enrolling a single file.", option));
+            }
+            if file_paths.iter().any(|path| path.as_os_str() == "-") {
+                return Err("Audio from stdin (-) can only be enrolled on its own.".to_string());
+            }
+            let files = expand_snippet_paths(&file_paths)?;
+            if files.is_empty() {
+                return Err("No audio files found.".to_string());
+            }
+            let enrolled = enroll_files(&mut conn, &fingerprinter, &load_options, &files, normalize, max_duration, force, dry_run, jobs, show_progress);
+            // Each file is its own transaction, so even without --bulk the WAL grows; --bulk
+            // runs are checkpointed at the end unless enrolling failed.
+            if !cli_args.in_memory && !dry_run && (!bulk || enrolled.is_err()) {
+                checkpoint_after_write(&conn)?;
+            }
+            enrolled?;
+        }
+        Commands::Enroll { file_paths, format, title, normalize, max_duration, start, end, force, stream, bulk: _, dry_run, jobs: _ } => {
+            let file_path = file_paths.into_iter().next().ok_or("No file to enroll.")?;
+            log::info!("Enroll command received for: {}", file_path.display());
 
-            if !file_path.exists() {
-                return Err(format!("Enroll error: File not found at '{}'", file_path.display()));
+            // "-" reads the audio from stdin; such songs have no stored file path.
+            let reading_stdin = file_path.as_os_str() == "-";
+            if reading_stdin {
+                if format.is_none() {
+                    return Err("Reading audio from stdin needs --format (e.g. --format wav), since there is no file extension.".to_string());
+                }
+                if stream {
+                    return Err("--stream needs a file; it cannot be used with audio from stdin.".to_string());
+                }
+            } else {
+                if !file_path.exists() {
+                    return Err(format!("Enroll error: File not found at '{}'", file_path.display()));
+                }
+                if format.is_some() {
+                    log::warn!("--format only applies to audio read from stdin; using the file's own format.");
+                }
             }
+            let source_label = if reading_stdin { "stdin".to_string() } else { file_path.display().to_string() };
 
-            let song_name = title.unwrap_or_else(|| {
-                file_path.file_stem()
-                    .unwrap_or_default() // Use empty string if no stem
-                    .to_string_lossy()
-                    .into_owned()
-            });
-            let file_path_str = file_path.to_str()
-                .ok_or_else(|| format!("Invalid file path string for: {}", file_path.display()))?;
+            let file_path_str = if reading_stdin {
+                None
+            } else {
+                Some(file_path.to_str().ok_or_else(|| format!("Invalid file path string for: {}", file_path.display()))?)
+            };
+            let segment = start.is_some() || end.is_some();
+            let segment_path = file_path_str.filter(|_| segment).map(|path| segment_file_path(path, start.unwrap_or(0.0), end));
+            let file_path_str = segment_path.as_deref().or(file_path_str);
+            fingerprinter.check_params(&conn).map_err(|e| e.to_string())?;
 
-            match load_audio_file(&file_path, SAMPLE_RATE) {
-                Ok(samples) => {
-                    if samples.is_empty() {
-                        return Err(format!("No audio samples loaded from '{}'. File might be empty, unsupported, or corrupted.", file_path.display()));
+            // Size and mtime of the file, to skip it next time if it hasn't changed. A segment
+            // is neither skipped nor recorded, so other segments of the file can be enrolled.
+            let file_stamp = if reading_stdin || segment {
+                None
+            } else {
+                FileStamp::of(&file_path)
+                    .inspect_err(|e| log::warn!("Could not read size/modification time of '{}': {}", file_path.display(), e))
+                    .ok()
+            };
+            if !force
+                && !dry_run
+                && let (Some(path), Some(stamp)) = (file_path_str, file_stamp)
+                && let Some(existing_song_id) = find_unchanged_song(&conn, path, stamp)
+                    .map_err(|e| format!("Failed to look up '{}' in the database: {}", path, e))?
+            {
+                println!(
+                    "'{}' is unchanged since it was enrolled as song ID {}; skipping (use --force to re-enroll).",
+                    file_path.display(), existing_song_id
+                );
+                return Ok(());
+            }
+
+            let max_samples = max_duration.map(|max_seconds| (max_seconds.max(0.0) * fingerprinter.sample_rate as f32) as usize);
+            if stream {
+                let mut audio = AudioStream::open(&file_path, fingerprinter.sample_rate, &load_options)
+                    .map_err(|e| format!("Error loading audio file '{}': {}{}", file_path.display(), e, load_error_hint(&e)))?;
+                let song_tags = audio.tags().clone();
+                let song_name = enroll_song_name(&song_tags, title, &file_path);
+                log::info!("Streaming '{}' (originally {} Hz).", song_name, audio.original_sample_rate());
+                let result = fingerprinter
+                    .enroll_stream(&mut conn, &song_name, file_path_str, &song_tags, &mut audio, max_samples, enroll_progress);
+                if show_progress {
+                    clear_progress_line();
+                }
+                let (db_song_id, samples_seen) = result
+                    .map_err(|e| format!("Error during enrollment process for '{}': {}", song_name, e))?;
+                fingerprinter.store_params(&conn).map_err(|e| e.to_string())?;
+                let duration_seconds = samples_seen as f64 / fingerprinter.sample_rate as f64;
+                if let Err(e) = set_song_duration(&conn, db_song_id, duration_seconds) {
+                    log::warn!("Failed to store duration for song ID {}: {}", db_song_id, e);
+                }
+                store_file_stamp(&conn, db_song_id, file_stamp);
+                println!("Successfully enrolled '{}' with DB Song ID: {}.", song_name, db_song_id);
+                log::info!("File path stored: {}", file_path.display());
+                return Ok(());
+            }
+
+            if let Some(progress) = enroll_progress {
+                progress(EnrollStage::Decoding, 0.0);
+            }
+            let loaded = if reading_stdin {
+                load_audio_from_reader_with_info(io::stdin(), format.as_deref(), fingerprinter.sample_rate, &load_options)
+            } else {
+                load_audio_file_with_info(&file_path, fingerprinter.sample_rate, &load_options)
+            };
+            match loaded {
+                Ok(mut audio) => {
+                    if let Some(progress) = enroll_progress {
+                        progress(EnrollStage::Decoding, 1.0);
+                    }
+                    if audio.samples.is_empty() {
+                        if show_progress {
+                            clear_progress_line();
+                        }
+                        return Err(format!("No audio samples loaded from '{}'. File might be empty, unsupported, or corrupted.", source_label));
+                    }
+                    if reading_stdin && audio.tags.title.is_none() && title.is_none() {
+                        if show_progress {
+                            clear_progress_line();
+                        }
+                        return Err("Audio from stdin has no title tag; pass --title to name the song.".to_string());
+                    }
+                    let song_name = enroll_song_name(&audio.tags, title, &file_path);
+                    log::info!(
+                        "Loaded {} samples for '{}' ({:.2} seconds, originally {} Hz).",
+                        audio.samples.len(), song_name, audio.duration_seconds(), audio.original_sample_rate
+                    );
+                    if segment {
+                        let start_seconds = start.unwrap_or(0.0);
+                        match slice_seconds(&audio.samples, fingerprinter.sample_rate, start_seconds, end) {
+                            Ok(samples) => audio.samples = samples.to_vec(),
+                            Err(e) => {
+                                if show_progress {
+                                    clear_progress_line();
+                                }
+                                return Err(format!("Cannot enroll a segment of '{}': {}", source_label, e));
+                            }
+                        }
+                        log::info!(
+                            "Enrolling the {:.2} second segment starting at {:.2} s; fingerprint times are relative to it.",
+                            audio.duration_seconds(), start_seconds
+                        );
+                    }
+                    if let Some(max_samples) = max_samples
+                        && audio.samples.len() > max_samples
+                    {
+                        audio.samples.truncate(max_samples);
+                        log::info!("Truncated to the first {:.2} seconds ({} samples).", max_duration.unwrap_or_default(), max_samples);
+                    }
+                    // The fingerprinted length, as the streaming path stores.
+                    let duration_seconds = audio.duration_seconds();
+                    if normalize {
+                        let gain = normalize_rms(&mut audio.samples, DEFAULT_TARGET_RMS);
+                        log::info!("Normalized loudness (gain {:.2}x).", gain);
                     }
-                    println!("Loaded {} samples for '{}'.", samples.len(), song_name);
-
-                    match enroll_song(
-                        &mut conn, // Pass mutable connection
-                        &song_name,
-                        Some(file_path_str),
-                        &samples,
-                        SAMPLE_RATE, FFT_WINDOW_SIZE, FFT_HOPSIZE,
-                        spec_peak_params, hashing_params
-                    ) {
+
+                    if dry_run {
+                        if show_progress {
+                            clear_progress_line();
+                        }
+                        report_dry_run(&fingerprinter, &song_name, &audio.samples);
+                        return Ok(());
+                    }
+
+                    let outcome = if force {
+                        fingerprinter
+                            .enroll_with_progress(&mut conn, &song_name, file_path_str, &audio.tags, &audio.samples, enroll_progress)
+                    } else {
+                        fingerprinter
+                            .enroll_unique_with_progress(&mut conn, &song_name, file_path_str, &audio.tags, &audio.samples, enroll_progress)
+                    };
+                    if show_progress {
+                        clear_progress_line();
+                    }
+                    match outcome {
+                        Err(SivanaError::DuplicateDetected { existing_song_id }) => {
+                            return Err(format!(
+                                "'{}' appears to already be enrolled as song ID {}. Use --force to enroll it anyway.",
+                                source_label, existing_song_id
+                            ));
+                        }
                         Ok(db_song_id) => {
+                            fingerprinter.store_params(&conn).map_err(|e| e.to_string())?;
+                            if let Err(e) = set_song_duration(&conn, db_song_id, duration_seconds) {
+                                log::warn!("Failed to store duration for song ID {}: {}", db_song_id, e);
+                            }
+                            store_file_stamp(&conn, db_song_id, file_stamp);
                             println!("Successfully enrolled '{}' with DB Song ID: {}.", song_name, db_song_id);
-                            println!("File path stored: {}", file_path_str);
+                            if let Some(path) = file_path_str {
+                                log::info!("File path stored: {}", path);
+                            }
                         }
                         Err(e) => {
                             return Err(format!("Error during enrollment process for '{}': {}", song_name, e));
@@ -123,115 +1850,484 @@ fn main() -> Result<(), String> {
                     }
                 }
                 Err(e) => {
-                    return Err(format!("Error loading audio file '{}': {}", file_path.display(), e));
+                    if show_progress {
+                        clear_progress_line();
+                    }
+                    return Err(format!("Error loading audio from '{}': {}{}", source_label, e, load_error_hint(&e)));
                 }
             }
         }
-        Commands::Query { snippet_path } => {
-            println!("Query command received for snippet: {}", snippet_path.display());
+        Commands::Query {
+            snippet_path, top, min_score, normalize, trim_silence: trim, silence_threshold, force, min_coverage, no_verify, max_hash_popularity, weight_magnitude, offset_tolerance, max_query_fingerprints, min_duration,
+            explain, log_queries, refine_offset, peel, format,
+        } => {
+            let format = output_format(format, json)?;
+            log::info!("Query command received for snippet: {}", snippet_path.display());
+            check_query_params(&conn, &fingerprinter, force)?;
+            attach_query_databases(&conn, &fingerprinter, extra_dbs, force)?;
 
-            if !snippet_path.exists() {
-                return Err(format!("Query error: Snippet file not found at '{}'", snippet_path.display()));
+            let trim_threshold = trim.then_some(silence_threshold);
+            let query_samples = load_query_samples(&snippet_path, &fingerprinter, &load_options, trim_threshold, normalize)?;
+            let reported = match peel {
+                Some(max_songs) => report_overlapping_matches(
+                    &conn, &fingerprinter, &query_samples, max_songs, min_score, !no_verify, max_hash_popularity, weight_magnitude,
+                    offset_tolerance, max_query_fingerprints, min_duration, format,
+                ),
+                None => match_and_report(&conn, &fingerprinter, &query_samples, top, min_score, min_coverage, !no_verify, max_hash_popularity, weight_magnitude, offset_tolerance, max_query_fingerprints, min_duration, explain, refine_offset.then_some(&load_options), format),
+            };
+            if log_queries
+                && let Err(e) = log_query(&conn, Some(&snippet_path.to_string_lossy()), reported.as_ref())
+            {
+                log::warn!("Failed to record the query in the query log: {}", e);
             }
+        }
+        Commands::QueryBatch {
+            paths, min_score, normalize, trim_silence: trim, silence_threshold, force, min_coverage, no_verify, max_hash_popularity, weight_magnitude, offset_tolerance, max_query_fingerprints, min_duration, aggregate, spacing,
+        } => {
+            check_query_params(&conn, &fingerprinter, force)?;
+            let snippet_paths = expand_snippet_paths(&paths)?;
+            if snippet_paths.is_empty() {
+                return Err("No snippet files found.".to_string());
+            }
+            log::info!("QueryBatch command received for {} snippets.", snippet_paths.len());
 
-            match load_audio_file(&snippet_path, SAMPLE_RATE) {
-                Ok(query_samples) => {
-                    if query_samples.is_empty() {
-                        return Err(format!("No audio samples loaded from snippet '{}'.", snippet_path.display()));
-                    }
-                    println!("Loaded {} samples for query snippet.", query_samples.len());
-
-                    let query_spectrogram = create_spectrogram(&query_samples, SAMPLE_RATE, FFT_WINDOW_SIZE, FFT_HOPSIZE);
-                    if query_spectrogram.is_empty() { println!("Warning: Query spectrogram is empty. This might lead to no match."); }
+            let options = QueryOptions {
+                min_query_coverage: (min_coverage > 0.0).then_some(min_coverage),
+                verify_min_fraction: (!no_verify).then_some(DEFAULT_VERIFY_MIN_FRACTION),
+                max_hash_popularity,
+                weight_by_magnitude: weight_magnitude,
+                offset_tolerance_frames: offset_tolerance,
+                ..fingerprinter.query_options(min_score)
+            };
+            let trim_threshold = trim.then_some(silence_threshold);
+            // One entry per snippet; a snippet that fails to load is reported and counts as unmatched.
+            let mut results: Vec<Result<Option<MatchResult>, String>> = Vec::with_capacity(snippet_paths.len());
+            for snippet_path in &snippet_paths {
+                let result = load_query_samples(snippet_path, &fingerprinter, &load_options, trim_threshold, normalize).map(|samples| {
+                    let query_fingerprints = fingerprinter.fingerprint(&samples);
+                    log::info!("Generated {} fingerprints for snippet '{}'.", query_fingerprints.len(), snippet_path.display());
+                    let query_fingerprints = cap_query_fingerprints(query_fingerprints, max_query_fingerprints);
+                    warn_if_query_too_short(&fingerprinter, samples.len(), query_fingerprints.len(), min_score, min_duration);
+                    query_db_and_match(&conn, &query_fingerprints, &options)
+                });
+                if let Err(e) = &result {
+                    log::warn!("{}", e);
+                }
+                results.push(result);
+            }
 
-                    let query_peaks = find_peaks(&query_spectrogram, spec_peak_params.0, spec_peak_params.1, spec_peak_params.2);
-                    if query_peaks.is_empty() { println!("Warning: No peaks found in query snippet. This might lead to no match."); }
+            let aggregated = aggregate.then(|| {
+                let matches: Vec<Option<MatchResult>> = results.iter().map(|r| r.clone().ok().flatten()).collect();
+                aggregate_matches(&matches, fingerprinter.frame_duration_seconds(), spacing)
+            });
 
-                    let query_fingerprints = create_hashes(&query_peaks, hashing_params.0, hashing_params.1, hashing_params.2, hashing_params.3);
-                    if query_fingerprints.is_empty() { println!("Warning: No fingerprints generated for query snippet. This might lead to no match."); }
-                    println!("Generated {} fingerprints for query snippet.", query_fingerprints.len());
+            if json {
+                let snippets: Vec<serde_json::Value> = snippet_paths.iter().zip(&results).map(|(path, result)| {
+                    let mut entry = match result {
+                        Ok(m) => query_result_json(&conn, &fingerprinter, m.as_slice(), false),
+                        Err(e) => serde_json::json!({ "matched": false, "error": e }),
+                    };
+                    entry["path"] = serde_json::Value::String(path.display().to_string());
+                    entry
+                }).collect();
+                let mut output = serde_json::json!({ "snippets": snippets });
+                if let Some(aggregated) = &aggregated {
+                    output["aggregate"] = match aggregated {
+                        Some(a) => serde_json::json!({
+                            "song_id": a.song_id,
+                            "name": get_song_info(&conn, a.song_id).ok().flatten().map(|s| s.name),
+                            "votes": a.votes,
+                            "snippets": a.snippets,
+                            "consistent_votes": a.consistent_votes,
+                            "total_score": a.total_score,
+                            "confidence": a.confidence,
+                        }),
+                        None => serde_json::Value::Null,
+                    };
+                }
+                println!("{}", output);
+            } else {
+                println!("\n======= RESULTS FOR {} SNIPPETS =======", snippet_paths.len());
+                for (idx, (path, result)) in snippet_paths.iter().zip(&results).enumerate() {
+                    let snippet_name = path.file_name().unwrap_or_default().to_string_lossy();
+                    match result {
+                        Ok(Some(m)) => println!(
+                            "#{:<3} | {:<30} | ID: {:<4} | Name: {:<40} | Score: {:<5} | Confidence: {:>5.1}% | Offset: {:.2}s",
+                            idx + 1, snippet_name, m.song_id, display_song_name(&conn, m.source_db, m.song_id), m.score,
+                            m.confidence * 100.0, fingerprinter.frames_to_seconds(m.time_offset_in_song_frames)
+                        ),
+                        Ok(None) => println!("#{:<3} | {:<30} | no match", idx + 1, snippet_name),
+                        Err(e) => println!("#{:<3} | {:<30} | error: {}", idx + 1, snippet_name, e),
+                    }
+                }
+                match aggregated {
+                    Some(Some(a)) => {
+                        println!("\n======= MOST LIKELY SONG =======");
+                        println!("Song ID: {}", a.song_id);
+                        println!("Song Name: {}", display_song_name(&conn, 0, a.song_id));
+                        println!("Votes: {} of {} snippets", a.votes, a.snippets);
+                        if spacing.is_some() {
+                            println!("Offset-consistent votes: {}", a.consistent_votes);
+                        }
+                        println!("Total Score: {}", a.total_score);
+                        println!("Combined Confidence: {:.1}%", a.confidence * 100.0);
+                    }
+                    Some(None) => println!("\n======= NO SNIPPET MATCHED ======="),
+                    None => {}
+                }
+            }
+        }
+        #[cfg(feature = "microphone")]
+        Commands::Listen { seconds, top, min_score, force, min_coverage, no_verify, max_hash_popularity, offset_tolerance } => {
+            check_query_params(&conn, &fingerprinter, force)?;
+            let mut samples = sivana::microphone::record_mono(seconds, fingerprinter.sample_rate, cli_args.resample_quality)
+                .map_err(|e| e.to_string())?;
+            if samples.is_empty() {
+                return Err("No audio was captured from the input device.".to_string());
+            }
+            // Room recordings vary wildly in level; bring them to the usual loudness.
+            let gain = normalize_rms(&mut samples, DEFAULT_TARGET_RMS);
+            log::info!("Normalized loudness (gain {:.2}x).", gain);
+            let _ = match_and_report(&conn, &fingerprinter, &samples, top, min_score, min_coverage, !no_verify, max_hash_popularity, false, offset_tolerance, None, DEFAULT_MIN_QUERY_SECONDS, false, None, output_format(OutputFormat::Text, json)?);
+        }
+        Commands::History { limit } => {
+            let entries = recent_queries(&conn, limit)
+                .map_err(|e| format!("Failed to read the query log: {}", e))?;
+            if json {
+                let entries_json: Vec<serde_json::Value> = entries.iter().map(|entry| serde_json::json!({
+                    "query_id": entry.query_id,
+                    "queried_at": entry.queried_at,
+                    "snippet_path": entry.snippet_path,
+                    "song_id": entry.song_id,
+                    "song_name": entry.song_name,
+                    "score": entry.score,
+                    "confidence": entry.confidence,
+                })).collect();
+                println!("{}", serde_json::Value::Array(entries_json));
+                return Ok(());
+            }
 
-                    if query_fingerprints.is_empty() {
-                        println!("\n======= NO FINGERPRINTS GENERATED FOR QUERY, CANNOT MATCH =======");
-                        return Ok(());
+            println!("\n--- Recent Queries ---");
+            for entry in &entries {
+                let result = match (entry.score, entry.song_id) {
+                    (None, _) => "no match".to_string(),
+                    (Some(score), song_id) => {
+                        let song = match (song_id, &entry.song_name) {
+                            (Some(id), Some(name)) => format!("{} (ID {})", name, id),
+                            (Some(id), None) => format!("deleted song (ID {})", id),
+                            (None, _) => "song in an attached database".to_string(),
+                        };
+                        format!("{} | Score: {} | Confidence: {:.1}%", song, score, entry.confidence.unwrap_or(0.0) * 100.0)
                     }
+                };
+                println!(
+                    "#{:<5} | {} | Snippet: {:<40} | {}",
+                    entry.query_id, entry.queried_at, entry.snippet_path.as_deref().unwrap_or("-"), result
+                );
+            }
+            if entries.is_empty() {
+                println!("No queries logged yet (use Query --log-queries).");
+            } else {
+                println!("--- Showed {} queries. ---", entries.len());
+            }
+        }
+        Commands::List { name, limit, offset, format } => {
+            let format = output_format(format, json)?;
+            let songs = list_songs(&conn, name.as_deref(), limit, offset)
+                .map_err(|e| format!("Failed to list songs: {}", e))?;
+            let fingerprint_counts = songs
+                .iter()
+                .map(|song| get_song_fingerprint_count(&conn, song.id))
+                .collect::<Result<Vec<usize>, _>>()
+                .map_err(|e| format!("Failed to count fingerprints: {}", e))?;
+            if format != OutputFormat::Text {
+                let songs_json: Vec<serde_json::Value> = songs.iter().zip(&fingerprint_counts).map(|(song, &fingerprint_count)| serde_json::json!({
+                    "song_id": song.id,
+                    "name": song.name,
+                    "artist": song.artist,
+                    "album": song.album,
+                    "file_path": song.file_path,
+                    "duration_seconds": song.duration_seconds,
+                    "fingerprint_count": fingerprint_count,
+                    "low_fingerprint_count": fingerprint_count < LOW_FINGERPRINT_COUNT,
+                })).collect();
+                if format == OutputFormat::Csv {
+                    print_csv(LIST_CSV_COLUMNS, &songs_json);
+                } else {
+                    println!("{}", serde_json::Value::Array(songs_json));
+                }
+                return Ok(());
+            }
 
-                    if let Some(match_result) = query_db_and_match(&conn, &query_fingerprints) {
-                        println!("\n======= MATCH FOUND! =======");
+            println!("\n--- Enrolled Songs in Database ---");
 
-                        // Fetch full song info for better display
-                        match get_song_info(&conn, match_result.song_id) {
-                            Ok(Some(song_info)) => {
-                                println!("Matched Song ID: {}", song_info.id);
-                                println!("Matched Song Name: {}", song_info.name);
-                                if let Some(path) = song_info.file_path {
-                                    println!("Original File Path: {}", path);
-                                }
-                            }
-                            Ok(None) => {
-                                println!("Matched Song ID: {} (but metadata not found in 'songs' table!)", match_result.song_id);
-                            }
-                            Err(e) => {
-                                println!("Matched Song ID: {} (error fetching full info: {})", match_result.song_id, e);
-                            }
-                        }
+            for (song, &fingerprint_count) in songs.iter().zip(&fingerprint_counts) {
+                let duration = song.duration_seconds
+                    .map(|d| format!("{}:{:02}", (d / 60.0) as u64, (d % 60.0) as u64))
+                    .unwrap_or_else(|| "-".to_string());
+                let artist = song.artist.as_deref().unwrap_or("-");
+                let low = if fingerprint_count < LOW_FINGERPRINT_COUNT { "!" } else { " " };
+                print!(
+                    "ID: {:<4} | Name: {:<40} | Artist: {:<24} | Duration: {:>6} | Fingerprints: {:>7}{} | Path: ",
+                    song.id, song.name, artist, duration, fingerprint_count, low
+                );
+                if let Some(path) = &song.file_path {
+                    print!("{}", path);
+                } else {
+                    print!("N/A");
+                }
+                println!(); // Newline
+            }
+            if songs.is_empty() {
+                println!("No songs found in the database.");
+            } else {
+                println!("--- Listed {} songs. ---", songs.len());
+            }
+            let low_count = fingerprint_counts.iter().filter(|&&count| count < LOW_FINGERPRINT_COUNT).count();
+            if low_count > 0 {
+                println!(
+                    "{} song(s) marked '!' have fewer than {} fingerprints and may not match reliably.",
+                    low_count, LOW_FINGERPRINT_COUNT
+                );
+            }
+        }
+        Commands::Dedup { min_score } => {
+            let pairs = find_duplicate_songs(&conn, min_score)
+                .map_err(|e| format!("Failed to search for duplicate songs: {}", e))?;
+            let groups = duplicate_groups(&pairs);
+            if json {
+                let pairs_json: Vec<serde_json::Value> = pairs.iter().map(|pair| serde_json::json!({
+                    "song_id": pair.song_id,
+                    "other_song_id": pair.other_song_id,
+                    "score": pair.score,
+                    "confidence": pair.confidence,
+                })).collect();
+                println!("{}", serde_json::json!({ "groups": groups, "pairs": pairs_json }));
+                return Ok(());
+            }
+
+            println!("\n--- Suspected Duplicates ---");
+            let song_label = |song_id: SongId| match get_song_info(&conn, song_id) {
+                Ok(Some(song)) => format!("{} (ID {}, path {})", song.name, song_id, song.file_path.as_deref().unwrap_or("N/A")),
+                _ => format!("ID {}", song_id),
+            };
+            for (group_idx, group) in groups.iter().enumerate() {
+                println!("Group {}:", group_idx + 1);
+                for &song_id in group {
+                    println!("  {}", song_label(song_id));
+                }
+                for pair in pairs.iter().filter(|pair| group.contains(&pair.song_id)) {
+                    println!(
+                        "    {} <-> {}: score {}, confidence {:.1}%",
+                        pair.song_id, pair.other_song_id, pair.score, pair.confidence * 100.0
+                    );
+                }
+            }
+            if groups.is_empty() {
+                println!("No duplicate songs found (min score {}).", min_score);
+            } else {
+                println!("--- Found {} group(s) of duplicates; delete the extra copies with Delete. ---", groups.len());
+            }
+        }
+        Commands::Delete { song_id } => {
+            log::info!("Delete command received for song ID: {}", song_id);
 
-                        println!("Match Score: {}", match_result.score);
-                        println!("Calculated Time Offset in Song (frames): {}", match_result.time_offset_in_song_frames);
-                        let offset_seconds = (match_result.time_offset_in_song_frames as f32 * FFT_HOPSIZE as f32) / SAMPLE_RATE as f32;
-                        println!("(Approx. offset in matched song: {:.2} seconds)", offset_seconds);
+            // Count before deleting; the cascade removes the rows so they can't be counted afterwards.
+            let fingerprint_count = get_song_fingerprint_count(&conn, song_id).map_err(|e| format!("Failed to count fingerprints for song ID {}: {}", song_id, e))?;
 
-                    } else {
-                        println!("\n======= NO MATCH FOUND =======");
-                    }
+            if delete_song(&mut conn, song_id).map_err(|e| e.to_string())? {
+                println!("Deleted song ID {} and {} fingerprints.", song_id, fingerprint_count);
+            } else {
+                println!("No song found with ID {}. Nothing deleted.", song_id);
+            }
+        }
+        Commands::Rename { song_id, name, path } => {
+            match update_song_metadata(&conn, song_id, Some(&name), path.as_deref()) {
+                Ok(true) => println!("Renamed song ID {} to '{}'.", song_id, name),
+                Ok(false) => println!("No song found with ID {}. Nothing renamed.", song_id),
+                Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::ConstraintViolation => {
+                    return Err(format!(
+                        "Cannot update song ID {}: path '{}' is already used by another song.",
+                        song_id, path.as_deref().unwrap_or_default()
+                    ));
                 }
-                Err(e) => {
-                    return Err(format!("Error loading audio snippet '{}': {}", snippet_path.display(), e));
+                Err(e) => return Err(format!("Failed to update song ID {}: {}", song_id, e)),
+            }
+        }
+        Commands::ShowPeaks { file_path, limit } => {
+            let audio = load_audio_file_with_info(&file_path, fingerprinter.sample_rate, &load_options)
+                .map_err(|e| format!("Error loading audio file '{}': {}{}", file_path.display(), e, load_error_hint(&e)))?;
+            let (_, peaks) = fingerprinter.spectrogram_and_peaks(&audio.samples);
+            let shown = limit.unwrap_or(peaks.len()).min(peaks.len());
+            let bin_hz = fingerprinter.bin_to_hz(1);
+            if bin_hz.is_none() {
+                log::warn!("Mel bands are enabled; peaks are shown as band indices without frequencies.");
+            }
+
+            if json {
+                let values: Vec<serde_json::Value> = peaks[..shown].iter().map(|p| serde_json::json!({
+                    "time_seconds": fingerprinter.frames_to_seconds(p.time_idx as isize),
+                    "frame": p.time_idx,
+                    "bin": p.freq_bin_idx,
+                    "frequency_hz": fingerprinter.bin_to_hz(p.freq_bin_idx),
+                })).collect();
+                println!("{}", serde_json::Value::Array(values));
+            } else {
+                match bin_hz {
+                    Some(resolution) => println!(
+                        "{} peaks in '{}' ({:.2} Hz per bin, {:.1} ms per frame):",
+                        peaks.len(), file_path.display(), resolution, fingerprinter.frame_duration_seconds() * 1000.0
+                    ),
+                    None => println!("{} peaks in '{}':", peaks.len(), file_path.display()),
+                }
+                println!("{:>10} {:>7} {:>6} {:>10}", "time_s", "frame", "bin", "freq_hz");
+                for peak in &peaks[..shown] {
+                    let hz = fingerprinter.bin_to_hz(peak.freq_bin_idx).map_or("-".to_string(), |hz| format!("{:.1}", hz));
+                    println!(
+                        "{:>10.3} {:>7} {:>6} {:>10}",
+                        fingerprinter.frames_to_seconds(peak.time_idx as isize), peak.time_idx, peak.freq_bin_idx, hz
+                    );
+                }
+                if shown < peaks.len() {
+                    println!("... {} more (see --limit).", peaks.len() - shown);
                 }
             }
         }
-        Commands::List => {
-            println!("\n--- Enrolled Songs in Database ---");
-            let mut stmt = conn.prepare("SELECT song_id, name, file_path, enrolled_at FROM songs ORDER BY name ASC")
-                .map_err(|e| format!("Failed to prepare statement to list songs: {}", e))?;
-
-            let song_iter = stmt.query_map([], |row| {
-                // Directly use database::Song if its fields match and it's pub
-                Ok(crate::database::Song {
-                    id: row.get::<_, i64>(0)? as SongId, // Assuming SongId is u32
-                    name: row.get(1)?,
-                    file_path: row.get(2)?,
-                    // enrolled_at: row.get(3)?, // Needs chrono feature for rusqlite for DATETIME
-                })
-            }).map_err(|e| format!("Failed to query songs: {}", e))?;
-
-            let mut count = 0;
-            for song_result in song_iter {
-                match song_result {
-                    Ok(song) => {
-                        count += 1;
-                        print!("ID: {:<4} | Name: {:<40} | Path: ", song.id, song.name);
-                        if let Some(path) = song.file_path {
-                            print!("{}", path);
-                        } else {
-                            print!("N/A");
-                        }
-                        // To print enrolled_at, you'd need to handle its type (likely String or a DateTime type if using chrono)
-                        // println!(" | Enrolled: {}", row.get::<_, String>(3)?);
-                        println!(); // Newline
-                    }
-                    Err(e) => {
-                        eprintln!("Error fetching song row: {}", e);
-                    }
+        Commands::Peaks { file_path, out } => {
+            let audio = load_audio_file_with_info(&file_path, fingerprinter.sample_rate, &load_options)
+                .map_err(|e| format!("Error loading audio file '{}': {}{}", file_path.display(), e, load_error_hint(&e)))?;
+            let (spectrogram, peaks) = fingerprinter.spectrogram_and_peaks(&audio.samples);
+            write_constellation_csv(&fingerprinter, &spectrogram, &peaks, &out)
+                .map_err(|e| format!("Failed to write '{}': {}", out.display(), e))?;
+            println!("Wrote {} peaks of '{}' to '{}'.", peaks.len(), file_path.display(), out.display());
+        }
+        Commands::Compare { reference, probe } => {
+            let load = |path: &PathBuf| -> Result<Vec<f32>, String> {
+                let audio = load_audio_file_with_info(path, fingerprinter.sample_rate, &load_options)
+                    .map_err(|e| format!("Error loading audio file '{}': {}{}", path.display(), e, load_error_hint(&e)))?;
+                if audio.samples.is_empty() {
+                    return Err(format!("No audio samples loaded from '{}'.", path.display()));
                 }
+                Ok(audio.samples)
+            };
+            let reference_samples = load(&reference)?;
+            let probe_samples = load(&probe)?;
+            let result = fingerprinter.compare(&reference_samples, &probe_samples);
+
+            if json {
+                let value = match result {
+                    Some(m) => serde_json::json!({
+                        "matched": true,
+                        "score": m.score,
+                        "confidence": m.confidence,
+                        "offset_seconds": fingerprinter.frames_to_seconds(m.time_offset_frames),
+                    }),
+                    None => serde_json::json!({
+                        "matched": false, "score": null, "confidence": null, "offset_seconds": null,
+                    }),
+                };
+                println!("{}", value);
+            } else if let Some(m) = result {
+                println!("\n======= PROBE FOUND IN REFERENCE =======");
+                println!("Score: {}", m.score);
+                println!("Confidence: {:.1}%", m.confidence * 100.0);
+                println!("Offset in reference: {:.2} seconds", fingerprinter.frames_to_seconds(m.time_offset_frames));
+            } else {
+                println!("\n======= NO COMMON FINGERPRINTS =======");
             }
-            if count == 0 {
-                println!("No songs found in the database.");
+        }
+        Commands::Export { song_id, output } => {
+            let count = export_fingerprints(&conn, song_id, &output).map_err(|e| format!("Export error: {}", e))?;
+            println!("Exported {} fingerprints of song ID {} to '{}'.", count, song_id, output.display());
+        }
+        Commands::Import { input, bulk: _, keep_id } => {
+            let song_id = if keep_id { import_fingerprints_keeping_id(&mut conn, &input) } else { import_fingerprints(&mut conn, &input) }
+                .map_err(|e| format!("Import error: {}", e))?;
+            println!("Imported '{}' as song ID {}.", input.display(), song_id);
+        }
+        Commands::DbInfo => {
+            let stats = get_db_stats(&conn)
+                .map_err(|e| format!("Failed to gather database stats: {}", e))?;
+
+            println!("\n--- Database Info ---");
+            println!("Enrolled songs:            {}", stats.song_count);
+            println!("Total fingerprints:        {}", stats.fingerprint_count);
+            println!("Avg fingerprints per song: {:.1}", stats.avg_fingerprints_per_song);
+            println!("Database file size:        {:.2} MiB ({} bytes)", stats.db_file_size_bytes as f64 / (1024.0 * 1024.0), stats.db_file_size_bytes);
+            match stats.wal_file_size_bytes {
+                Some(wal_size) => println!("WAL file size:             {:.2} MiB ({} bytes)", wal_size as f64 / (1024.0 * 1024.0), wal_size),
+                None => println!("WAL file size:             (no WAL file)"),
+            }
+        }
+        Commands::Optimize => {
+            let size_of = |conn: &Connection| {
+                get_db_stats(conn)
+                    .map(|stats| stats.db_file_size_bytes + stats.wal_file_size_bytes.unwrap_or(0))
+                    .map_err(|e| format!("Failed to gather database stats: {}", e))
+            };
+            let before = size_of(&conn)?;
+            optimize_db(&conn).map_err(|e| format!("Failed to optimize database: {}", e))?;
+            let after = size_of(&conn)?;
+            println!(
+                "Optimized database: {:.2} MiB -> {:.2} MiB (including WAL).",
+                before as f64 / (1024.0 * 1024.0), after as f64 / (1024.0 * 1024.0)
+            );
+        }
+        Commands::Checkpoint => {
+            let (before, after) = checkpoint_and_measure(&conn)?;
+            println!(
+                "Checkpointed WAL: {:.2} MiB -> {:.2} MiB.",
+                before as f64 / (1024.0 * 1024.0), after as f64 / (1024.0 * 1024.0)
+            );
+        }
+        Commands::EnsureIndexes => {
+            let built = ensure_indexes(&conn).map_err(|e| format!("Failed to build indexes: {}", e))?;
+            if built.is_empty() {
+                println!("All fingerprint indexes are present.");
             } else {
-                println!("--- Listed {} songs. ---", count);
+                println!("Built missing index(es): {}.", built.join(", "));
+            }
+        }
+        Commands::Selftest => {
+            let workload = SelftestWorkload::default();
+            log::info!("Selftest command received: {:?}.", workload);
+            let report = run_selftest(&fingerprinter, &mut conn, workload).map_err(|e| format!("Self-test failed: {}", e))?;
+            print_selftest_report(&report, json);
+            if !report.passed() {
+                return Err(format!(
+                    "Self-test failed: only {} of {} queries matched their song.",
+                    report.queries_matched, workload.queries
+                ));
+            }
+        }
+        Commands::ClearDb { yes } => {
+            let stats = get_db_stats(&conn)
+                .map_err(|e| format!("Failed to gather database stats: {}", e))?;
+
+            if !yes {
+                print!(
+                    "This will permanently delete {} songs and {} fingerprints. Continue? [y/N] ",
+                    stats.song_count, stats.fingerprint_count
+                );
+                io::stdout().flush().map_err(|e| format!("Failed to flush stdout: {}", e))?;
+
+                let mut answer = String::new();
+                io::stdin().lock().read_line(&mut answer)
+                    .map_err(|e| format!("Failed to read confirmation: {}", e))?;
+                if !matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+                    println!("Aborted. Database left unchanged.");
+                    return Ok(());
+                }
             }
+
+            clear_db(&mut conn).map_err(|e| format!("Failed to clear database: {}", e))?;
+            println!("Cleared database: removed {} songs and {} fingerprints.", stats.song_count, stats.fingerprint_count);
         }
     }
 
-    Ok(())
+    if bulk {
+        checkpoint_after_write(&conn)?;
+    }
+    // Closing explicitly reports a failed final checkpoint instead of ignoring it on drop.
+    conn.close().map_err(|(_, e)| format!("Failed to close database: {}", e))
 }
\ No newline at end of file
@@ -0,0 +1,500 @@
+// src/matching.rs
+//! Offset-histogram matching without database access: between two in-memory fingerprint
+//! sets, of a query against stored entries from any backend (`OffsetHistogram`, which the
+//! SQLite matcher feeds), aggregation of per-snippet matches, and sample-accurate
+//! refinement of a matched offset against the song's audio.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use crate::database::{MatchResult, SongId};
+use crate::hashing::Fingerprint;
+
+/// Best alignment of a probe against a reference recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffsetMatch {
+    /// Number of probe fingerprints that agree on `time_offset_frames`.
+    pub score: usize,
+    /// Reference frame at which the probe's frame 0 lines up.
+    pub time_offset_frames: isize,
+    /// `score` divided by the number of probe fingerprints that hit the reference at all (0..1).
+    pub confidence: f32,
+}
+
+/// Finds the reference/probe time offset most probe fingerprints agree on, the same way the
+/// database matcher does for a single song. Returns None if no hash is shared.
+pub fn match_fingerprints(reference: &[Fingerprint], probe: &[Fingerprint]) -> Option<OffsetMatch> {
+    let mut reference_by_hash: HashMap<u64, Vec<&Fingerprint>> = HashMap::new();
+    for fp in reference {
+        reference_by_hash.entry(fp.hash).or_default().push(fp);
+    }
+
+    let mut offset_histogram: HashMap<isize, usize> = HashMap::new();
+    let mut matched_probe_fps = 0;
+    for probe_fp in probe {
+        let Some(candidates) = reference_by_hash.get(&probe_fp.hash) else { continue };
+        let mut hit = false;
+        for reference_fp in candidates {
+            // Same geometry check as the database matcher: masked-hash collisions don't count.
+            if reference_fp.target_delta_frames != probe_fp.target_delta_frames {
+                continue;
+            }
+            hit = true;
+            let offset = reference_fp.anchor_time_idx as isize - probe_fp.anchor_time_idx as isize;
+            *offset_histogram.entry(offset).or_insert(0) += 1;
+        }
+        if hit {
+            matched_probe_fps += 1;
+        }
+    }
+
+    // Ties go to the earliest offset so the result doesn't depend on HashMap order.
+    let (time_offset_frames, score) = offset_histogram.into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))?;
+    log::debug!(
+        "match_fingerprints - Best offset {} with score {} ({} of {} probe fingerprints hit).",
+        time_offset_frames, score, matched_probe_fps, probe.len()
+    );
+    Some(OffsetMatch {
+        score,
+        time_offset_frames,
+        confidence: (score as f32 / matched_probe_fps.max(1) as f32).clamp(0.0, 1.0),
+    })
+}
+
+/// Anchor positions implied by two snippets may differ by this much and still count as the
+/// same alignment in `aggregate_matches`.
+pub const AGGREGATE_OFFSET_TOLERANCE_SECONDS: f32 = 0.5;
+
+/// Single verdict for several snippets of one recording, from `aggregate_matches`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateMatch {
+    pub song_id: SongId,
+    /// Snippets whose best match is this song.
+    pub votes: usize,
+    /// Number of snippets considered, including those without a match.
+    pub snippets: usize,
+    /// Of the votes, how many agree on where the recording sits in the song (all of them
+    /// when no snippet spacing was given).
+    pub consistent_votes: usize,
+    /// Sum of the scores of the votes.
+    pub total_score: usize,
+    /// `consistent_votes / snippets` times the combined confidence of those votes, where
+    /// each agreeing snippet is independent evidence: 1 - Π(1 - confidence). Several
+    /// aligned, individually weak matches thus outweigh one strong but isolated one (0..1).
+    pub confidence: f32,
+}
+
+/// Combines the best matches of successive snippets into the most likely song: the song
+/// with the most votes wins (ties go to the higher total score, then the lower ID).
+/// `results[i]` is snippet i's best match, if any. When the snippets are evenly spaced
+/// chunks of one recording, pass the start-to-start spacing as `snippet_spacing_seconds`:
+/// snippet i at song offset `o_i` then implies the recording starts at `o_i - i * spacing`,
+/// and only votes that agree on that (within `AGGREGATE_OFFSET_TOLERANCE_SECONDS` of the
+/// median) count as consistent. Returns None if no snippet matched.
+pub fn aggregate_matches(
+    results: &[Option<MatchResult>],
+    frame_duration_seconds: f32,
+    snippet_spacing_seconds: Option<f32>,
+) -> Option<AggregateMatch> {
+    let mut votes_by_song: HashMap<SongId, Vec<(usize, &MatchResult)>> = HashMap::new();
+    for (snippet_idx, result) in results.iter().enumerate() {
+        if let Some(m) = result {
+            votes_by_song.entry(m.song_id).or_default().push((snippet_idx, m));
+        }
+    }
+
+    let total_score = |votes: &[(usize, &MatchResult)]| votes.iter().map(|(_, m)| m.score).sum::<usize>();
+    let (song_id, votes) = votes_by_song.into_iter().max_by(|a, b| {
+        a.1.len().cmp(&b.1.len())
+            .then_with(|| total_score(&a.1).cmp(&total_score(&b.1)))
+            .then_with(|| b.0.cmp(&a.0))
+    })?;
+
+    let consistent: Vec<&MatchResult> = match snippet_spacing_seconds {
+        Some(spacing) => {
+            let implied_start = |&(snippet_idx, m): &(usize, &MatchResult)| {
+                m.time_offset_in_song_frames as f32 * frame_duration_seconds - snippet_idx as f32 * spacing
+            };
+            let mut starts: Vec<f32> = votes.iter().map(implied_start).collect();
+            starts.sort_by(f32::total_cmp);
+            let median = starts[starts.len() / 2];
+            votes.iter()
+                .filter(|vote| (implied_start(vote) - median).abs() <= AGGREGATE_OFFSET_TOLERANCE_SECONDS)
+                .map(|&(_, m)| m)
+                .collect()
+        }
+        None => votes.iter().map(|&(_, m)| m).collect(),
+    };
+
+    let combined = 1.0 - consistent.iter().map(|m| 1.0 - m.confidence.clamp(0.0, 1.0)).product::<f32>();
+    let confidence = consistent.len() as f32 / results.len() as f32 * combined;
+    log::debug!(
+        "aggregate_matches - Song {} has {} of {} votes ({} consistent), confidence {:.3}.",
+        song_id, votes.len(), results.len(), consistent.len(), confidence
+    );
+    Some(AggregateMatch {
+        song_id,
+        votes: votes.len(),
+        snippets: results.len(),
+        consistent_votes: consistent.len(),
+        total_score: total_score(&votes),
+        confidence,
+    })
+}
+
+/// A stored fingerprint as the matcher sees it: (hash, song ID, anchor frame, anchor-target
+/// delta in frames). The delta may be unknown (`None`) for rows written before it was stored;
+/// such rows skip the geometry check.
+pub type CandidateEntry = (u64, SongId, usize, Option<usize>);
+
+/// One (song, offset) cell of an `OffsetHistogram`.
+#[derive(Debug, Clone, Default)]
+struct OffsetBin {
+    count: usize,
+    // Sum of the hits' weights; equal to `count` without magnitude weighting.
+    weight: usize,
+    hashes: HashSet<u64>,
+    // Query frames spanned by the hits: the earliest anchor to just past the latest target.
+    query_span: Option<(usize, usize)>,
+}
+
+impl OffsetBin {
+    // Ranking key: weighted hits, then raw hit count, then distinct hashes.
+    fn rank(&self) -> (usize, usize, usize) {
+        (self.weight, self.count, self.hashes.len())
+    }
+
+    fn merge(&mut self, other: &OffsetBin) {
+        self.count += other.count;
+        self.weight += other.weight;
+        self.hashes.extend(&other.hashes);
+        self.query_span = match (self.query_span, other.query_span) {
+            (Some((start, end)), Some((other_start, other_end))) => Some((start.min(other_start), end.max(other_end))),
+            (span, other_span) => span.or(other_span),
+        };
+    }
+}
+
+/// The best offset of one song's histogram and its bin. With a tolerance, every offset is
+/// scored by the sum of the bins within `tolerance` of it and the winning window's bins are
+/// merged; ties go to the offset whose own bin ranks higher, then to the earliest.
+fn best_offset(histogram: &HashMap<isize, OffsetBin>, tolerance: usize) -> Option<(isize, Cow<'_, OffsetBin>)> {
+    if tolerance == 0 {
+        return histogram.iter()
+            .max_by(|a, b| a.1.rank().cmp(&b.1.rank()).then_with(|| b.0.cmp(a.0)))
+            .map(|(&delta, bin)| (delta, Cow::Borrowed(bin)));
+    }
+    let tolerance = tolerance as isize;
+    let window = |center: isize| (center - tolerance..=center + tolerance).filter_map(move |delta| histogram.get(&delta));
+    // Summed distinct-hash counts stand in for the window's distinct hashes while ranking.
+    let window_rank = |center: isize| {
+        window(center).fold((0, 0, 0), |(weight, count, hashes), bin| {
+            (weight + bin.weight, count + bin.count, hashes + bin.hashes.len())
+        })
+    };
+    let (&center, bin) = histogram.iter().max_by(|a, b| {
+        window_rank(*a.0).cmp(&window_rank(*b.0))
+            .then_with(|| a.1.rank().cmp(&b.1.rank()))
+            .then_with(|| b.0.cmp(a.0))
+    })?;
+    let mut merged = bin.clone();
+    for neighbor in (center - tolerance..=center + tolerance).filter(|&delta| delta != center) {
+        if let Some(neighbor_bin) = histogram.get(&neighbor) {
+            merged.merge(neighbor_bin);
+        }
+    }
+    Some((center, Cow::Owned(merged)))
+}
+
+/// Offset-histogram scoring of a query against stored fingerprints, independent of where
+/// they are stored: feed every stored entry whose hash occurs in the query to `add` (other
+/// hashes are ignored), then rank songs with `into_candidates`. `query_db_and_match_topn`
+/// is this fed from SQLite; `match_candidates` feeds it from any iterator.
+#[derive(Debug, Clone)]
+pub struct OffsetHistogram<'q> {
+    query_fingerprints: &'q [Fingerprint],
+    // Query fingerprints by hash, with their index into `query_fingerprints`.
+    query_by_hash: HashMap<u64, Vec<(usize, &'q Fingerprint)>>,
+    // Per (source database, song) and offset: the hit count and the distinct hashes behind
+    // those hits. Song IDs are only unique within one database.
+    offset_histograms: HashMap<(usize, SongId), HashMap<isize, OffsetBin>>,
+    // Which query fingerprints found at least one (geometry-consistent) stored entry; the
+    // denominator of `MatchResult::confidence`.
+    query_fp_has_hit: Vec<bool>,
+    rejected_geometry_hits: usize,
+    weight_by_magnitude: bool,
+    offset_tolerance_frames: usize,
+}
+
+impl<'q> OffsetHistogram<'q> {
+    pub fn new(query_fingerprints: &'q [Fingerprint]) -> Self {
+        let mut query_by_hash: HashMap<u64, Vec<(usize, &Fingerprint)>> = HashMap::new();
+        for (q_idx, q_fp) in query_fingerprints.iter().enumerate() {
+            query_by_hash.entry(q_fp.hash).or_default().push((q_idx, q_fp));
+        }
+        OffsetHistogram {
+            query_fingerprints,
+            query_by_hash,
+            offset_histograms: HashMap::new(),
+            query_fp_has_hit: vec![false; query_fingerprints.len()],
+            rejected_geometry_hits: 0,
+            weight_by_magnitude: false,
+            offset_tolerance_frames: 0,
+        }
+    }
+
+    /// Weights each hit by the strength of the weaker of the query and stored fingerprint
+    /// (`Fingerprint::strength`; an unknown stored strength defers to the query's) instead
+    /// of counting every hit once. Songs and offsets are then ranked by the summed weights,
+    /// while `min_score` still applies to the raw hit count. Off by default.
+    pub fn with_magnitude_weighting(mut self, weight_by_magnitude: bool) -> Self {
+        self.weight_by_magnitude = weight_by_magnitude;
+        self
+    }
+
+    /// Scores each offset together with the offsets up to `frames` away, so a match whose
+    /// hits drift across neighboring offsets (a query recorded with a slightly fast or slow
+    /// clock) is not split into several small peaks. The reported offset is the window's
+    /// center, and the score counts every hit in the window. 0 (the default) scores exact
+    /// offsets only.
+    pub fn with_offset_tolerance(mut self, frames: usize) -> Self {
+        self.offset_tolerance_frames = frames;
+        self
+    }
+
+    /// The query's distinct hashes, sorted; the only ones worth looking up.
+    pub fn distinct_hashes(&self) -> Vec<u64> {
+        let mut hashes: Vec<u64> = self.query_by_hash.keys().copied().collect();
+        hashes.sort_unstable();
+        hashes
+    }
+
+    /// Counts one stored entry towards its song's histogram, once per query fingerprint
+    /// with the same hash.
+    pub fn add(&mut self, entry: CandidateEntry) {
+        self.add_from(0, entry);
+    }
+
+    /// `add` for an entry of the `source_db`-th searched database (see `MatchResult::source_db`).
+    pub fn add_from(&mut self, source_db: usize, entry: CandidateEntry) {
+        self.add_with_strength(source_db, entry, None);
+    }
+
+    /// `add_from` for an entry whose stored `Fingerprint::strength` is known (`None` or 0
+    /// if not); only used with magnitude weighting.
+    pub fn add_with_strength(
+        &mut self,
+        source_db: usize,
+        (hash, song_id, anchor_time_idx, target_delta_frames): CandidateEntry,
+        stored_strength: Option<u8>,
+    ) {
+        let Some(matching_query_fps) = self.query_by_hash.get(&hash) else { return };
+        for &(q_idx, q_fp) in matching_query_fps {
+            // Hash hits whose stored anchor-target delta disagrees with the query's are
+            // coincidental collisions (e.g. from bit masking) and are not counted.
+            if target_delta_frames.is_some_and(|d| d != q_fp.target_delta_frames) {
+                self.rejected_geometry_hits += 1;
+                continue;
+            }
+            self.query_fp_has_hit[q_idx] = true;
+            // The weaker side bounds how reliable the hit is; unknown (0) strengths don't count.
+            let weight = if self.weight_by_magnitude {
+                [Some(q_fp.strength), stored_strength].into_iter().flatten().filter(|&s| s > 0).min().unwrap_or(1) as usize
+            } else {
+                1
+            };
+            let time_offset_delta = anchor_time_idx as isize - q_fp.anchor_time_idx as isize;
+            let bin = self.offset_histograms.entry((source_db, song_id)).or_default().entry(time_offset_delta).or_default();
+            bin.count += 1;
+            bin.weight += weight;
+            bin.hashes.insert(hash);
+            let (hit_start, hit_end) = (q_fp.anchor_time_idx, q_fp.anchor_time_idx + q_fp.target_delta_frames + 1);
+            bin.query_span = Some(match bin.query_span {
+                Some((start, end)) => (start.min(hit_start), end.max(hit_end)),
+                None => (hit_start, hit_end),
+            });
+        }
+    }
+
+    /// Up to `n` songs (best offset per song) scoring at least `min_score`, by descending
+    /// weighted score (the plain score without magnitude weighting). `frame_duration_seconds` (hop size / sample rate) converts frame positions
+    /// into the match's start/end times.
+    pub fn into_candidates(self, n: usize, min_score: usize, frame_duration_seconds: f32) -> Vec<MatchResult> {
+        if self.rejected_geometry_hits > 0 {
+            log::debug!("match_candidates - Ignored {} hash hits with mismatched anchor-target delta.", self.rejected_geometry_hits);
+        }
+        if self.offset_histograms.is_empty() {
+            log::debug!("match_candidates - No stored entry matches any query fingerprint.");
+            return Vec::new();
+        }
+
+        log::trace!("Offset Histograms (Song ID -> <Offset Delta -> Count>):");
+        for ((source_db, song_id), histogram) in &self.offset_histograms {
+            log::trace!("  Song ID {} (database {}):", song_id, source_db);
+            if histogram.is_empty() { log::trace!("    (No matching offsets for this song)"); continue; }
+            let mut sorted_histogram: Vec<_> = histogram.iter().collect();
+            sorted_histogram.sort_by(|a, b| b.1.rank().cmp(&a.1.rank()).then_with(|| a.0.cmp(b.0)));
+            log::trace!("    Top {} matching offsets:", sorted_histogram.len().min(5));
+            for (delta, bin) in sorted_histogram.iter().take(5) {
+                log::trace!("      Delta: {: >4}, Count: {}, Distinct hashes: {}", delta, bin.count, bin.hashes.len());
+            }
+            if sorted_histogram.len() > 5 { log::trace!("      ... and {} more.", sorted_histogram.len() - 5); }
+        }
+
+        let matched_query_fps = self.query_fp_has_hit.iter().filter(|&&hit| hit).count();
+        let query_coverage = matched_query_fps as f32 / self.query_fingerprints.len().max(1) as f32;
+        log::debug!(
+            "match_candidates - {} of {} query fingerprints hit a stored entry.",
+            matched_query_fps, self.query_fingerprints.len()
+        );
+
+        let mut candidates: Vec<MatchResult> = Vec::with_capacity(self.offset_histograms.len());
+        for (&(source_db, song_id), histogram) in &self.offset_histograms {
+            // A repeated loop in both song and query can pile many hits of the same few hashes
+            // onto one offset; among equal (weighted) counts, the offset backed by more distinct
+            // hashes wins.
+            // Remaining ties go to the earliest offset so the result doesn't depend on HashMap order.
+            if let Some((best_delta_for_song, bin)) = best_offset(histogram, self.offset_tolerance_frames) {
+                let score_for_song = bin.count;
+                log::debug!(
+                    "match_candidates - For Song ID {}: Best offset_delta {} has score {} (weighted {}) from {} distinct hashes.",
+                    song_id, best_delta_for_song, score_for_song, bin.weight, bin.hashes.len()
+                );
+                // Every bin holds at least one hit, so it has a span.
+                let (query_start, query_end) = bin.query_span.unwrap_or_default();
+                let to_seconds = |frame: isize| frame.max(0) as f32 * frame_duration_seconds;
+                candidates.push(MatchResult {
+                    song_id,
+                    source_db,
+                    score: score_for_song,
+                    weighted_score: bin.weight,
+                    distinct_hash_score: bin.hashes.len(),
+                    time_offset_in_song_frames: best_delta_for_song,
+                    confidence: (score_for_song as f32 / matched_query_fps.max(1) as f32).clamp(0.0, 1.0),
+                    query_coverage,
+                    match_start_seconds: to_seconds(best_delta_for_song + query_start as isize),
+                    match_end_seconds: to_seconds(best_delta_for_song + query_end as isize),
+                    query_match_start_seconds: to_seconds(query_start as isize),
+                    query_match_end_seconds: to_seconds(query_end as isize),
+                });
+            }
+        }
+
+        // Highest (weighted) score first, then most distinct hashes; break remaining ties by
+        // song ID so the ordering is stable across runs.
+        candidates.sort_by(|a, b| {
+            b.weighted_score.cmp(&a.weighted_score)
+                .then_with(|| b.score.cmp(&a.score))
+                .then_with(|| b.distinct_hash_score.cmp(&a.distinct_hash_score))
+                .then_with(|| a.song_id.cmp(&b.song_id))
+                .then_with(|| a.source_db.cmp(&b.source_db))
+        });
+
+        let total_candidates = candidates.len();
+        candidates.retain(|c| {
+            if c.score < min_score {
+                log::debug!("match_candidates - Match score {} for Song ID {} is below threshold {}. Discarding.", c.score, c.song_id, min_score);
+                return false;
+            }
+            true
+        });
+        candidates.truncate(n);
+
+        if let Some(best) = candidates.first() {
+            log::debug!("match_candidates - Found best overall match: {:?}", best);
+            log::debug!("match_candidates - Returning {} of {} candidate songs.", candidates.len(), total_candidates);
+        } else {
+            log::debug!("match_candidates - No suitable match found after analyzing histograms.");
+        }
+        candidates
+    }
+}
+
+/// Ranks the songs in `candidates` (stored entries from any backend; entries whose hash is
+/// not in the query are ignored) by offset-histogram score, like `query_db_and_match_topn`
+/// without the database.
+pub fn match_candidates_topn(
+    query_fingerprints: &[Fingerprint],
+    candidates: impl IntoIterator<Item = CandidateEntry>,
+    n: usize,
+    min_score: usize,
+    frame_duration_seconds: f32,
+) -> Vec<MatchResult> {
+    let mut histogram = OffsetHistogram::new(query_fingerprints);
+    for entry in candidates {
+        histogram.add(entry);
+    }
+    histogram.into_candidates(n, min_score, frame_duration_seconds)
+}
+
+/// The best song in `candidates` scoring at least `min_score`; see `match_candidates_topn`.
+pub fn match_candidates(
+    query_fingerprints: &[Fingerprint],
+    candidates: impl IntoIterator<Item = CandidateEntry>,
+    min_score: usize,
+    frame_duration_seconds: f32,
+) -> Option<MatchResult> {
+    match_candidates_topn(query_fingerprints, candidates, 1, min_score, frame_duration_seconds).into_iter().next()
+}
+
+/// Sample-accurate alignment from `refine_offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RefinedOffset {
+    /// Song sample at which the query's first sample lines up.
+    pub offset_samples: isize,
+    /// Normalized cross-correlation of query and song at that offset (-1..1). Close to 1
+    /// means the waveforms really line up; low values mean the refinement is a guess.
+    pub correlation: f32,
+}
+
+/// Refines a frame-level offset (e.g. `time_offset_in_song_frames * hop_size`) to the
+/// sample: cross-correlates `query` with the song at every offset within
+/// `search_radius_samples` of `coarse_offset_samples` and returns the best. Both must be at
+/// the same sample rate; a few seconds of query are plenty. Offsets where the query would
+/// run past either end of the song are skipped; None if none remain or the query is silent.
+pub fn refine_offset(
+    song: &[f32],
+    query: &[f32],
+    coarse_offset_samples: isize,
+    search_radius_samples: usize,
+) -> Option<RefinedOffset> {
+    if query.is_empty() || query.len() > song.len() {
+        return None;
+    }
+    let query_energy: f64 = query.iter().map(|&q| q as f64 * q as f64).sum();
+    if query_energy == 0.0 {
+        return None;
+    }
+    // Prefix sums of the song's squared samples give each window's energy in O(1).
+    let mut song_energy_prefix = Vec::with_capacity(song.len() + 1);
+    song_energy_prefix.push(0.0f64);
+    for &s in song {
+        song_energy_prefix.push(song_energy_prefix.last().unwrap() + s as f64 * s as f64);
+    }
+
+    let last_start = (song.len() - query.len()) as isize;
+    let radius = search_radius_samples as isize;
+    let first = (coarse_offset_samples - radius).max(0);
+    let last = (coarse_offset_samples + radius).min(last_start);
+    let mut best: Option<RefinedOffset> = None;
+    for offset in first..=last {
+        let start = offset as usize;
+        let window = &song[start..start + query.len()];
+        let window_energy = song_energy_prefix[start + query.len()] - song_energy_prefix[start];
+        if window_energy <= 0.0 {
+            continue;
+        }
+        let dot: f64 = query.iter().zip(window).map(|(&q, &s)| q as f64 * s as f64).sum();
+        let correlation = (dot / (query_energy * window_energy).sqrt()) as f32;
+        // Ties keep the earliest offset.
+        if best.is_none_or(|b| correlation > b.correlation) {
+            best = Some(RefinedOffset { offset_samples: offset, correlation });
+        }
+    }
+    log::debug!(
+        "refine_offset - Coarse offset {} samples refined to {:?} (searched {}..={}).",
+        coarse_offset_samples, best, first, last
+    );
+    best
+}
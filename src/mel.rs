@@ -0,0 +1,78 @@
+// src/mel.rs
+//! Mel-scale filterbank for reducing FFT frames to a few perceptually spaced bands
+//! (requires the `mel` feature).
+//!
+//! Mel bands are narrow at low frequencies and wide at high ones, which suits speech:
+//! formants land in distinct bands and quiet, noisy high frequencies are pooled instead of
+//! each bin competing for peaks. For music the trade-off usually goes the other way. With
+//! only tens of bands there are far fewer distinct (f1, f2) pairs, so hashes collide more
+//! often and matches need longer snippets; keep the linear spectrogram for music libraries.
+
+/// Converts a frequency in Hz to mels (HTK formula).
+pub fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+/// Converts mels back to Hz (inverse of `hz_to_mel`).
+pub fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+/// Triangular filters spaced evenly on the mel scale between 0 Hz and Nyquist, each
+/// stored as (first FFT bin, weights).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MelFilterbank {
+    filters: Vec<(usize, Vec<f32>)>,
+    num_fft_bins: usize,
+}
+
+impl MelFilterbank {
+    /// Builds `num_bands` filters for frames of `window_size / 2 + 1` bins at `sample_rate`.
+    pub fn new(sample_rate: u32, window_size: usize, num_bands: usize) -> Self {
+        let num_fft_bins = window_size / 2 + 1;
+        let bin_hz = sample_rate as f32 / window_size.max(1) as f32;
+        let max_mel = hz_to_mel(sample_rate as f32 / 2.0);
+        // num_bands + 2 edges: each filter rises from edge i to i+1 and falls to i+2.
+        let edges_hz: Vec<f32> = (0..num_bands + 2)
+            .map(|i| mel_to_hz(max_mel * i as f32 / (num_bands + 1) as f32))
+            .collect();
+
+        let filters = edges_hz
+            .windows(3)
+            .map(|edge| {
+                let (lower, center, upper) = (edge[0], edge[1], edge[2]);
+                let first_bin = (lower / bin_hz).ceil() as usize;
+                let last_bin = ((upper / bin_hz).floor() as usize).min(num_fft_bins - 1);
+                let weights = (first_bin..=last_bin)
+                    .map(|bin| {
+                        let hz = bin as f32 * bin_hz;
+                        if hz <= center {
+                            (hz - lower) / (center - lower).max(f32::EPSILON)
+                        } else {
+                            (upper - hz) / (upper - center).max(f32::EPSILON)
+                        }
+                    })
+                    .map(|w| w.max(0.0))
+                    .collect();
+                (first_bin, weights)
+            })
+            .collect();
+        MelFilterbank { filters, num_fft_bins }
+    }
+
+    pub fn num_bands(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Weighted sum of `frame` (one FFT frame) under each filter. Narrow low-frequency
+    /// filters may cover no bin at all for small windows and then always yield 0.
+    pub fn apply(&self, frame: &[f32]) -> Vec<f32> {
+        debug_assert_eq!(frame.len(), self.num_fft_bins);
+        self.filters
+            .iter()
+            .map(|(first_bin, weights)| {
+                weights.iter().enumerate().map(|(i, w)| w * frame.get(first_bin + i).copied().unwrap_or(0.0)).sum()
+            })
+            .collect()
+    }
+}
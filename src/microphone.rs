@@ -0,0 +1,68 @@
+// src/microphone.rs
+//! Live capture from the default input device (requires the `microphone` feature).
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::audio_loader::{downmix_interleaved, resample_mono, ChannelMode, ResampleQuality};
+use crate::error::SivanaError;
+
+/// Records `seconds` of audio from the default input device and returns it as mono
+/// samples at `target_sample_rate` (resampled if the device runs at another rate).
+pub fn record_mono(seconds: u64, target_sample_rate: u32, quality: ResampleQuality) -> Result<Vec<f32>, SivanaError> {
+    let host = cpal::default_host();
+    let device = host.default_input_device()
+        .ok_or_else(|| SivanaError::AudioDevice("No default audio input device available.".to_string()))?;
+    let supported_config = device.default_input_config()
+        .map_err(|e| SivanaError::AudioDevice(format!("Failed to query input device configuration: {}", e)))?;
+    let device_rate = supported_config.sample_rate().0;
+    let channels = supported_config.channels() as usize;
+    log::info!(
+        "Recording {} s from '{}' ({} Hz, {} channels, {:?}).",
+        seconds, device.name().unwrap_or_else(|_| "unknown device".to_string()),
+        device_rate, channels, supported_config.sample_format()
+    );
+
+    // Interleaved samples as delivered by the device callback.
+    let captured: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
+    let config = supported_config.config();
+    let stream = match supported_config.sample_format() {
+        cpal::SampleFormat::F32 => build_capture_stream::<f32>(&device, &config, Arc::clone(&captured)),
+        cpal::SampleFormat::I16 => build_capture_stream::<i16>(&device, &config, Arc::clone(&captured)),
+        cpal::SampleFormat::U16 => build_capture_stream::<u16>(&device, &config, Arc::clone(&captured)),
+        cpal::SampleFormat::I32 => build_capture_stream::<i32>(&device, &config, Arc::clone(&captured)),
+        other => return Err(SivanaError::AudioDevice(format!("Unsupported input sample format: {:?}", other))),
+    }?;
+
+    stream.play().map_err(|e| SivanaError::AudioDevice(format!("Failed to start recording: {}", e)))?;
+    std::thread::sleep(Duration::from_secs(seconds));
+    drop(stream);
+
+    let interleaved = std::mem::take(&mut *captured.lock().map_err(|_| SivanaError::AudioDevice("Capture buffer was poisoned.".to_string()))?);
+    log::info!("Captured {} interleaved samples.", interleaved.len());
+    let mono = downmix_interleaved(&interleaved, channels, ChannelMode::Average)?;
+    resample_mono(mono, device_rate, target_sample_rate, quality)
+}
+
+fn build_capture_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    captured: Arc<Mutex<Vec<f32>>>,
+) -> Result<cpal::Stream, SivanaError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            if let Ok(mut buffer) = captured.lock() {
+                buffer.extend(data.iter().map(|&s| s.to_sample::<f32>()));
+            }
+        },
+        |err| log::error!("Input stream error: {}", err),
+        None,
+    ).map_err(|e| SivanaError::AudioDevice(format!("Failed to open input stream: {}", e)))
+}
@@ -1,76 +1,490 @@
 // src/peaks.rs
+#[cfg(feature = "ndarray")]
+use ndarray::ArrayView2;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Peak { // Made public
     pub time_idx: usize,     // Fields also public
     pub freq_bin_idx: usize,
+    /// Spectrogram value at the peak, in the spectrogram's scale (linear, power or dB).
+    pub magnitude: f32,
+}
+
+/// Default for `PeakParams::min_frame_energy`: well below any audible content, so only
+/// digital silence and dither-level noise are skipped.
+pub const DEFAULT_MIN_FRAME_ENERGY: f32 = 1e-3;
+
+/// How `find_peaks` picks the constellation: a cell is a peak if it reaches `min_magnitude`
+/// and no cell within `time_radius` frames and `freq_radius` bins is louder. When
+/// `max_peaks_per_frame` is set, only the strongest N peaks of each frame are kept, bounding
+/// the fingerprint count. Frames whose energy (sum of squared values) is below
+/// `min_frame_energy` yield no peaks, so near-silent passages don't produce spurious maxima
+/// out of noise or ties; the check is on the values as given, so it is only meaningful for
+/// linear or power spectrograms (0.0 disables it).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakParams {
+    pub time_radius: usize,
+    pub freq_radius: usize,
+    /// In the spectrogram's scale (linear, power or dB).
+    pub min_magnitude: f32,
+    pub max_peaks_per_frame: Option<usize>,
+    pub min_frame_energy: f32,
+}
+
+impl Default for PeakParams {
+    fn default() -> Self {
+        PeakParams {
+            time_radius: 2,
+            freq_radius: 5,
+            min_magnitude: 2.0,
+            max_peaks_per_frame: None,
+            min_frame_energy: DEFAULT_MIN_FRAME_ENERGY,
+        }
+    }
+}
+
+/// Minimum magnitude a spectrogram cell needs to become a peak in `find_peaks_with_threshold`.
+/// A plain `f32` converts to `Absolute`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    /// A fixed value in the spectrogram's scale (linear, power or dB).
+    Absolute(f32),
+    /// A percentile (0-100) of all values in the spectrogram being searched, e.g. 95 keeps
+    /// only cells louder than 95% of it. Scales with the recording's level, so the same
+    /// setting works for quiet and loud material.
+    Percentile(f32),
+}
+
+impl From<f32> for Threshold {
+    fn from(value: f32) -> Self {
+        Threshold::Absolute(value)
+    }
+}
+
+impl Threshold {
+    /// The absolute threshold for `spectrogram`. Percentiles use the nearest-rank value over
+    /// every frame with the first frame's bin count (out-of-range percentiles are clamped);
+    /// an empty spectrogram gives 0.
+    pub fn resolve(&self, spectrogram: &[Vec<f32>]) -> f32 {
+        self.resolve_frames(spectrogram)
+    }
+
+    fn resolve_frames<F: AsRef<[f32]>>(&self, spectrogram: &[F]) -> f32 {
+        let percentile = match *self {
+            Threshold::Absolute(value) => return value,
+            Threshold::Percentile(percentile) => percentile,
+        };
+        let Some(num_freq_bins) = spectrogram.first().map(|frame| frame.as_ref().len()) else { return 0.0 };
+        let mut values: Vec<f32> = spectrogram.iter()
+            .map(AsRef::as_ref)
+            .filter(|frame| frame.len() == num_freq_bins)
+            .flatten()
+            .copied()
+            .collect();
+        if values.is_empty() {
+            return 0.0;
+        }
+        let fraction = if percentile.is_nan() { 0.0 } else { percentile.clamp(0.0, 100.0) / 100.0 };
+        let rank = ((fraction * values.len() as f32).ceil() as usize).clamp(1, values.len()) - 1;
+        *values.select_nth_unstable_by(rank, f32::total_cmp).1
+    }
+}
+
+/// Finds local maxima in the spectrogram as described by `params`.
+/// Peaks are returned sorted by `time_idx`, then `freq_bin_idx`. `create_hashes` pairs
+/// peaks in index order, so this canonical ordering is what makes fingerprints reproducible.
+pub fn find_peaks(spectrogram: &[Vec<f32>], params: PeakParams) -> Vec<Peak> { // Made public
+    find_peaks_in_frames(spectrogram, params, Threshold::Absolute(params.min_magnitude))
 }
 
-pub fn find_peaks( // Made public
-                   spectrogram: &[Vec<f32>],
-                   neighborhood_time_radius: usize,
-                   neighborhood_freq_radius: usize,
-                   min_magnitude_threshold: f32,
+/// `find_peaks` with `min_magnitude_threshold` in place of `params.min_magnitude`: an
+/// absolute value (a plain `f32`) or a `Threshold::Percentile` of this spectrogram's values.
+pub fn find_peaks_with_threshold(
+    spectrogram: &[Vec<f32>],
+    params: PeakParams,
+    min_magnitude_threshold: impl Into<Threshold>,
 ) -> Vec<Peak> {
+    find_peaks_in_frames(spectrogram, params, min_magnitude_threshold.into())
+}
+
+/// `find_peaks` on a frames x bins array (row `t` is frame `t`), e.g. from
+/// `SpectrogramBuilder::build_array`. Returns the same peaks as `find_peaks` on the same
+/// values; arrays that are not in row-major layout are copied into it first.
+#[cfg(feature = "ndarray")]
+pub fn find_peaks_array(spectrogram: ArrayView2<'_, f32>, params: PeakParams) -> Vec<Peak> {
+    let num_freq_bins = spectrogram.ncols();
+    let contiguous = spectrogram.as_standard_layout();
+    let values = contiguous.as_slice().expect("standard layout is contiguous");
+    // Every row is a slice of the one allocation, so the neighborhood scan stays cache-friendly.
+    let frames: Vec<&[f32]> = if num_freq_bins == 0 { Vec::new() } else { values.chunks_exact(num_freq_bins).collect() };
+    find_peaks_in_frames(&frames, params, Threshold::Absolute(params.min_magnitude))
+}
+
+fn find_peaks_in_frames<F: AsRef<[f32]>>(spectrogram: &[F], params: PeakParams, threshold: Threshold) -> Vec<Peak> {
     let mut peaks: Vec<Peak> = Vec::new();
 
-    if spectrogram.is_empty() || spectrogram.first().map_or(true, |frame| frame.is_empty()) {
-        println!("Debug: find_peaks - Spectrogram is empty or first frame is empty.");
+    if spectrogram.is_empty() || spectrogram.first().is_none_or(|frame| frame.as_ref().is_empty()) {
+        log::debug!("find_peaks - Spectrogram is empty or first frame is empty.");
         return peaks;
     }
 
     let num_frames = spectrogram.len();
-    let num_freq_bins = spectrogram[0].len();
+    let num_freq_bins = spectrogram[0].as_ref().len();
+    warn_about_ragged_frames(spectrogram, "find_peaks");
+    let min_magnitude_threshold = threshold.resolve_frames(spectrogram);
+    if let Threshold::Percentile(percentile) = threshold {
+        log::debug!("find_peaks - Percentile {} of the spectrogram is {}.", percentile, min_magnitude_threshold);
+    }
 
-    println!(
-        "Debug: find_peaks - Spectrogram: {} frames, {} freq bins.",
+    log::debug!(
+        "find_peaks - Spectrogram: {} frames, {} freq bins.",
         num_frames, num_freq_bins
     );
-    println!(
-        "Debug: find_peaks - Neighborhood: TimeRadius={}, FreqRadius={}, MinMag={}",
-        neighborhood_time_radius, neighborhood_freq_radius, min_magnitude_threshold
+    log::debug!(
+        "find_peaks - Neighborhood: TimeRadius={}, FreqRadius={}, MinMag={}, MaxPerFrame={:?}, MinFrameEnergy={}",
+        params.time_radius, params.freq_radius, min_magnitude_threshold, params.max_peaks_per_frame, params.min_frame_energy
     );
 
+    // Local maxima of the current frame as (freq_bin_idx, magnitude)
+    let mut frame_candidates: Vec<(usize, f32)> = Vec::new();
+
     for t_idx in 0..num_frames {
-        for f_idx in 0..num_freq_bins {
-            let current_magnitude = spectrogram[t_idx][f_idx];
+        if spectrogram[t_idx].as_ref().len() != num_freq_bins {
+            continue;
+        }
+        frame_local_maxima(
+            spectrogram, t_idx,
+            params.time_radius, params.freq_radius, |_| min_magnitude_threshold, params.max_peaks_per_frame, params.min_frame_energy,
+            &mut frame_candidates,
+        );
+        peaks.extend(frame_candidates.iter().map(|&(f_idx, magnitude)| Peak {
+            time_idx: t_idx,
+            freq_bin_idx: f_idx,
+            magnitude,
+        }));
+    }
 
-            if current_magnitude < min_magnitude_threshold {
-                continue;
-            }
+    // The scan above already yields this order; sorting enforces it regardless of how the scan is done.
+    peaks.sort_by_key(|p| (p.time_idx, p.freq_bin_idx));
+    log::debug!("find_peaks - Found {} peaks.", peaks.len());
+    peaks
+}
+
+/// Warns if any frame's bin count differs from the first frame's. The peak finders skip
+/// such ragged frames (they yield no peaks) instead of indexing past their end; neighbors
+/// are only read where they exist. `SpectrogramBuilder` never produces ragged frames.
+fn warn_about_ragged_frames<F: AsRef<[f32]>>(spectrogram: &[F], caller: &str) {
+    let Some(num_freq_bins) = spectrogram.first().map(|frame| frame.as_ref().len()) else { return };
+    let ragged = spectrogram.iter().filter(|frame| frame.as_ref().len() != num_freq_bins).count();
+    if ragged > 0 {
+        log::warn!(
+            "{} - Skipping {} of {} spectrogram frames whose bin count differs from the first frame's {}.",
+            caller, ragged, spectrogram.len(), num_freq_bins
+        );
+    }
+}
+
+/// Collects the local maxima of `spectrogram[t_idx]` into `frame_candidates` as
+/// (freq_bin_idx, magnitude), sorted by bin and capped at `max_peaks_per_frame`; nothing
+/// if the frame's energy is below `min_frame_energy`. `min_magnitude_threshold` gives the
+/// threshold for each frequency bin.
+/// Only the frames within `neighborhood_time_radius` of `t_idx` are read, which is what
+/// lets `StreamingPeakFinder` work on a short buffer of recent frames.
+#[allow(clippy::too_many_arguments)]
+fn frame_local_maxima<F: AsRef<[f32]>>(
+    spectrogram: &[F],
+    t_idx: usize,
+    neighborhood_time_radius: usize,
+    neighborhood_freq_radius: usize,
+    min_magnitude_threshold: impl Fn(usize) -> f32,
+    max_peaks_per_frame: Option<usize>,
+    min_frame_energy: f32,
+    frame_candidates: &mut Vec<(usize, f32)>,
+) {
+    let num_frames = spectrogram.len();
+    let frame = spectrogram[t_idx].as_ref();
+    let num_freq_bins = frame.len();
+    frame_candidates.clear();
+    if min_frame_energy > 0.0 {
+        let frame_energy: f32 = frame.iter().map(|&v| v * v).sum();
+        if frame_energy < min_frame_energy {
+            return;
+        }
+    }
+    for (f_idx, &current_magnitude) in frame.iter().enumerate() {
+
+        if current_magnitude < min_magnitude_threshold(f_idx) {
+            continue;
+        }
+
+        let mut is_local_max = true;
+        let t_start = t_idx.saturating_sub(neighborhood_time_radius);
+        let t_end = (t_idx + neighborhood_time_radius + 1).min(num_frames);
+        let f_start = f_idx.saturating_sub(neighborhood_freq_radius);
+        let f_end = (f_idx + neighborhood_freq_radius + 1).min(num_freq_bins);
 
-            let mut is_local_max = true;
-            let t_start = t_idx.saturating_sub(neighborhood_time_radius);
-            let t_end = (t_idx + neighborhood_time_radius + 1).min(num_frames);
-            let f_start = f_idx.saturating_sub(neighborhood_freq_radius);
-            let f_end = (f_idx + neighborhood_freq_radius + 1).min(num_freq_bins);
-
-            for nt_idx in t_start..t_end {
-                for nf_idx in f_start..f_end {
-                    if nt_idx == t_idx && nf_idx == f_idx {
-                        continue;
-                    }
-                    if spectrogram[nt_idx][nf_idx] > current_magnitude {
-                        is_local_max = false;
-                        break;
-                    }
-                    if spectrogram[nt_idx][nf_idx] == current_magnitude && (nt_idx < t_idx || (nt_idx == t_idx && nf_idx < f_idx)) {
-                        is_local_max = false;
-                        break;
-                    }
+        for (nt_idx, neighbor_frame) in spectrogram.iter().enumerate().take(t_end).skip(t_start) {
+            for (nf_idx, &neighbor_magnitude) in neighbor_frame.as_ref().iter().enumerate().take(f_end).skip(f_start) {
+                if nt_idx == t_idx && nf_idx == f_idx {
+                    continue;
+                }
+                if neighbor_magnitude > current_magnitude {
+                    is_local_max = false;
+                    break;
                 }
-                if !is_local_max {
+                // Equal magnitudes only tie-break between directly adjacent cells (including
+                // diagonals), so a plateau collapses to its first cell while separate peaks
+                // that merely share a value (common on quantized or clipped audio) all survive.
+                if neighbor_magnitude == current_magnitude
+                    && nt_idx.abs_diff(t_idx) <= 1
+                    && nf_idx.abs_diff(f_idx) <= 1
+                    && (nt_idx < t_idx || (nt_idx == t_idx && nf_idx < f_idx))
+                {
+                    is_local_max = false;
                     break;
                 }
             }
+            if !is_local_max {
+                break;
+            }
+        }
+
+        if is_local_max {
+            frame_candidates.push((f_idx, current_magnitude));
+        }
+    }
+
+    if let Some(max_peaks) = max_peaks_per_frame
+        && frame_candidates.len() > max_peaks
+    {
+        frame_candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        frame_candidates.truncate(max_peaks);
+        frame_candidates.sort_by_key(|&(f_idx, _)| f_idx);
+    }
+}
+
+/// `find_peaks` for spectrogram frames that arrive one at a time. A frame's peaks are
+/// emitted once the `time_radius` frames after it are known (or on `finish`),
+/// and only that many frames are kept in memory; the output matches `find_peaks` with the
+/// same absolute threshold (a `Threshold::Percentile` needs the whole spectrogram up front).
+#[derive(Debug, Clone)]
+pub struct StreamingPeakFinder {
+    params: PeakParams,
+    // Recent frames; frames[0] is frame number `first_frame_idx`.
+    frames: Vec<Vec<f32>>,
+    // Bin count of the first frame pushed; later frames of another width yield no peaks.
+    num_freq_bins: Option<usize>,
+    first_frame_idx: usize,
+    // Next frame whose peaks have not been emitted yet.
+    next_frame_idx: usize,
+    frame_candidates: Vec<(usize, f32)>,
+}
+
+impl StreamingPeakFinder {
+    pub fn new(params: PeakParams) -> Self {
+        StreamingPeakFinder {
+            params,
+            frames: Vec::with_capacity(2 * params.time_radius + 2),
+            num_freq_bins: None,
+            first_frame_idx: 0,
+            next_frame_idx: 0,
+            frame_candidates: Vec::new(),
+        }
+    }
+
+    /// Adds the next frame and returns the peaks of every frame that is now settled.
+    pub fn push(&mut self, frame: Vec<f32>) -> Vec<Peak> {
+        let num_freq_bins = *self.num_freq_bins.get_or_insert(frame.len());
+        if frame.len() != num_freq_bins {
+            log::warn!(
+                "StreamingPeakFinder - Skipping frame {} with {} bins instead of {}.",
+                self.first_frame_idx + self.frames.len(), frame.len(), num_freq_bins
+            );
+        }
+        self.frames.push(frame);
+        let mut peaks = Vec::new();
+        while self.next_frame_idx + self.params.time_radius < self.first_frame_idx + self.frames.len() {
+            self.emit_next_frame(&mut peaks);
+        }
+        // Frames older than the radius behind the next frame to emit are no longer read.
+        let keep_from = self.next_frame_idx.saturating_sub(self.params.time_radius);
+        if keep_from > self.first_frame_idx {
+            self.frames.drain(..keep_from - self.first_frame_idx);
+            self.first_frame_idx = keep_from;
+        }
+        peaks
+    }
+
+    /// Returns the peaks of the frames still held back at the end of the stream.
+    pub fn finish(mut self) -> Vec<Peak> {
+        let mut peaks = Vec::new();
+        while self.next_frame_idx < self.first_frame_idx + self.frames.len() {
+            self.emit_next_frame(&mut peaks);
+        }
+        peaks
+    }
+
+    fn emit_next_frame(&mut self, peaks: &mut Vec<Peak>) {
+        let t_idx = self.next_frame_idx;
+        let frame_len = self.frames[t_idx - self.first_frame_idx].len();
+        if self.num_freq_bins.is_some_and(|bins| bins > 0 && bins == frame_len) {
+            frame_local_maxima(
+                &self.frames, t_idx - self.first_frame_idx,
+                self.params.time_radius, self.params.freq_radius,
+                |_| self.params.min_magnitude, self.params.max_peaks_per_frame, self.params.min_frame_energy,
+                &mut self.frame_candidates,
+            );
+            peaks.extend(self.frame_candidates.iter().map(|&(f_idx, magnitude)| Peak {
+                time_idx: t_idx,
+                freq_bin_idx: f_idx,
+                magnitude,
+            }));
+        }
+        self.next_frame_idx += 1;
+    }
+}
+
+// Number of logarithmic frequency bands find_peaks_adaptive computes thresholds for.
+const ADAPTIVE_THRESHOLD_BANDS: usize = 24;
+
+/// `find_peaks` with a per-band threshold instead of a global one: bins are grouped into
+/// logarithmic frequency bands, and a bin must reach `mean + k * std` of all magnitudes of
+/// its band across the whole spectrogram. Each band self-calibrates, so loud bass doesn't
+/// set the bar for quiet high frequencies (or the other way round) and no absolute threshold
+/// has to be tuned per mastering style. Typical `k` is 0.5 to 2; the DC bin uses the lowest
+/// band's threshold. No per-frame cap or energy gate is applied.
+pub fn find_peaks_adaptive(
+    spectrogram: &[Vec<f32>],
+    neighborhood_time_radius: usize,
+    neighborhood_freq_radius: usize,
+    k: f32,
+) -> Vec<Peak> {
+    let mut peaks: Vec<Peak> = Vec::new();
+
+    if spectrogram.is_empty() || spectrogram.first().is_none_or(|frame| frame.is_empty()) {
+        log::debug!("find_peaks_adaptive - Spectrogram is empty or first frame is empty.");
+        return peaks;
+    }
+
+    let num_freq_bins = spectrogram[0].len();
+    warn_about_ragged_frames(spectrogram, "find_peaks_adaptive");
+    let edges = log_band_edges(num_freq_bins, ADAPTIVE_THRESHOLD_BANDS);
+    let mut bin_thresholds = vec![f32::INFINITY; num_freq_bins];
+    for band in edges.windows(2) {
+        let (mut sum, mut sum_sq, mut count) = (0.0f64, 0.0f64, 0usize);
+        for frame in spectrogram.iter().filter(|frame| frame.len() == num_freq_bins) {
+            for &magnitude in &frame[band[0]..band[1]] {
+                sum += magnitude as f64;
+                sum_sq += (magnitude as f64).powi(2);
+                count += 1;
+            }
+        }
+        let mean = sum / count as f64;
+        let std = (sum_sq / count as f64 - mean * mean).max(0.0).sqrt();
+        let threshold = (mean + k as f64 * std) as f32;
+        let first_bin = if band[0] == edges[0] { 0 } else { band[0] };
+        bin_thresholds[first_bin..band[1]].fill(threshold);
+    }
+    log::debug!(
+        "find_peaks_adaptive - {} frames, {} freq bins, k={}, band edges: {:?}",
+        spectrogram.len(), num_freq_bins, k, edges
+    );
 
-            if is_local_max {
-                peaks.push(Peak {
-                    time_idx: t_idx,
-                    freq_bin_idx: f_idx,
-                });
+    let mut frame_candidates: Vec<(usize, f32)> = Vec::new();
+    for t_idx in 0..spectrogram.len() {
+        if spectrogram[t_idx].len() != num_freq_bins {
+            continue;
+        }
+        frame_local_maxima(
+            spectrogram, t_idx,
+            neighborhood_time_radius, neighborhood_freq_radius, |f_idx| bin_thresholds[f_idx], None, 0.0,
+            &mut frame_candidates,
+        );
+        peaks.extend(frame_candidates.iter().map(|&(f_idx, magnitude)| Peak {
+            time_idx: t_idx,
+            freq_bin_idx: f_idx,
+            magnitude,
+        }));
+    }
+
+    log::debug!("find_peaks_adaptive - Found {} peaks.", peaks.len());
+    peaks
+}
+
+// Smoothing factor for the per-band running amplitude threshold used by find_peaks_banded.
+const BANDED_THRESHOLD_SMOOTHING: f32 = 0.1;
+
+/// Splits bins `1..num_freq_bins` (DC excluded) into `num_bands` logarithmically spaced bands.
+/// Returns `num_bands + 1` strictly increasing edges (fewer if there aren't enough bins).
+fn log_band_edges(num_freq_bins: usize, num_bands: usize) -> Vec<usize> {
+    let mut edges = vec![1usize];
+    let max_bin = num_freq_bins as f32;
+    for band in 1..=num_bands {
+        let edge = max_bin.powf(band as f32 / num_bands as f32).round() as usize;
+        let prev = *edges.last().unwrap();
+        let edge = edge.max(prev + 1).min(num_freq_bins);
+        if edge <= prev {
+            break;
+        }
+        edges.push(edge);
+    }
+    edges
+}
+
+/// Alternative peak picker in the style of the original Shazam paper: each frame is split
+/// into `num_bands` logarithmic frequency bands and the `keep_per_band` strongest bins of
+/// each band are kept, provided they reach that band's running amplitude threshold (an
+/// exponential moving average of the band's strongest magnitude over previous frames).
+/// This gives a more even constellation than `find_peaks` on bass-heavy material.
+pub fn find_peaks_banded(
+    spectrogram: &[Vec<f32>],
+    num_bands: usize,
+    keep_per_band: usize,
+) -> Vec<Peak> {
+    let mut peaks: Vec<Peak> = Vec::new();
+
+    if spectrogram.is_empty() || spectrogram.first().is_none_or(|frame| frame.len() < 2) || num_bands == 0 || keep_per_band == 0 {
+        log::debug!("find_peaks_banded - Spectrogram is empty or band parameters are zero.");
+        return peaks;
+    }
+
+    let num_freq_bins = spectrogram[0].len();
+    warn_about_ragged_frames(spectrogram, "find_peaks_banded");
+    let edges = log_band_edges(num_freq_bins, num_bands);
+    log::debug!(
+        "find_peaks_banded - Spectrogram: {} frames, {} freq bins, band edges: {:?}, keep_per_band={}",
+        spectrogram.len(), num_freq_bins, edges, keep_per_band
+    );
+
+    // Seeded from the first frame so the threshold works for both linear and dB spectrograms.
+    let mut running_thresholds: Vec<Option<f32>> = vec![None; edges.len() - 1];
+    let mut band_candidates: Vec<(usize, f32)> = Vec::new();
+
+    for (t_idx, frame) in spectrogram.iter().enumerate() {
+        if frame.len() != num_freq_bins {
+            continue;
+        }
+        for (band_idx, band) in edges.windows(2).enumerate() {
+            band_candidates.clear();
+            band_candidates.extend((band[0]..band[1]).map(|f_idx| (f_idx, frame[f_idx])));
+            band_candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+            let (Some(&(_, band_max)), Some(&(_, band_min))) = (band_candidates.first(), band_candidates.last()) else {
+                continue;
+            };
+            let threshold = running_thresholds[band_idx].get_or_insert(band_max);
+
+            for &(f_idx, magnitude) in band_candidates.iter().take(keep_per_band) {
+                // A flat band (e.g. digital silence) has no meaningful peak.
+                if magnitude > band_min && magnitude >= *threshold {
+                    peaks.push(Peak { time_idx: t_idx, freq_bin_idx: f_idx, magnitude });
+                }
             }
+            *threshold += BANDED_THRESHOLD_SMOOTHING * (band_max - *threshold);
         }
     }
-    println!("Debug: find_peaks - Found {} peaks.", peaks.len());
+
+    peaks.sort_by_key(|p| (p.time_idx, p.freq_bin_idx));
+    log::debug!("find_peaks_banded - Found {} peaks.", peaks.len());
     peaks
-}
\ No newline at end of file
+}
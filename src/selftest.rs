@@ -0,0 +1,178 @@
+// src/selftest.rs
+//! A fixed synthetic workload that runs the whole pipeline (spectrogram, peaks, hashing,
+//! enrollment, query) and times each stage, for checking that a build works and comparing
+//! its speed across machines, the `rayon` feature and storage backends.
+
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+use crate::audio_loader::AudioTags;
+use crate::database::DEFAULT_MIN_MATCH_SCORE;
+use crate::error::SivanaError;
+use crate::fingerprinter::Fingerprinter;
+use crate::hashing::create_hashes;
+use crate::store::FingerprintStore;
+
+/// How much synthetic audio `run_selftest` enrolls and queries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelftestWorkload {
+    /// Songs enrolled, each with its own tone sequence.
+    pub songs: usize,
+    pub song_seconds: usize,
+    /// Queries run, cycling through the songs at varying offsets.
+    pub queries: usize,
+    pub query_seconds: usize,
+}
+
+impl Default for SelftestWorkload {
+    fn default() -> Self {
+        SelftestWorkload { songs: 8, song_seconds: 30, queries: 40, query_seconds: 5 }
+    }
+}
+
+/// Work done and time taken by one stage of `run_selftest`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StageTiming {
+    /// Items processed: frames, fingerprints or queries depending on the stage.
+    pub items: usize,
+    pub elapsed: Duration,
+}
+
+impl StageTiming {
+    /// Items per second (infinite if the stage took no measurable time).
+    pub fn per_second(&self) -> f64 {
+        self.items as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Results of `run_selftest`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelftestReport {
+    pub workload: SelftestWorkload,
+    /// Frames turned into spectrogram columns.
+    pub spectrogram: StageTiming,
+    /// Frames searched for peaks.
+    pub peaks: StageTiming,
+    /// Fingerprints hashed from the peaks.
+    pub hashing: StageTiming,
+    /// Fingerprints enrolled, running the whole pipeline again plus the store's inserts.
+    pub enrollment: StageTiming,
+    /// Queries identified, whether or not they matched.
+    pub queries: StageTiming,
+    /// Queries that matched the song they were cut from.
+    pub queries_matched: usize,
+}
+
+impl SelftestReport {
+    /// Seconds of audio enrolled per second of enrollment time.
+    pub fn enrollment_realtime_factor(&self) -> f64 {
+        (self.workload.songs * self.workload.song_seconds) as f64 / self.enrollment.elapsed.as_secs_f64()
+    }
+
+    /// Whether every query matched its song, i.e. the pipeline works end to end.
+    pub fn passed(&self) -> bool {
+        self.queries_matched == self.workload.queries
+    }
+}
+
+/// Synthetic audio for song `index`: three tones that jump to pseudo-random frequencies
+/// every quarter second, seeded by the song, so songs never repeat or align with each other.
+pub fn selftest_samples(sample_rate: u32, seconds: usize, index: usize) -> Vec<f32> {
+    let step = (sample_rate as usize / 4).max(1);
+    let mut tones = Vec::new();
+    (0..sample_rate as usize * seconds)
+        .map(|i| {
+            if i % step == 0 {
+                let mut state = ((index as u64) << 32 | (i / step) as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+                tones = (0..3)
+                    .map(|_| {
+                        // xorshift; the frequencies stay well below Nyquist at any supported rate.
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        200.0 + (state % 3000) as f32
+                    })
+                    .collect();
+            }
+            let t = i as f32 / sample_rate as f32;
+            tones.iter().map(|&f| 0.3 * (2.0 * PI * f * t).sin()).sum::<f32>()
+        })
+        .collect()
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let value = f();
+    (value, start.elapsed())
+}
+
+/// Runs `workload` through `fingerprinter` and `store`, which should start out empty (the
+/// songs stay enrolled afterwards). Fails only if the store does; queries that do not match
+/// their song are counted in `queries_matched` instead.
+pub fn run_selftest<S: FingerprintStore + ?Sized>(
+    fingerprinter: &Fingerprinter,
+    store: &mut S,
+    workload: SelftestWorkload,
+) -> Result<SelftestReport, SivanaError> {
+    let songs: Vec<Vec<f32>> = (0..workload.songs)
+        .map(|index| selftest_samples(fingerprinter.sample_rate, workload.song_seconds, index))
+        .collect();
+
+    let (spectrograms, spectrogram_time) = time(|| songs.iter().map(|song| fingerprinter.spectrogram(song)).collect::<Vec<_>>());
+    let frames = spectrograms.iter().map(Vec::len).sum();
+    let (constellations, peak_time) = time(|| spectrograms.iter().map(|spectrogram| fingerprinter.peaks(spectrogram)).collect::<Vec<_>>());
+    let (fingerprint_counts, hash_time) = time(|| {
+        constellations
+            .iter()
+            .map(|peaks| create_hashes(peaks, fingerprinter.target_zone, fingerprinter.hash_config).len())
+            .collect::<Vec<_>>()
+    });
+    let fingerprints = fingerprint_counts.iter().sum();
+    log::debug!("Self-test: {} frames, {} peaks, {} fingerprints.", frames, constellations.iter().map(Vec::len).sum::<usize>(), fingerprints);
+
+    let tags = AudioTags::default();
+    let (song_ids, enroll_time) = time(|| {
+        songs
+            .iter()
+            .enumerate()
+            .map(|(index, song)| fingerprinter.enroll_in_store(store, &format!("selftest-{}", index), None, &tags, song))
+            .collect::<Result<Vec<_>, _>>()
+    });
+    let song_ids = song_ids?;
+
+    // Snippets start at whole seconds that cycle through each song, rounded down to a frame
+    // boundary: this synthetic audio's abrupt tone changes lose most hits half a hop off one.
+    let query_samples = workload.query_seconds * fingerprinter.sample_rate as usize;
+    let start_choices = workload.song_seconds.saturating_sub(workload.query_seconds) + 1;
+    let num_queries = if songs.is_empty() { 0 } else { workload.queries };
+    let snippets: Vec<(usize, &[f32])> = (0..num_queries)
+        .map(|query| {
+            let index = query % songs.len();
+            let start_second = query / songs.len() * 7 % start_choices;
+            let start = start_second * fingerprinter.sample_rate as usize / fingerprinter.hop_size * fingerprinter.hop_size;
+            let song = &songs[index];
+            (index, &song[start.min(song.len())..(start + query_samples).min(song.len())])
+        })
+        .collect();
+    let (matches, query_time) = time(|| {
+        snippets
+            .iter()
+            .map(|(_, snippet)| fingerprinter.identify_in_store(store, snippet, DEFAULT_MIN_MATCH_SCORE))
+            .collect::<Result<Vec<_>, _>>()
+    });
+    let queries_matched = matches?
+        .iter()
+        .zip(&snippets)
+        .filter(|(found, (index, _))| found.as_ref().is_some_and(|m| m.song_id == song_ids[*index]))
+        .count();
+
+    Ok(SelftestReport {
+        workload,
+        spectrogram: StageTiming { items: frames, elapsed: spectrogram_time },
+        peaks: StageTiming { items: frames, elapsed: peak_time },
+        hashing: StageTiming { items: fingerprints, elapsed: hash_time },
+        enrollment: StageTiming { items: fingerprints, elapsed: enroll_time },
+        queries: StageTiming { items: snippets.len(), elapsed: query_time },
+        queries_matched,
+    })
+}
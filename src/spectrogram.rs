@@ -1,10 +1,81 @@
 // src/spectrogram.rs
-use rustfft::FftPlanner;
+use rustfft::{Fft, FftPlanner};
 use rustfft::num_complex::Complex;
 use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
 
-// This function is only used by create_spectrogram in this module, so it doesn't need to be pub
-fn hann_window(window_size: usize) -> Vec<f32> {
+#[cfg(feature = "mel")]
+use crate::mel::MelFilterbank;
+#[cfg(feature = "ndarray")]
+use ndarray::Array2;
+
+/// Window function applied to each frame before the FFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WindowType {
+    #[default]
+    Hann,
+    Hamming,
+    BlackmanHarris,
+}
+
+/// Scale of the magnitudes returned in each spectrogram frame.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MagnitudeScale {
+    /// Raw linear FFT magnitudes (the historical behavior).
+    #[default]
+    Linear,
+    /// `20 * log10(mag)`, clamped from below at `floor_db` (e.g. -80.0).
+    Decibel { floor_db: f32 },
+    /// Squared magnitudes (`norm_sqr`). Widens the gap between strong components and the
+    /// low-energy floor, so quiet high-frequency noise yields fewer peaks (useful for speech).
+    Power,
+}
+
+/// Default floor for `MagnitudeScale::Decibel`.
+pub const DEFAULT_DB_FLOOR: f32 = -80.0;
+
+/// Whether frames of `window_size` samples can advance by `hop_size`: the hop must be at
+/// least 1 and at most the window (a larger hop would skip audio between frames). Logs why
+/// when it can't.
+fn hop_size_is_valid(window_size: usize, hop_size: usize) -> bool {
+    if hop_size == 0 || hop_size > window_size {
+        log::error!("Invalid hop size {} for window size {}: need 1 <= hop <= window; no frames computed.", hop_size, window_size);
+        return false;
+    }
+    true
+}
+
+/// Frequency spacing between neighbouring FFT bins, in Hz.
+pub fn bin_resolution_hz(sample_rate: u32, window_size: usize) -> f32 {
+    sample_rate as f32 / window_size.max(1) as f32
+}
+
+/// Center frequency of FFT bin `bin` (a `Peak::freq_bin_idx`), in Hz. Frames keep bins
+/// `0..=window_size / 2`, i.e. from DC up to the Nyquist frequency. Not meaningful for mel
+/// spectrograms, whose values are band indices.
+pub fn bin_to_hz(bin: usize, sample_rate: u32, window_size: usize) -> f32 {
+    bin as f32 * bin_resolution_hz(sample_rate, window_size)
+}
+
+fn scale_magnitude(magnitude: f32, scale: MagnitudeScale) -> f32 {
+    match scale {
+        // Power values are squared before this point (and, with mel bands, pooled).
+        MagnitudeScale::Linear | MagnitudeScale::Power => magnitude,
+        MagnitudeScale::Decibel { floor_db } => {
+            (20.0 * magnitude.max(f32::MIN_POSITIVE).log10()).max(floor_db)
+        }
+    }
+}
+
+/// Sum of the `window_type` window's values over `window_size` samples (`window_size` times
+/// its coherent gain): the factor `SpectrogramBuilder::with_normalized_magnitudes` divides by.
+pub fn window_sum(window_type: WindowType, window_size: usize) -> f32 {
+    window(window_type, window_size).iter().sum()
+}
+
+// This function is only used by SpectrogramBuilder in this module, so it doesn't need to be pub
+fn window(kind: WindowType, window_size: usize) -> Vec<f32> {
     let mut window = Vec::with_capacity(window_size);
     if window_size == 0 {
         return window;
@@ -13,58 +84,403 @@ fn hann_window(window_size: usize) -> Vec<f32> {
         window.push(1.0);
         return window;
     }
+    let denom = (window_size - 1) as f32;
     for i in 0..window_size {
-        window.push(0.5 * (1.0 - (2.0 * PI * i as f32 / (window_size - 1) as f32).cos()));
+        let phase = 2.0 * PI * i as f32 / denom;
+        let value = match kind {
+            WindowType::Hann => 0.5 * (1.0 - phase.cos()),
+            WindowType::Hamming => 0.54 - 0.46 * phase.cos(),
+            // 4-term Blackman-Harris (~-92 dB sidelobes)
+            WindowType::BlackmanHarris => {
+                0.35875 - 0.48829 * phase.cos() + 0.14128 * (2.0 * phase).cos() - 0.01168 * (3.0 * phase).cos()
+            }
+        };
+        window.push(value);
     }
     window
 }
 
-pub fn create_spectrogram( // Made public
-                           samples: &[f32],
-                           _sample_rate: u32,
-                           window_size: usize,
-                           hop_size: usize,
-) -> Vec<Vec<f32>> {
-    if samples.len() < window_size {
-        println!("Not enough samples for a full FFT window.");
-        return vec![];
+/// Holds a planned FFT and precomputed window for a fixed `window_size`, so that
+/// spectrograms for many inputs can be computed without re-planning each time.
+#[derive(Clone)]
+pub struct SpectrogramBuilder {
+    window_size: usize,
+    window_type: WindowType,
+    magnitude_scale: MagnitudeScale,
+    pad_final_frame: bool,
+    center: bool,
+    normalized_magnitudes: bool,
+    fft: Arc<dyn Fft<f32>>,
+    window_values: Vec<f32>,
+    window_sum: f32,
+    #[cfg(feature = "mel")]
+    mel_filterbank: Option<Arc<MelFilterbank>>,
+}
+
+impl fmt::Debug for SpectrogramBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpectrogramBuilder")
+            .field("window_size", &self.window_size)
+            .field("window_type", &self.window_type)
+            .field("magnitude_scale", &self.magnitude_scale)
+            .field("pad_final_frame", &self.pad_final_frame)
+            .field("center", &self.center)
+            .field("normalized_magnitudes", &self.normalized_magnitudes)
+            .field("mel_bands", &self.mel_bands())
+            .finish_non_exhaustive()
     }
+}
 
-    let num_frames = (samples.len() - window_size) / hop_size + 1;
-    if num_frames == 0 {
-        println!("Calculated zero frames. Check sample length, window size, and hop size.");
-        return vec![];
+impl SpectrogramBuilder {
+    /// Creates a builder using the default (Hann) window.
+    pub fn new(window_size: usize) -> Self {
+        Self::with_window(window_size, WindowType::default())
+    }
+
+    pub fn with_window(window_size: usize, window_type: WindowType) -> Self {
+        let mut planner = FftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(window_size);
+        let window_values = window(window_type, window_size);
+        SpectrogramBuilder {
+            window_size,
+            window_type,
+            magnitude_scale: MagnitudeScale::default(),
+            pad_final_frame: false,
+            center: false,
+            normalized_magnitudes: false,
+            fft,
+            window_sum: window_values.iter().sum(),
+            window_values,
+            #[cfg(feature = "mel")]
+            mel_filterbank: None,
+        }
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    pub fn window_type(&self) -> WindowType {
+        self.window_type
+    }
+
+    /// Switches the output magnitude scale (linear by default).
+    /// Note that peak thresholds must be chosen to match: dB values are typically in [floor, ~60].
+    pub fn with_magnitude_scale(mut self, magnitude_scale: MagnitudeScale) -> Self {
+        self.magnitude_scale = magnitude_scale;
+        self
+    }
+
+    pub fn magnitude_scale(&self) -> MagnitudeScale {
+        self.magnitude_scale
+    }
+
+    /// Reduces every frame to `num_bands` mel bands (see the `mel` module); frames then have
+    /// `num_bands` values instead of `window_size / 2 + 1`. The magnitude scale is applied
+    /// after pooling, so `Decibel` gives log-mel energies.
+    #[cfg(feature = "mel")]
+    pub fn with_mel_bands(mut self, sample_rate: u32, num_bands: usize) -> Self {
+        self.mel_filterbank = Some(Arc::new(MelFilterbank::new(sample_rate, self.window_size, num_bands)));
+        self
+    }
+
+    /// Zero-pads the audio after the last full frame into one more frame, so trailing
+    /// samples are analysed and any non-empty input shorter than a window still yields a
+    /// frame (off by default: such input yields no frames).
+    pub fn with_pad_final_frame(mut self, pad_final_frame: bool) -> Self {
+        self.pad_final_frame = pad_final_frame;
+        self
+    }
+
+    pub fn pad_final_frame(&self) -> bool {
+        self.pad_final_frame
+    }
+
+    /// Centers frame `i` on sample `i * hop_size` instead of starting it there, by
+    /// reflect-padding the signal with `window_size / 2` samples on both ends (as e.g.
+    /// librosa does). Frame indices then map to the time of the frame's center; the same
+    /// setting must be used for enrollment and queries.
+    pub fn with_center(mut self, center: bool) -> Self {
+        self.center = center;
+        self
+    }
+
+    pub fn center(&self) -> bool {
+        self.center
+    }
+
+    /// Divides every FFT magnitude by `window_sum`, so a sinusoid of amplitude `a` peaks at
+    /// about `a / 2` whatever the window size and type, and a magnitude threshold tuned at one
+    /// window size carries over to another. Power magnitudes are divided by its square. Off
+    /// by default: rustfft doesn't normalize, so raw magnitudes grow with `window_size`.
+    pub fn with_normalized_magnitudes(mut self, normalized_magnitudes: bool) -> Self {
+        self.normalized_magnitudes = normalized_magnitudes;
+        self
+    }
+
+    pub fn normalized_magnitudes(&self) -> bool {
+        self.normalized_magnitudes
+    }
+
+    /// Number of mel bands per frame, or `None` for a plain FFT-bin spectrogram.
+    pub fn mel_bands(&self) -> Option<usize> {
+        #[cfg(feature = "mel")]
+        {
+            self.mel_filterbank.as_ref().map(|filterbank| filterbank.num_bands())
+        }
+        #[cfg(not(feature = "mel"))]
+        {
+            None
+        }
+    }
+
+    /// Computes the magnitude spectrogram (frames x (window_size/2 + 1) bins, or mel bands) of `samples`.
+    /// Returns no frames unless `1 <= hop_size <= window_size`.
+    pub fn build(&self, samples: &[f32], hop_size: usize) -> Vec<Vec<f32>> {
+        let mut spectrogram: Vec<Vec<f32>> = Vec::new();
+        self.build_frames(samples, hop_size, |frame| spectrogram.push(frame));
+        spectrogram
+    }
+
+    /// `build` into one contiguous frames x bins array (row `t` is frame `t`), for further
+    /// analysis with ndarray or `peaks::find_peaks_array`. The values are the same.
+    #[cfg(feature = "ndarray")]
+    pub fn build_array(&self, samples: &[f32], hop_size: usize) -> Array2<f32> {
+        let mut values: Vec<f32> = Vec::new();
+        let mut num_frames = 0;
+        let mut num_bins = 0;
+        self.build_frames(samples, hop_size, |frame| {
+            num_bins = frame.len();
+            num_frames += 1;
+            values.extend_from_slice(&frame);
+        });
+        Array2::from_shape_vec((num_frames, num_bins), values).expect("every frame has the same number of bins")
     }
 
-    println!(
-        "Debug: create_spectrogram - Samples: {}, Window: {}, Hop: {}, Frames: {}",
-        samples.len(), window_size, hop_size, num_frames
-    );
+    // Computes the frames of `build` in order, handing each to `emit`.
+    fn build_frames(&self, samples: &[f32], hop_size: usize, mut emit: impl FnMut(Vec<f32>)) {
+        let window_size = self.window_size;
+        if !hop_size_is_valid(window_size, hop_size) {
+            return;
+        }
+        let centered;
+        let samples = if self.center && !samples.is_empty() {
+            let half = window_size / 2;
+            centered = [reflected_start(samples, half), samples.to_vec(), reflected_end(samples, half)].concat();
+            centered.as_slice()
+        } else {
+            samples
+        };
+        let can_pad = self.pad_final_frame && !samples.is_empty();
+        if samples.len() < window_size && !can_pad {
+            log::warn!("Not enough samples for a full FFT window.");
+            return;
+        }
 
-    let mut planner = FftPlanner::<f32>::new();
-    let fft = planner.plan_fft_forward(window_size);
-    let mut buffer: Vec<Complex<f32>> = vec![Complex::new(0.0, 0.0); window_size];
-    let mut spectrogram: Vec<Vec<f32>> = Vec::with_capacity(num_frames);
+        let num_full_frames = if samples.len() < window_size { 0 } else { (samples.len() - window_size) / hop_size + 1 };
+        let mut buffer: Vec<Complex<f32>> = vec![Complex::new(0.0, 0.0); window_size];
+        let mut num_frames = 0;
 
-    let window_values = hann_window(window_size); // Calls local hann_window
+        for i in 0..num_full_frames {
+            let start = i * hop_size;
+            let end = start + window_size;
+            emit(self.frame_magnitudes(&samples[start..end], &mut buffer));
+            num_frames += 1;
+        }
+        // Samples past the end of the last full frame, if any.
+        let covered = if num_full_frames == 0 { 0 } else { (num_full_frames - 1) * hop_size + window_size };
+        if self.pad_final_frame && samples.len() > covered {
+            emit(self.padded_frame_magnitudes(&samples[num_full_frames * hop_size..], &mut buffer));
+            num_frames += 1;
+        }
 
-    for i in 0..num_frames {
-        let start = i * hop_size;
-        let end = start + window_size;
-        let audio_chunk = &samples[start..end];
+        log::debug!(
+            "create_spectrogram - Samples: {}, Window: {}, Hop: {}, Frames: {}",
+            samples.len(), window_size, hop_size, num_frames
+        );
+    }
+
+    /// `frame_magnitudes` for fewer than `window_size` samples, zero-padded at the end.
+    fn padded_frame_magnitudes(&self, audio_chunk: &[f32], buffer: &mut [Complex<f32>]) -> Vec<f32> {
+        let mut padded = audio_chunk.to_vec();
+        padded.resize(self.window_size, 0.0);
+        self.frame_magnitudes(&padded, buffer)
+    }
 
+    /// Windows and transforms exactly `window_size` samples into one spectrogram frame.
+    fn frame_magnitudes(&self, audio_chunk: &[f32], buffer: &mut [Complex<f32>]) -> Vec<f32> {
         for (j, sample) in audio_chunk.iter().enumerate() {
-            buffer[j] = Complex::new(*sample * window_values[j], 0.0);
+            buffer[j] = Complex::new(*sample * self.window_values[j], 0.0);
         }
 
-        fft.process(&mut buffer);
+        self.fft.process(buffer);
 
-        let num_bins_to_keep = window_size / 2 + 1;
+        let num_bins_to_keep = self.window_size / 2 + 1;
+        let norm = if self.normalized_magnitudes { 1.0 / self.window_sum } else { 1.0 };
         let mut magnitudes: Vec<f32> = Vec::with_capacity(num_bins_to_keep);
-        for k in 0..num_bins_to_keep {
-            magnitudes.push(buffer[k].norm());
+        for bin in buffer.iter().take(num_bins_to_keep) {
+            magnitudes.push(match self.magnitude_scale {
+                MagnitudeScale::Power => bin.norm_sqr() * norm * norm,
+                _ => bin.norm() * norm,
+            });
+        }
+        #[cfg(feature = "mel")]
+        if let Some(filterbank) = &self.mel_filterbank {
+            magnitudes = filterbank.apply(&magnitudes);
+        }
+        for magnitude in magnitudes.iter_mut() {
+            *magnitude = scale_magnitude(*magnitude, self.magnitude_scale);
+        }
+        magnitudes
+    }
+}
+
+/// The `pad` samples mirrored before `samples[0]` (excluding it): `samples[pad], ..., samples[1]`.
+/// Positions past the end of a too-short signal are zero.
+fn reflected_start(samples: &[f32], pad: usize) -> Vec<f32> {
+    (1..=pad).rev().map(|k| samples.get(k).copied().unwrap_or(0.0)).collect()
+}
+
+/// The `pad` samples mirrored after the last one (excluding it), zero where the signal is too short.
+fn reflected_end(samples: &[f32], pad: usize) -> Vec<f32> {
+    let last = samples.len().saturating_sub(1);
+    (1..=pad).map(|k| last.checked_sub(k).map_or(0.0, |i| samples[i])).collect()
+}
+
+/// Computes spectrogram frames from audio that arrives in consecutive chunks of any size.
+/// Samples that later frames still need (the window/hop overlap) are carried over between
+/// calls, so the concatenated output equals `SpectrogramBuilder::build` on the whole signal
+/// (in particular, there are no frames unless `1 <= hop_size <= window_size`).
+#[derive(Debug, Clone)]
+pub struct StreamingSpectrogram {
+    builder: SpectrogramBuilder,
+    hop_size: usize,
+    valid_hop: bool,
+    // Samples not yet consumed by a complete frame.
+    pending: Vec<f32>,
+    // Whether any frame was produced (the start of `pending` then overlaps the last one).
+    emitted_any: bool,
+    // Centered framing: the start padding is added once `window_size / 2 + 1` samples are
+    // in, and the end padding at `finish` from the last samples kept here.
+    awaiting_start_padding: bool,
+    tail: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+}
+
+impl StreamingSpectrogram {
+    pub fn new(builder: SpectrogramBuilder, hop_size: usize) -> Self {
+        let window_size = builder.window_size();
+        let builder_center = builder.center();
+        StreamingSpectrogram {
+            builder,
+            hop_size,
+            valid_hop: hop_size_is_valid(window_size, hop_size),
+            pending: Vec::with_capacity(window_size * 2),
+            emitted_any: false,
+            awaiting_start_padding: builder_center,
+            tail: Vec::new(),
+            buffer: vec![Complex::new(0.0, 0.0); window_size],
+        }
+    }
+
+    /// Appends `samples` and returns every frame that became complete.
+    pub fn push(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        if !self.valid_hop {
+            return Vec::new();
+        }
+        self.pending.extend_from_slice(samples);
+        if self.builder.center() {
+            let keep = self.builder.window_size() / 2 + 1;
+            self.tail.extend_from_slice(&samples[samples.len().saturating_sub(keep)..]);
+            self.tail.drain(..self.tail.len().saturating_sub(keep));
+            if self.awaiting_start_padding {
+                if self.pending.len() < keep {
+                    return Vec::new();
+                }
+                self.add_start_padding();
+            }
+        }
+        self.full_frames()
+    }
+
+    fn add_start_padding(&mut self) {
+        let padding = reflected_start(&self.pending, self.builder.window_size() / 2);
+        self.pending.splice(0..0, padding);
+        self.awaiting_start_padding = false;
+    }
+
+    // Computes every complete frame in `pending` and drops the samples no later frame needs.
+    fn full_frames(&mut self) -> Vec<Vec<f32>> {
+        let window_size = self.builder.window_size();
+        let mut frames = Vec::new();
+        let mut start = 0;
+        while start + window_size <= self.pending.len() {
+            frames.push(self.builder.frame_magnitudes(&self.pending[start..start + window_size], &mut self.buffer));
+            start += self.hop_size;
         }
-        spectrogram.push(magnitudes);
+        // With hop <= window, the next frame never starts past the buffered samples.
+        self.pending.drain(..start);
+        self.emitted_any |= !frames.is_empty();
+        frames
+    }
+
+    /// Ends the stream and returns the frames that needed the end of the signal: those over
+    /// the end padding of centered framing and, with
+    /// `SpectrogramBuilder::with_pad_final_frame`, the zero-padded frame over the samples no
+    /// full frame covered.
+    pub fn finish(mut self) -> Vec<Vec<f32>> {
+        if !self.valid_hop {
+            return Vec::new();
+        }
+        let mut frames = Vec::new();
+        if self.builder.center() {
+            if self.awaiting_start_padding {
+                if self.pending.is_empty() {
+                    return frames;
+                }
+                self.add_start_padding();
+            }
+            let padding = reflected_end(&self.tail, self.builder.window_size() / 2);
+            self.pending.extend(padding);
+            frames = self.full_frames();
+        }
+        // The first `window - hop` pending samples belong to the last full frame.
+        let covered = if self.emitted_any { self.builder.window_size() - self.hop_size } else { 0 };
+        if self.builder.pad_final_frame() && self.pending.len() > covered {
+            frames.push(self.builder.padded_frame_magnitudes(&self.pending, &mut self.buffer));
+        }
+        frames
+    }
+}
+
+/// Convenience wrapper that plans a fresh FFT for a single call, using a Hann window.
+/// Prefer `SpectrogramBuilder` when processing many inputs with the same window size.
+pub fn create_spectrogram( // Made public
+                           samples: &[f32],
+                           sample_rate: u32,
+                           window_size: usize,
+                           hop_size: usize,
+) -> Vec<Vec<f32>> {
+    create_spectrogram_with_window(samples, sample_rate, window_size, hop_size, WindowType::Hann)
+}
+
+/// `create_spectrogram` as one contiguous frames x bins array; see `SpectrogramBuilder::build_array`.
+#[cfg(feature = "ndarray")]
+pub fn create_spectrogram_ndarray(samples: &[f32], _sample_rate: u32, window_size: usize, hop_size: usize) -> Array2<f32> {
+    SpectrogramBuilder::with_window(window_size, WindowType::Hann).build_array(samples, hop_size)
+}
+
+/// Like `create_spectrogram`, but with a selectable window function.
+pub fn create_spectrogram_with_window(
+    samples: &[f32],
+    _sample_rate: u32,
+    window_size: usize,
+    hop_size: usize,
+    window_type: WindowType,
+) -> Vec<Vec<f32>> {
+    if samples.len() < window_size {
+        log::warn!("Not enough samples for a full FFT window.");
+        return vec![];
     }
-    spectrogram
-}
\ No newline at end of file
+    SpectrogramBuilder::with_window(window_size, window_type).build(samples, hop_size)
+}
@@ -0,0 +1,217 @@
+// src/store.rs
+//! Storage-agnostic enrollment and matching. `FingerprintStore` is the small set of
+//! operations the pipeline needs from a backend; it is implemented for the SQLite
+//! `Connection` and, with the `sled` feature, for `SledStore`, an embedded key-value store
+//! whose hash lookups are plain prefix scans and stay fast on very large libraries.
+//!
+//! The SQLite-specific functions in `database` (duplicate detection, verification, hash
+//! popularity limits, progress reporting) remain the richer path for SQLite databases.
+
+use rusqlite::{params, Connection};
+
+use crate::audio_loader::AudioTags;
+use crate::database::{get_song_info, insert_fingerprint_batches, MatchResult, Song, SongId};
+use crate::error::{Result, SivanaError};
+use crate::hashing::Fingerprint;
+use crate::matching::OffsetHistogram;
+
+/// A stored fingerprint as returned by `FingerprintStore::lookup`: (song ID, anchor frame,
+/// anchor-target delta in frames if known).
+pub type StoredFingerprint = (SongId, usize, Option<usize>);
+
+/// Backend holding songs and their fingerprints.
+pub trait FingerprintStore {
+    /// Adds a song and returns its ID. Fails if a song with the same file path exists.
+    fn insert_song(&mut self, name: &str, file_path: Option<&str>, tags: &AudioTags) -> Result<SongId>;
+
+    /// Stores fingerprints of an existing song (atomically: all or none).
+    fn insert_fingerprints(&mut self, song_id: SongId, fingerprints: &[Fingerprint]) -> Result<()>;
+
+    /// Every stored fingerprint with this hash.
+    fn lookup(&self, hash: u64) -> Result<Vec<StoredFingerprint>>;
+
+    fn song_info(&self, song_id: SongId) -> Result<Option<Song>>;
+}
+
+/// Adds a song with its (already computed) fingerprints to `store`.
+pub fn enroll_in_store<S: FingerprintStore + ?Sized>(
+    store: &mut S,
+    song_name: &str,
+    song_file_path: Option<&str>,
+    song_tags: &AudioTags,
+    fingerprints: &[Fingerprint],
+) -> Result<SongId> {
+    if fingerprints.is_empty() {
+        return Err(SivanaError::NoFingerprints { song_name: song_name.to_string() });
+    }
+    let song_id = store.insert_song(song_name, song_file_path, song_tags)?;
+    store.insert_fingerprints(song_id, fingerprints)?;
+    log::debug!("enroll_in_store - Stored {} fingerprints for song ID {}.", fingerprints.len(), song_id);
+    Ok(song_id)
+}
+
+/// Up to `n` matches for the query from `store`, by descending score; the same scoring as
+/// `query_db_and_match_topn` (without verification or hash popularity limits).
+pub fn match_in_store<S: FingerprintStore + ?Sized>(
+    store: &S,
+    query_fingerprints: &[Fingerprint],
+    n: usize,
+    min_score: usize,
+    frame_duration_seconds: f32,
+) -> Result<Vec<MatchResult>> {
+    let mut histogram = OffsetHistogram::new(query_fingerprints);
+    for hash in histogram.distinct_hashes() {
+        for (song_id, anchor_time_idx, target_delta_frames) in store.lookup(hash)? {
+            histogram.add((hash, song_id, anchor_time_idx, target_delta_frames));
+        }
+    }
+    Ok(histogram.into_candidates(n, min_score, frame_duration_seconds))
+}
+
+impl FingerprintStore for Connection {
+    fn insert_song(&mut self, name: &str, file_path: Option<&str>, tags: &AudioTags) -> Result<SongId> {
+        let song_id: i64 = self.query_row(
+            "INSERT INTO songs (name, file_path, artist, album) VALUES (?1, ?2, ?3, ?4) RETURNING song_id",
+            params![name, file_path, tags.artist, tags.album],
+            |row| row.get(0),
+        ).map_err(|e| SivanaError::sqlite(format!("Failed to insert song '{}'", name), e))?;
+        Ok(song_id as SongId)
+    }
+
+    fn insert_fingerprints(&mut self, song_id: SongId, fingerprints: &[Fingerprint]) -> Result<()> {
+        let tx = self.transaction().map_err(|e| SivanaError::sqlite("Failed to start transaction", e))?;
+        insert_fingerprint_batches(&tx, song_id as i64, fingerprints, None)
+            .map_err(|e| SivanaError::sqlite(format!("Failed to insert fingerprints for song ID {}", song_id), e))?;
+        tx.commit().map_err(|e| SivanaError::sqlite("Failed to commit fingerprints", e))
+    }
+
+    fn lookup(&self, hash: u64) -> Result<Vec<StoredFingerprint>> {
+        let lookup_error = |e| SivanaError::sqlite(format!("Failed to look up hash {}", hash), e);
+        let mut stmt = self.prepare_cached("SELECT song_id, anchor_time_idx, target_delta_frames FROM fingerprints WHERE hash = ?1")
+            .map_err(lookup_error)?;
+        let rows = stmt.query_map(params![hash as i64], |row| {
+            Ok((
+                row.get::<_, i64>(0)? as SongId,
+                row.get::<_, i64>(1)? as usize,
+                row.get::<_, Option<i64>>(2)?.map(|d| d as usize),
+            ))
+        }).map_err(lookup_error)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(lookup_error)
+    }
+
+    fn song_info(&self, song_id: SongId) -> Result<Option<Song>> {
+        get_song_info(self, song_id).map_err(|e| SivanaError::sqlite(format!("Failed to read song ID {}", song_id), e))
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use self::sled_store::SledStore;
+
+#[cfg(feature = "sled")]
+mod sled_store {
+    use std::path::Path;
+
+    use super::{FingerprintStore, StoredFingerprint};
+    use crate::audio_loader::AudioTags;
+    use crate::database::{Song, SongId};
+    use crate::error::{Result, SivanaError};
+    use crate::hashing::Fingerprint;
+
+    /// Fingerprint store in a sled database directory. Fingerprints live in one tree keyed
+    /// by `hash | song_id | anchor | delta` (big-endian, so a hash's entries are contiguous
+    /// and `lookup` is a single prefix scan); songs are JSON values keyed by song ID.
+    pub struct SledStore {
+        db: sled::Db,
+        songs: sled::Tree,
+        // File path -> song ID, enforcing unique paths like SQLite's UNIQUE column.
+        paths: sled::Tree,
+        fingerprints: sled::Tree,
+    }
+
+    fn sled_error(context: impl Into<String>) -> impl FnOnce(sled::Error) -> SivanaError {
+        let context = context.into();
+        move |source| SivanaError::Sled { context, source }
+    }
+
+    impl SledStore {
+        /// Opens (creating if needed) the store in directory `path`.
+        pub fn open(path: &Path) -> Result<Self> {
+            let db = sled::open(path).map_err(sled_error(format!("Failed to open sled store '{}'", path.display())))?;
+            let open_tree = |name: &str| db.open_tree(name).map_err(sled_error(format!("Failed to open tree '{}'", name)));
+            let (songs, paths, fingerprints) = (open_tree("songs")?, open_tree("paths")?, open_tree("fingerprints")?);
+            Ok(SledStore { db, songs, paths, fingerprints })
+        }
+
+        /// Writes buffered changes to disk (sled also flushes periodically on its own).
+        pub fn flush(&self) -> Result<()> {
+            self.db.flush().map(|_| ()).map_err(sled_error("Failed to flush sled store"))
+        }
+    }
+
+    impl FingerprintStore for SledStore {
+        fn insert_song(&mut self, name: &str, file_path: Option<&str>, tags: &AudioTags) -> Result<SongId> {
+            if let Some(path) = file_path
+                && self.paths.contains_key(path).map_err(sled_error("Failed to read song paths"))?
+            {
+                return Err(SivanaError::InvalidInput(format!("A song with file path '{}' is already stored.", path)));
+            }
+            // IDs start at 1 like SQLite's.
+            let id = self.db.generate_id().map_err(sled_error("Failed to allocate a song ID"))? + 1;
+            let song_id = SongId::try_from(id)
+                .map_err(|_| SivanaError::InvalidInput(format!("Song ID {} exceeds the supported range.", id)))?;
+            let value = serde_json::json!({
+                "name": name, "file_path": file_path, "artist": tags.artist, "album": tags.album,
+            });
+            self.songs.insert(song_id.to_be_bytes(), value.to_string().into_bytes())
+                .map_err(sled_error(format!("Failed to insert song '{}'", name)))?;
+            if let Some(path) = file_path {
+                self.paths.insert(path, &song_id.to_be_bytes()).map_err(sled_error("Failed to record song path"))?;
+            }
+            Ok(song_id)
+        }
+
+        fn insert_fingerprints(&mut self, song_id: SongId, fingerprints: &[Fingerprint]) -> Result<()> {
+            let mut batch = sled::Batch::default();
+            for fp in fingerprints {
+                let mut key = Vec::with_capacity(28);
+                key.extend_from_slice(&fp.hash.to_be_bytes());
+                key.extend_from_slice(&song_id.to_be_bytes());
+                key.extend_from_slice(&(fp.anchor_time_idx as u64).to_be_bytes());
+                key.extend_from_slice(&(fp.target_delta_frames as u64).to_be_bytes());
+                batch.insert(key, &[]);
+            }
+            self.fingerprints.apply_batch(batch)
+                .map_err(sled_error(format!("Failed to insert fingerprints for song ID {}", song_id)))
+        }
+
+        fn lookup(&self, hash: u64) -> Result<Vec<StoredFingerprint>> {
+            let be_u64 = |bytes: &[u8]| u64::from_be_bytes(bytes.try_into().expect("8-byte key field"));
+            self.fingerprints
+                .scan_prefix(hash.to_be_bytes())
+                .keys()
+                .map(|key| {
+                    let key = key.map_err(sled_error(format!("Failed to look up hash {}", hash)))?;
+                    let song_id = SongId::from_be_bytes(key[8..12].try_into().expect("4-byte song ID"));
+                    Ok((song_id, be_u64(&key[12..20]) as usize, Some(be_u64(&key[20..28]) as usize)))
+                })
+                .collect()
+        }
+
+        fn song_info(&self, song_id: SongId) -> Result<Option<Song>> {
+            let Some(bytes) = self.songs.get(song_id.to_be_bytes()).map_err(sled_error(format!("Failed to read song ID {}", song_id)))? else {
+                return Ok(None);
+            };
+            let value: serde_json::Value = serde_json::from_slice(&bytes)
+                .map_err(|e| SivanaError::InvalidFormat(format!("Corrupt record for song ID {}: {}", song_id, e)))?;
+            let text = |field: &str| value[field].as_str().map(str::to_string);
+            Ok(Some(Song {
+                id: song_id,
+                name: text("name").unwrap_or_default(),
+                file_path: text("file_path"),
+                duration_seconds: None,
+                artist: text("artist"),
+                album: text("album"),
+            }))
+        }
+    }
+}
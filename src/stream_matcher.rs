@@ -0,0 +1,188 @@
+// src/stream_matcher.rs
+//! Continuous identification of an endless audio stream (e.g. a radio feed): fingerprints
+//! are computed incrementally, the last few seconds of them are matched against the
+//! database at a fixed interval, and a change is reported once a new song (or silence /
+//! unknown audio) has been matched several times in a row.
+
+use std::collections::VecDeque;
+
+use rusqlite::Connection;
+
+use crate::database::{query_db_and_match, MatchResult, QueryOptions, SongId};
+use crate::fingerprinter::{FingerprintStream, Fingerprinter};
+use crate::hashing::{fingerprints_in_window, Fingerprint};
+
+/// Default time between two database queries of a `StreamMatcher`.
+pub const DEFAULT_STREAM_QUERY_INTERVAL_SECONDS: f32 = 2.0;
+/// Default number of consecutive queries that must agree before a `StreamMatcher` reports a change.
+pub const DEFAULT_STREAM_CONFIRMATIONS: usize = 2;
+
+/// The stably matched song changed.
+#[derive(Debug, Clone)]
+pub struct SongChange {
+    /// Song matched before the change; `None` at the start of the stream or after unknown audio.
+    pub previous: Option<SongId>,
+    /// The match that confirmed the change; `None` if the stream no longer matches anything.
+    /// Offsets and match times refer to the start of the matched window.
+    pub current: Option<MatchResult>,
+    /// Position in the stream, in seconds since the first sample, of the query that
+    /// confirmed the change.
+    pub stream_seconds: f32,
+}
+
+/// Sliding-window matcher over a stream of mono samples at the fingerprinter's sample rate.
+/// Every query interval, the fingerprints whose anchors fall into the last `window_seconds`
+/// are matched with `query_db_and_match`. A different result (another song, or no match)
+/// only becomes the current song after `confirmations` consecutive queries returned it, so
+/// a single noisy window during a song, or a short jingle, doesn't flip the result.
+pub struct StreamMatcher<'a> {
+    conn: &'a Connection,
+    stream: FingerprintStream,
+    sample_rate: u32,
+    hop_size: usize,
+    window_frames: usize,
+    query_interval_samples: usize,
+    confirmations: usize,
+    options: QueryOptions,
+    // Fingerprints of the current window, oldest anchor first.
+    window: VecDeque<Fingerprint>,
+    samples_seen: usize,
+    samples_since_query: usize,
+    current_song: Option<SongId>,
+    // Result of the latest queries that disagree with `current_song`, and how many in a row.
+    candidate: Option<SongId>,
+    candidate_streak: usize,
+}
+
+impl<'a> StreamMatcher<'a> {
+    /// Matches the last `window_seconds` of audio against `conn`, using `fingerprinter`'s
+    /// settings (which must be those the database was built with), and reports candidates
+    /// scoring at least `min_score`. Windows below `DEFAULT_MIN_QUERY_COVERAGE` count as
+    /// unknown audio, and matches are verified with `DEFAULT_VERIFY_MIN_FRACTION`.
+    pub fn new(fingerprinter: &Fingerprinter, conn: &'a Connection, window_seconds: f32, min_score: usize) -> Self {
+        let frame_duration_seconds = fingerprinter.frame_duration_seconds();
+        StreamMatcher {
+            conn,
+            stream: fingerprinter.fingerprint_stream(),
+            sample_rate: fingerprinter.sample_rate,
+            hop_size: fingerprinter.hop_size,
+            window_frames: (window_seconds / frame_duration_seconds).ceil().max(1.0) as usize,
+            query_interval_samples: seconds_to_samples(DEFAULT_STREAM_QUERY_INTERVAL_SECONDS, fingerprinter.sample_rate),
+            confirmations: DEFAULT_STREAM_CONFIRMATIONS,
+            options: fingerprinter.query_options(min_score),
+            window: VecDeque::new(),
+            samples_seen: 0,
+            samples_since_query: 0,
+            current_song: None,
+            candidate: None,
+            candidate_streak: 0,
+        }
+    }
+
+    /// Sets the time between queries (`DEFAULT_STREAM_QUERY_INTERVAL_SECONDS` by default).
+    pub fn with_query_interval(mut self, seconds: f32) -> Self {
+        self.query_interval_samples = seconds_to_samples(seconds, self.sample_rate);
+        self
+    }
+
+    /// Sets how many consecutive queries must agree before a change is reported (at least 1).
+    pub fn with_confirmations(mut self, confirmations: usize) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    /// Sets the minimum share of window fingerprints that must hit the database, or disables
+    /// the check with `None`.
+    pub fn with_min_query_coverage(mut self, min_query_coverage: Option<f32>) -> Self {
+        self.options.min_query_coverage = min_query_coverage;
+        self
+    }
+
+    /// Sets the `verify_match` threshold, or disables verification with `None`.
+    pub fn with_verify_min_fraction(mut self, verify_min_fraction: Option<f32>) -> Self {
+        self.options.verify_min_fraction = verify_min_fraction;
+        self
+    }
+
+    /// Ignores hashes stored more than N times in the database.
+    pub fn with_max_hash_popularity(mut self, max_hash_popularity: Option<usize>) -> Self {
+        self.options.max_hash_popularity = max_hash_popularity;
+        self
+    }
+
+    /// Lets hits up to `frames` away from an offset count towards it, for streams whose
+    /// clock runs slightly fast or slow (see `OffsetHistogram::with_offset_tolerance`).
+    pub fn with_offset_tolerance(mut self, frames: usize) -> Self {
+        self.options.offset_tolerance_frames = frames;
+        self
+    }
+
+    /// The song currently considered playing, if any.
+    pub fn current_song(&self) -> Option<SongId> {
+        self.current_song
+    }
+
+    /// Feeds the next samples and returns the song changes confirmed by the queries that
+    /// became due (usually none; more than one only if `samples` spans several intervals).
+    pub fn push_samples(&mut self, samples: &[f32]) -> Vec<SongChange> {
+        let mut changes = Vec::new();
+        let mut rest = samples;
+        while !rest.is_empty() {
+            let take = rest.len().min(self.query_interval_samples - self.samples_since_query);
+            let (chunk, remaining) = rest.split_at(take);
+            rest = remaining;
+            self.window.extend(self.stream.push_samples(chunk));
+            self.samples_seen += chunk.len();
+            self.samples_since_query += chunk.len();
+            if self.samples_since_query >= self.query_interval_samples {
+                self.samples_since_query = 0;
+                changes.extend(self.query_window());
+            }
+        }
+        changes
+    }
+
+    /// Matches the current window and applies the hysteresis; returns the change, if any.
+    fn query_window(&mut self) -> Option<SongChange> {
+        // Frames completed so far; anchors older than one window before that are dropped.
+        let newest_frame = self.samples_seen / self.hop_size.max(1);
+        let window_start = newest_frame.saturating_sub(self.window_frames);
+        while self.window.front().is_some_and(|fp| fp.anchor_time_idx < window_start) {
+            self.window.pop_front();
+        }
+
+        // Rebased so the match offset is the song position of the window's first frame.
+        let query = fingerprints_in_window(self.window.make_contiguous(), window_start, usize::MAX);
+        let best = query_db_and_match(self.conn, &query, &self.options);
+        let stream_seconds = self.samples_seen as f32 / self.sample_rate as f32;
+        let matched_song = best.as_ref().map(|m| m.song_id);
+        log::debug!(
+            "StreamMatcher - {:.1}s: {} window fingerprints, best {:?}, current {:?}.",
+            stream_seconds, query.len(), best.as_ref().map(|m| (m.song_id, m.score)), self.current_song
+        );
+
+        if matched_song == self.current_song {
+            self.candidate_streak = 0;
+            return None;
+        }
+        if matched_song == self.candidate && self.candidate_streak > 0 {
+            self.candidate_streak += 1;
+        } else {
+            self.candidate = matched_song;
+            self.candidate_streak = 1;
+        }
+        if self.candidate_streak < self.confirmations {
+            return None;
+        }
+
+        let previous = self.current_song;
+        self.current_song = matched_song;
+        self.candidate_streak = 0;
+        log::info!("StreamMatcher - Song changed from {:?} to {:?} at {:.1}s.", previous, matched_song, stream_seconds);
+        Some(SongChange { previous, current: best, stream_seconds })
+    }
+}
+
+fn seconds_to_samples(seconds: f32, sample_rate: u32) -> usize {
+    ((seconds * sample_rate as f32).round() as usize).max(1)
+}
@@ -0,0 +1,53 @@
+use sivana::peaks::{find_peaks, find_peaks_adaptive, Peak, PeakParams};
+
+const NUM_FRAMES: usize = 40;
+const NUM_BINS: usize = 513;
+const QUIET_PEAK: (usize, usize) = (20, 400);
+
+/// Loud, noisy bass (bins below 32) over a near-silent top end holding one quiet peak.
+fn bass_heavy_spectrogram() -> Vec<Vec<f32>> {
+    let mut state: u32 = 7;
+    let mut spectrogram = vec![vec![0.01f32; NUM_BINS]; NUM_FRAMES];
+    for frame in spectrogram.iter_mut() {
+        for value in frame.iter_mut().take(32) {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            *value = 50.0 + (state >> 16) as f32 / 65536.0 * 10.0;
+        }
+    }
+    spectrogram[QUIET_PEAK.0][QUIET_PEAK.1] = 0.5;
+    spectrogram
+}
+
+fn contains(peaks: &[Peak], (t, f): (usize, usize)) -> bool {
+    peaks.iter().any(|p| p.time_idx == t && p.freq_bin_idx == f)
+}
+
+fn bass_peak_count(peaks: &[Peak]) -> usize {
+    peaks.iter().filter(|p| p.freq_bin_idx < 32).count()
+}
+
+#[test]
+fn adaptive_threshold_keeps_quiet_treble_peak() {
+    let spectrogram = bass_heavy_spectrogram();
+    // A global threshold high enough to skip the 0.01 floor drops the quiet peak...
+    assert!(!contains(&find_peaks(&spectrogram, PeakParams { min_magnitude: 1.0, min_frame_energy: 0.0, ..PeakParams::default() }), QUIET_PEAK));
+
+    // ...while each band's own threshold finds it, and nothing else in the flat treble.
+    let adaptive = find_peaks_adaptive(&spectrogram, 2, 5, 1.0);
+    assert!(contains(&adaptive, QUIET_PEAK));
+    assert!(adaptive.iter().all(|p| p.freq_bin_idx < 32 || (p.time_idx, p.freq_bin_idx) == QUIET_PEAK));
+}
+
+#[test]
+fn larger_k_keeps_fewer_noise_peaks() {
+    let spectrogram = bass_heavy_spectrogram();
+    let loose = find_peaks_adaptive(&spectrogram, 2, 5, 0.0);
+    let strict = find_peaks_adaptive(&spectrogram, 2, 5, 1.5);
+    assert!(bass_peak_count(&strict) < bass_peak_count(&loose));
+    assert!(contains(&strict, QUIET_PEAK));
+}
+
+#[test]
+fn adaptive_threshold_on_empty_spectrogram_finds_nothing() {
+    assert!(find_peaks_adaptive(&[], 2, 5, 1.0).is_empty());
+}
@@ -0,0 +1,52 @@
+use sivana::database::{MatchResult, SongId};
+use sivana::matching::aggregate_matches;
+
+// Frames of 1/10 s keep the offsets below easy to read.
+const FRAME_SECONDS: f32 = 0.1;
+
+fn hit(song_id: SongId, offset_frames: isize, score: usize, confidence: f32) -> Option<MatchResult> {
+    Some(MatchResult {
+        song_id,
+        source_db: 0,
+        score,
+        weighted_score: score,
+        distinct_hash_score: score,
+        time_offset_in_song_frames: offset_frames,
+        confidence,
+        query_coverage: 1.0,
+        match_start_seconds: offset_frames as f32 * FRAME_SECONDS,
+        match_end_seconds: offset_frames as f32 * FRAME_SECONDS + 5.0,
+        query_match_start_seconds: 0.0,
+        query_match_end_seconds: 5.0,
+    })
+}
+
+#[test]
+fn majority_song_wins_and_offset_outliers_are_not_consistent() {
+    // Snippets every 3 s from a recording starting 10 s into song 1; snippet 3 aligned wrongly.
+    let results = vec![
+        hit(1, 100, 30, 0.5),
+        hit(1, 130, 30, 0.5),
+        None,
+        hit(1, 20, 25, 0.4),
+        hit(2, 0, 500, 1.0),
+        hit(1, 250, 40, 0.6),
+    ];
+
+    let with_spacing = aggregate_matches(&results, FRAME_SECONDS, Some(3.0)).unwrap();
+    assert_eq!(with_spacing.song_id, 1);
+    assert_eq!((with_spacing.votes, with_spacing.snippets, with_spacing.consistent_votes), (4, 6, 3));
+    assert_eq!(with_spacing.total_score, 125);
+    let expected = 3.0 / 6.0 * (1.0 - 0.5 * 0.5 * 0.4);
+    assert!((with_spacing.confidence - expected).abs() < 1e-6);
+
+    let without_spacing = aggregate_matches(&results, FRAME_SECONDS, None).unwrap();
+    assert_eq!(without_spacing.consistent_votes, 4);
+    assert!(without_spacing.confidence > with_spacing.confidence);
+}
+
+#[test]
+fn no_matches_aggregate_to_none() {
+    assert!(aggregate_matches(&[None, None], FRAME_SECONDS, Some(3.0)).is_none());
+    assert!(aggregate_matches(&[], FRAME_SECONDS, None).is_none());
+}
@@ -0,0 +1,23 @@
+use sivana::database::{open_bulk_db_connection, open_db_connection};
+
+#[test]
+fn bulk_connection_relaxes_sync_but_default_stays_durable() {
+    let dir = std::env::temp_dir().join(format!("sivana-bulk-{}", std::process::id()));
+    let path = dir.join("library.sqlite");
+
+    let pragma = |conn: &rusqlite::Connection, name: &str| -> i64 {
+        conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0)).unwrap()
+    };
+    {
+        let bulk = open_bulk_db_connection(&path).unwrap();
+        assert_eq!(pragma(&bulk, "synchronous"), 1); // NORMAL
+        assert_eq!(pragma(&bulk, "cache_size"), -64000);
+        assert_eq!(pragma(&bulk, "temp_store"), 2); // MEMORY
+    }
+    {
+        // Per-connection settings: reopening normally brings back the durable defaults.
+        let normal = open_db_connection(&path).unwrap();
+        assert_eq!(pragma(&normal, "synchronous"), 2); // FULL
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::hashing::Fingerprint;
+use sivana::spectrogram::SpectrogramBuilder;
+use sivana::Fingerprinter;
+
+#[test]
+fn centered_frames_match_manually_reflect_padded_signal() {
+    let samples = synthetic_samples(22050, 1);
+    let (window, hop) = (1024, 256);
+    let half = window / 2;
+    let mut padded: Vec<f32> = (1..=half).rev().map(|k| samples[k]).collect();
+    padded.extend_from_slice(&samples);
+    padded.extend((1..=half).map(|k| samples[samples.len() - 1 - k]));
+
+    let centered = SpectrogramBuilder::new(window).with_center(true).build(&samples, hop);
+    assert_eq!(centered, SpectrogramBuilder::new(window).build(&padded, hop));
+    // One frame centered on every hop, including sample 0.
+    assert_eq!(centered.len(), samples.len() / hop + 1);
+}
+
+#[test]
+fn streaming_centered_fingerprints_match_whole_signal() {
+    let mut fingerprinter = Fingerprinter::default();
+    fingerprinter.centered_frames = true;
+    let samples = synthetic_samples(fingerprinter.sample_rate, 4);
+    let samples = &samples[..samples.len() - 333];
+
+    for pad_final_frame in [false, true] {
+        fingerprinter.pad_final_frame = pad_final_frame;
+        let expected = fingerprinter.fingerprint(samples);
+        assert!(!expected.is_empty());
+
+        // Smaller than the start padding needs, between that and a window, and all at once.
+        for chunk_size in [300, 1500, samples.len()] {
+            let mut stream = fingerprinter.fingerprint_stream();
+            let mut streamed: Vec<Fingerprint> = samples.chunks(chunk_size).flat_map(|chunk| stream.push_samples(chunk)).collect();
+            streamed.extend(stream.finish());
+            assert_eq!(streamed, expected, "chunk size {}, padding {}", chunk_size, pad_final_frame);
+        }
+    }
+}
+
+#[test]
+fn centered_enroll_and_query_agree_on_offset() {
+    let mut fingerprinter = Fingerprinter::default();
+    fingerprinter.centered_frames = true;
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+
+    let start_frame = 200;
+    let start = start_frame * fingerprinter.hop_size;
+    let result = fingerprinter.identify(&conn, &song[start..start + 6 * fingerprinter.sample_rate as usize], 20).unwrap();
+    assert_eq!(result.song_id, song_id);
+    assert!(result.time_offset_in_song_frames.abs_diff(start_frame as isize) <= 1);
+}
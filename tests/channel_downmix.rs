@@ -0,0 +1,37 @@
+use sivana::audio_loader::{downmix_interleaved, ChannelMode};
+
+// Three interleaved channels, two frames: (0.3, 0.6, 0.9) and (-0.3, 0.0, 0.6)
+const THREE_CHANNEL: [f32; 6] = [0.3, 0.6, 0.9, -0.3, 0.0, 0.6];
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+    }
+}
+
+#[test]
+fn average_mode_uses_all_three_channels() {
+    let mono = downmix_interleaved(&THREE_CHANNEL, 3, ChannelMode::Average).unwrap();
+    assert_close(&mono, &[0.6, 0.1]);
+}
+
+#[test]
+fn average_mode_folds_four_channel_surround() {
+    let quad = [0.4, 0.0, -0.4, 0.8, 1.0, 1.0, 1.0, 1.0, -0.2, 0.2, -0.6, 0.2];
+    let mono = downmix_interleaved(&quad, 4, ChannelMode::Average).unwrap();
+    assert_close(&mono, &[0.2, 1.0, -0.1]);
+}
+
+#[test]
+fn explicit_channel_modes_pick_one_channel() {
+    assert_close(&downmix_interleaved(&THREE_CHANNEL, 3, ChannelMode::Left).unwrap(), &[0.3, -0.3]);
+    assert_close(&downmix_interleaved(&THREE_CHANNEL, 3, ChannelMode::Right).unwrap(), &[0.6, 0.0]);
+    assert_close(&downmix_interleaved(&THREE_CHANNEL, 3, ChannelMode::Channel(2)).unwrap(), &[0.9, 0.6]);
+}
+
+#[test]
+fn out_of_range_channel_is_an_error() {
+    assert!(downmix_interleaved(&THREE_CHANNEL, 3, ChannelMode::Channel(3)).is_err());
+    assert!(downmix_interleaved(&[0.1, 0.2], 1, ChannelMode::Channel(1)).is_err());
+}
@@ -0,0 +1,33 @@
+// Shared helpers for integration tests. Each test crate only uses some of them.
+#![allow(dead_code)]
+
+use std::f32::consts::PI;
+
+/// Stepped two-tone audio: the pair of frequencies changes every quarter second,
+/// giving a dense, reproducible constellation without needing audio fixtures.
+pub fn synthetic_samples(sample_rate: u32, seconds: usize) -> Vec<f32> {
+    let step = sample_rate as usize / 4;
+    (0..sample_rate as usize * seconds)
+        .map(|i| {
+            let segment = (i / step) as f32;
+            let f1 = 300.0 + 170.0 * (segment % 13.0);
+            let f2 = 1200.0 + 230.0 * (segment % 7.0);
+            let t = i as f32 / sample_rate as f32;
+            0.5 * (2.0 * PI * f1 * t).sin() + 0.3 * (2.0 * PI * f2 * t).sin()
+        })
+        .collect()
+}
+
+/// Like `synthetic_samples` but with a different tone sequence, so the two never align.
+pub fn other_synthetic_samples(sample_rate: u32, seconds: usize) -> Vec<f32> {
+    let step = sample_rate as usize / 5;
+    (0..sample_rate as usize * seconds)
+        .map(|i| {
+            let segment = (i / step) as f32;
+            let f1 = 410.0 + 130.0 * (segment % 11.0);
+            let f2 = 1700.0 + 190.0 * (segment % 5.0);
+            let t = i as f32 / sample_rate as f32;
+            0.4 * (2.0 * PI * f1 * t).sin() + 0.4 * (2.0 * PI * f2 * t).sin()
+        })
+        .collect()
+}
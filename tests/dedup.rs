@@ -0,0 +1,49 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::{
+    duplicate_groups, find_duplicate_songs, get_song_fingerprints, open_in_memory_connection, DuplicatePair, DEFAULT_MIN_MATCH_SCORE,
+};
+use sivana::Fingerprinter;
+
+#[test]
+fn stored_fingerprints_round_trip() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 10);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+    assert_eq!(get_song_fingerprints(&conn, song_id).unwrap(), fingerprinter.fingerprint(&song));
+    assert!(get_song_fingerprints(&conn, song_id + 1).unwrap().is_empty());
+}
+
+#[test]
+fn copies_and_excerpts_of_a_song_form_one_group() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    let rate = fingerprinter.sample_rate as usize;
+    let original = fingerprinter.enroll(&mut conn, "original", Some("original.wav"), &tags, &song).unwrap();
+    let other = fingerprinter.enroll(&mut conn, "other", Some("other.wav"), &tags, &other_synthetic_samples(fingerprinter.sample_rate, 20)).unwrap();
+    let copy = fingerprinter.enroll(&mut conn, "copy", Some("copy.wav"), &tags, &song).unwrap();
+    let excerpt = fingerprinter.enroll(&mut conn, "excerpt", Some("excerpt.wav"), &tags, &song[5 * rate..15 * rate]).unwrap();
+
+    let pairs = find_duplicate_songs(&conn, DEFAULT_MIN_MATCH_SCORE).unwrap();
+    assert!(pairs.iter().all(|p| p.song_id < p.other_song_id && p.score >= DEFAULT_MIN_MATCH_SCORE));
+    assert!(pairs.iter().all(|p| p.song_id != other && p.other_song_id != other));
+    // The full copy matches the original on every fingerprint, so it ranks first.
+    assert_eq!((pairs[0].song_id, pairs[0].other_song_id), (original, copy));
+    assert_eq!(pairs[0].confidence, 1.0);
+    assert_eq!(duplicate_groups(&pairs), [vec![original, copy, excerpt]]);
+
+    assert!(find_duplicate_songs(&conn, usize::MAX).unwrap().is_empty());
+}
+
+#[test]
+fn groups_follow_chains_of_pairs() {
+    let pair = |song_id, other_song_id| DuplicatePair { song_id, other_song_id, score: 100, confidence: 1.0 };
+    let pairs = [pair(7, 9), pair(1, 4), pair(4, 9), pair(2, 3)];
+    assert_eq!(duplicate_groups(&pairs), [vec![1, 4, 7, 9], vec![2, 3]]);
+    assert!(duplicate_groups(&[]).is_empty());
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::{load_audio_from_reader, AudioTags};
+use sivana::database::{open_in_memory_connection, query_db_and_match, query_db_closest_candidates, QueryOptions};
+use sivana::hashing::Fingerprint;
+use sivana::Fingerprinter;
+
+// Mono 16-bit PCM WAV in memory, at the fingerprinter's rate so no resampling happens.
+fn mono_wav_bytes(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data: Vec<u8> = samples.iter().flat_map(|s| ((s.clamp(-1.0, 1.0) * 32767.0) as i16).to_le_bytes()).collect();
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend((36 + data.len() as u32).to_le_bytes());
+    bytes.extend(b"WAVEfmt ");
+    bytes.extend(16u32.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(sample_rate.to_le_bytes());
+    bytes.extend((sample_rate * 2).to_le_bytes());
+    bytes.extend(2u16.to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"data");
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend(data);
+    bytes
+}
+
+#[test]
+fn sub_segment_decoded_from_reader_matches_enrolled_song_and_offset() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    let other = other_synthetic_samples(fingerprinter.sample_rate, 20);
+    fingerprinter.enroll(&mut conn, "other", Some("other.wav"), &tags, &other).unwrap();
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &tags, &song).unwrap();
+
+    // Six seconds starting on a frame boundary, so the expected offset is exact.
+    let start_frame = 300;
+    let start = start_frame * fingerprinter.hop_size;
+    let snippet = &song[start..start + 6 * fingerprinter.sample_rate as usize];
+    let wav = mono_wav_bytes(snippet, fingerprinter.sample_rate);
+    let decoded = load_audio_from_reader(Cursor::new(wav), Some("wav"), fingerprinter.sample_rate).unwrap();
+    assert_eq!(decoded.len(), snippet.len());
+
+    let result = fingerprinter.identify(&conn, &decoded, 20).expect("snippet should match");
+    assert_eq!(result.song_id, song_id);
+    assert!(
+        result.time_offset_in_song_frames.abs_diff(start_frame as isize) <= 1,
+        "offset {} frames, expected about {}", result.time_offset_in_song_frames, start_frame
+    );
+    let expected_seconds = fingerprinter.frames_to_seconds(start_frame as isize);
+    assert!((result.match_start_seconds - expected_seconds).abs() < 0.1);
+}
+
+#[test]
+fn audio_not_in_database_does_not_match() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+
+    let unknown = other_synthetic_samples(fingerprinter.sample_rate, 6);
+    assert!(fingerprinter.identify(&conn, &unknown, 20).is_none());
+}
+
+#[test]
+fn closest_candidate_is_reported_when_score_is_below_threshold() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+
+    let start = 100 * fingerprinter.hop_size;
+    let query = fingerprinter.fingerprint(&song[start..start + 3 * fingerprinter.sample_rate as usize]);
+    let frame_duration = fingerprinter.frame_duration_seconds();
+    let unreachable_score = query.len() + 1;
+    let unverified = QueryOptions { min_query_coverage: None, verify_min_fraction: None, ..fingerprinter.query_options(unreachable_score) };
+    assert!(query_db_and_match(&conn, &query, &unverified).is_none());
+
+    let closest = query_db_closest_candidates(&conn, &query, 1, frame_duration, None);
+    assert_eq!(closest.len(), 1);
+    assert_eq!(closest[0].song_id, song_id);
+    assert!(closest[0].score > 0 && closest[0].score < unreachable_score);
+}
+
+#[test]
+fn query_mostly_missing_from_database_is_rejected_by_coverage() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+
+    // A real excerpt buried among ten times as many fingerprints the database has never seen.
+    let start = 100 * fingerprinter.hop_size;
+    let mut query = fingerprinter.fingerprint(&song[start..start + 3 * fingerprinter.sample_rate as usize]);
+    let excerpt_len = query.len();
+    query.extend((0..excerpt_len * 10).map(|i| Fingerprint {
+        hash: u64::MAX - i as u64,
+        anchor_time_idx: i % 100,
+        target_delta_frames: 5,
+        strength: 1,
+    }));
+    let options = QueryOptions { min_query_coverage: None, verify_min_fraction: None, ..fingerprinter.query_options(5) };
+
+    let unchecked = query_db_and_match(&conn, &query, &options).expect("excerpt should score");
+    assert_eq!(unchecked.song_id, song_id);
+    assert!(unchecked.query_coverage <= 1.0 / 11.0 + f32::EPSILON, "coverage {}", unchecked.query_coverage);
+    assert!(query_db_and_match(&conn, &query, &QueryOptions { min_query_coverage: Some(0.2), ..options }).is_none());
+    assert!(query_db_and_match(&conn, &query, &QueryOptions { min_query_coverage: Some(0.01), ..options }).is_some());
+}
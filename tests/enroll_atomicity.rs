@@ -0,0 +1,43 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::Fingerprinter;
+
+fn count(conn: &rusqlite::Connection, table: &str) -> i64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn failed_fingerprint_insert_rolls_back_song_row() {
+    let fingerprinter = Fingerprinter::default();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 5);
+    let mut conn = open_in_memory_connection().unwrap();
+
+    // Make every fingerprint insert fail after the song row has been written.
+    conn.execute_batch(
+        "CREATE TRIGGER fail_fingerprint_insert BEFORE INSERT ON fingerprints
+         BEGIN SELECT RAISE(ABORT, 'simulated fingerprint insert failure'); END;",
+    ).unwrap();
+
+    let result = fingerprinter.enroll(&mut conn, "doomed", Some("doomed.wav"), &AudioTags::default(), &samples);
+    assert!(result.is_err());
+    assert_eq!(count(&conn, "songs"), 0);
+    assert_eq!(count(&conn, "fingerprints"), 0);
+}
+
+#[test]
+fn re_enrolling_same_path_keeps_song_id_and_replaces_fingerprints() {
+    let fingerprinter = Fingerprinter::default();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 5);
+    let mut conn = open_in_memory_connection().unwrap();
+
+    let first = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &samples).unwrap();
+    let fingerprints_after_first = count(&conn, "fingerprints");
+    let second = fingerprinter.enroll(&mut conn, "renamed", Some("song.wav"), &AudioTags::default(), &samples).unwrap();
+
+    assert_eq!(first, second);
+    assert_eq!(count(&conn, "songs"), 1);
+    assert_eq!(count(&conn, "fingerprints"), fingerprints_after_first);
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::{Fingerprinter, SivanaError};
+
+#[test]
+fn audio_shorter_than_a_window_reports_no_spectrogram() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let samples = vec![0.0f32; fingerprinter.window_size / 2];
+
+    let result = fingerprinter.enroll(&mut conn, "tiny", Some("tiny.wav"), &AudioTags::default(), &samples);
+    assert!(matches!(result, Err(SivanaError::NoSpectrogram { ref song_name }) if song_name == "tiny"));
+}
+
+#[test]
+fn re_enrolling_same_audio_elsewhere_reports_duplicate() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 10);
+
+    let first = fingerprinter.enroll_unique(&mut conn, "original", Some("a.wav"), &AudioTags::default(), &samples).unwrap();
+    let second = fingerprinter.enroll_unique(&mut conn, "copy", Some("b.wav"), &AudioTags::default(), &samples);
+    match second {
+        Err(SivanaError::DuplicateDetected { existing_song_id }) => assert_eq!(existing_song_id, first),
+        other => panic!("expected DuplicateDetected, got {:?}", other),
+    }
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::{enroll_fingerprints_with_id, get_db_stats, list_songs, open_in_memory_connection};
+use sivana::export::{export_fingerprints, import_fingerprints_keeping_id};
+use sivana::Fingerprinter;
+
+#[test]
+fn enrolling_with_an_id_replaces_whatever_held_it() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let song = fingerprinter.fingerprint(&synthetic_samples(fingerprinter.sample_rate, 10));
+    let other = fingerprinter.fingerprint(&other_synthetic_samples(fingerprinter.sample_rate, 10));
+
+    assert_eq!(enroll_fingerprints_with_id(&mut conn, 4_000_000_000, "song", Some("song.wav"), &tags, &song).unwrap(), 4_000_000_000);
+    // The same call again is a no-op.
+    enroll_fingerprints_with_id(&mut conn, 4_000_000_000, "song", Some("song.wav"), &tags, &song).unwrap();
+    assert_eq!(get_db_stats(&conn).unwrap().fingerprint_count, song.len());
+
+    // Another song at the same ID, then the first song again at a new ID but its old path.
+    enroll_fingerprints_with_id(&mut conn, 4_000_000_000, "other", Some("other.wav"), &tags, &other).unwrap();
+    enroll_fingerprints_with_id(&mut conn, 7, "song", Some("other.wav"), &tags, &song).unwrap();
+    let songs = list_songs(&conn, None, None, None).unwrap();
+    assert_eq!(songs.iter().map(|s| (s.id, s.name.as_str())).collect::<Vec<_>>(), [(7, "song")]);
+    assert_eq!(get_db_stats(&conn).unwrap().fingerprint_count, song.len());
+}
+
+#[test]
+fn import_can_keep_the_exported_song_id() {
+    let dir = std::env::temp_dir().join(format!("sivana-explicit-ids-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fingerprinter = Fingerprinter::default();
+    let mut source = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 10);
+    enroll_fingerprints_with_id(&mut source, 42, "song", Some("song.wav"), &tags, &fingerprinter.fingerprint(&samples)).unwrap();
+    let export = dir.join("song.txt");
+    let count = export_fingerprints(&source, 42, &export).unwrap();
+
+    let mut target = open_in_memory_connection().unwrap();
+    fingerprinter.enroll(&mut target, "local", Some("local.wav"), &tags, &other_synthetic_samples(fingerprinter.sample_rate, 10)).unwrap();
+    assert_eq!(import_fingerprints_keeping_id(&mut target, &export).unwrap(), 42);
+    assert_eq!(import_fingerprints_keeping_id(&mut target, &export).unwrap(), 42);
+    assert_eq!(list_songs(&target, Some("song"), None, None).unwrap().len(), 1);
+    let matched = fingerprinter.identify(&target, &samples[..6 * fingerprinter.sample_rate as usize], 20).unwrap();
+    assert_eq!(matched.song_id, 42);
+    let song_rows: i64 = target.query_row("SELECT COUNT(*) FROM fingerprints WHERE song_id = 42", [], |row| row.get(0)).unwrap();
+    assert_eq!(song_rows as usize, count);
+
+    // IDs that don't fit a SongId are rejected before anything is written.
+    let text = std::fs::read_to_string(&export).unwrap();
+    let bad = dir.join("bad.txt");
+    for bad_id in ["-1", "4294967296", "abc"] {
+        std::fs::write(&bad, text.replace("# song_id: 42", &format!("# song_id: {}", bad_id))).unwrap();
+        assert!(import_fingerprints_keeping_id(&mut target, &bad).is_err(), "{}", bad_id);
+    }
+    std::fs::write(&bad, text.replace("# song_id: 42\n", "")).unwrap();
+    assert!(import_fingerprints_keeping_id(&mut target, &bad).is_err());
+    assert_eq!(list_songs(&target, None, None, None).unwrap().len(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::{
+    attach_database, database_file, fingerprint_databases, get_song_info_in, init_db, open_db_connection,
+    open_in_memory_connection,
+};
+use sivana::Fingerprinter;
+
+#[test]
+fn query_searches_attached_databases_and_reports_the_source() {
+    let dir = std::env::temp_dir().join(format!("sivana-federated-{}", std::process::id()));
+    let fingerprinter = Fingerprinter::default();
+    let tags = AudioTags::default();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+
+    // Both databases start numbering at 1, so the songs share an ID.
+    let rock_path = dir.join("rock.sqlite");
+    let mut rock = open_db_connection(&rock_path).unwrap();
+    init_db(&rock).unwrap();
+    let song_id = fingerprinter.enroll(&mut rock, "song", Some("song.wav"), &tags, &song).unwrap();
+    drop(rock);
+
+    let mut main = open_in_memory_connection().unwrap();
+    let other_id = fingerprinter.enroll(&mut main, "other", Some("other.wav"), &tags, &other_synthetic_samples(fingerprinter.sample_rate, 20)).unwrap();
+    assert_eq!(other_id, song_id);
+
+    let snippet = &song[100 * fingerprinter.hop_size..][..6 * fingerprinter.sample_rate as usize];
+    assert!(fingerprinter.identify(&main, snippet, 20).is_none());
+
+    assert_eq!(attach_database(&main, &rock_path).unwrap(), "source1");
+    assert_eq!(fingerprint_databases(&main).unwrap(), ["main", "source1"]);
+    let found = fingerprinter.identify(&main, snippet, 20).expect("attached song should match");
+    assert_eq!((found.source_db, found.song_id), (1, song_id));
+    assert_eq!(get_song_info_in(&main, found.source_db, found.song_id).unwrap().unwrap().name, "song");
+    let file = database_file(&main, found.source_db).unwrap().unwrap();
+    assert!(file.ends_with("rock.sqlite"), "{}", file);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn attaching_requires_an_existing_fingerprint_database() {
+    let dir = std::env::temp_dir().join(format!("sivana-federated-bad-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let conn = open_in_memory_connection().unwrap();
+
+    let missing = dir.join("missing.sqlite");
+    assert!(attach_database(&conn, &missing).is_err());
+    assert!(!missing.exists());
+
+    let not_fingerprints = dir.join("notes.txt");
+    std::fs::write(&not_fingerprints, b"").unwrap();
+    assert!(attach_database(&conn, &not_fingerprints).is_err());
+    assert_eq!(fingerprint_databases(&conn).unwrap(), ["main"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
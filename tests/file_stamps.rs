@@ -0,0 +1,39 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::{find_unchanged_song, open_in_memory_connection, set_song_file_stamp, FileStamp};
+use sivana::Fingerprinter;
+
+const STAMP: FileStamp = FileStamp { size_bytes: 1234, modified_unix_ns: 1_700_000_000_000_000_000 };
+
+#[test]
+fn unchanged_stamp_finds_song_and_any_change_does_not() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 5);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &samples).unwrap();
+
+    // Enrolled without a stamp: never considered unchanged.
+    assert_eq!(find_unchanged_song(&conn, "song.wav", STAMP).unwrap(), None);
+
+    assert!(set_song_file_stamp(&conn, song_id, STAMP).unwrap());
+    assert_eq!(find_unchanged_song(&conn, "song.wav", STAMP).unwrap(), Some(song_id));
+    let resized = FileStamp { size_bytes: 1235, ..STAMP };
+    let touched = FileStamp { modified_unix_ns: STAMP.modified_unix_ns + 1, ..STAMP };
+    assert_eq!(find_unchanged_song(&conn, "song.wav", resized).unwrap(), None);
+    assert_eq!(find_unchanged_song(&conn, "song.wav", touched).unwrap(), None);
+    assert_eq!(find_unchanged_song(&conn, "other.wav", STAMP).unwrap(), None);
+}
+
+#[test]
+fn re_enrolling_a_path_clears_its_stamp() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 5);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &samples).unwrap();
+    set_song_file_stamp(&conn, song_id, STAMP).unwrap();
+
+    fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &samples).unwrap();
+    assert_eq!(find_unchanged_song(&conn, "song.wav", STAMP).unwrap(), None);
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::synthetic_samples;
+use rusqlite::{params, Connection};
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::Fingerprinter;
+
+fn stored_fingerprints(conn: &Connection, song_id: u32) -> Vec<(i64, i64)> {
+    let mut stmt = conn
+        .prepare("SELECT hash, anchor_time_idx FROM fingerprints WHERE song_id = ?1 ORDER BY rowid")
+        .unwrap();
+    stmt.query_map(params![song_id as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn enrolling_same_audio_twice_stores_identical_fingerprints() {
+    let fingerprinter = Fingerprinter::default();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 10);
+    let mut conn = open_in_memory_connection().unwrap();
+
+    let first = fingerprinter.enroll(&mut conn, "first", Some("first.wav"), &AudioTags::default(), &samples).unwrap();
+    let second = fingerprinter.enroll(&mut conn, "second", Some("second.wav"), &AudioTags::default(), &samples).unwrap();
+    assert_ne!(first, second);
+
+    let first_fps = stored_fingerprints(&conn, first);
+    let second_fps = stored_fingerprints(&conn, second);
+    assert!(!first_fps.is_empty());
+    assert_eq!(first_fps, second_fps);
+}
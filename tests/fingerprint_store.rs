@@ -0,0 +1,52 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::store::FingerprintStore;
+use sivana::Fingerprinter;
+
+/// Enrolls two songs, then checks metadata, lookups and identification through the trait only.
+fn exercise_store<S: FingerprintStore>(store: &mut S) {
+    let fingerprinter = Fingerprinter::default();
+    let rate = fingerprinter.sample_rate;
+    let first = synthetic_samples(rate, 12);
+    let tags = AudioTags { artist: Some("Artist".to_string()), ..AudioTags::default() };
+
+    let first_id = fingerprinter.enroll_in_store(store, "first", Some("first.wav"), &tags, &first).unwrap();
+    let second_id = fingerprinter
+        .enroll_in_store(store, "second", Some("second.wav"), &AudioTags::default(), &other_synthetic_samples(rate, 12))
+        .unwrap();
+    assert_ne!(first_id, second_id);
+    assert!(fingerprinter.enroll_in_store(store, "again", Some("first.wav"), &tags, &first).is_err());
+
+    let song = store.song_info(first_id).unwrap().unwrap();
+    assert_eq!((song.name.as_str(), song.artist.as_deref()), ("first", Some("Artist")));
+    assert!(store.song_info(9999).unwrap().is_none());
+
+    let some_hash = fingerprinter.fingerprint(&first)[0].hash;
+    assert!(store.lookup(some_hash).unwrap().iter().any(|&(song_id, _, _)| song_id == first_id));
+
+    let offset_frames = 50;
+    let snippet = &first[offset_frames * fingerprinter.hop_size..][..rate as usize * 5];
+    let best = fingerprinter.identify_in_store(store, snippet, 20).unwrap().unwrap();
+    assert_eq!((best.song_id, best.time_offset_in_song_frames), (first_id, offset_frames as isize));
+}
+
+#[test]
+fn sqlite_connection_is_a_fingerprint_store() {
+    let mut conn = open_in_memory_connection().unwrap();
+    exercise_store(&mut conn);
+}
+
+#[cfg(feature = "sled")]
+#[test]
+fn sled_store_behaves_like_sqlite() {
+    let dir = std::env::temp_dir().join(format!("sivana-sled-{}", std::process::id()));
+    {
+        let mut store = sivana::store::SledStore::open(&dir).unwrap();
+        exercise_store(&mut store);
+        store.flush().unwrap();
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
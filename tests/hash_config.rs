@@ -0,0 +1,41 @@
+use sivana::hashing::{create_hashes, HashConfig, TargetZone};
+use sivana::peaks::Peak;
+
+fn single_pair_hash(anchor_bin: usize, target_bin: usize, hash_config: HashConfig) -> u64 {
+    let peaks = [
+        Peak { time_idx: 0, freq_bin_idx: anchor_bin, magnitude: 1.0 },
+        Peak { time_idx: 3, freq_bin_idx: target_bin, magnitude: 1.0 },
+    ];
+    let fingerprints = create_hashes(&peaks, TargetZone::new(1, 50, 2048, 5).unwrap(), hash_config);
+    assert_eq!(fingerprints.len(), 1);
+    fingerprints[0].hash
+}
+
+#[test]
+fn eleven_freq_bits_separate_bins_1025_and_1() {
+    let wide = HashConfig::new(11, 8).unwrap();
+    assert_ne!(single_pair_hash(1025, 700, wide), single_pair_hash(1, 700, wide));
+}
+
+#[test]
+fn default_ten_freq_bits_wrap_bin_1025_onto_1() {
+    let narrow = HashConfig::default();
+    assert_eq!(single_pair_hash(1025, 700, narrow), single_pair_hash(1, 700, narrow));
+}
+
+#[test]
+fn hash_config_rejects_layouts_wider_than_u64() {
+    assert!(HashConfig::new(28, 8).is_ok());
+    assert!(HashConfig::new(29, 8).is_err());
+    assert!(HashConfig::new(0, 8).is_err());
+    assert!(HashConfig::new(u32::MAX / 2 + 1, 8).is_err());
+    assert!(HashConfig::new(1, u32::MAX - 1).is_err());
+}
+
+#[test]
+fn target_zone_rejects_empty_time_range_and_zero_pairs() {
+    assert!(TargetZone::new(10, 5, 200, 5).is_err());
+    assert!(TargetZone::new(1, 50, 200, 0).is_err());
+    let zone = TargetZone::new(5, 5, 0, 1).unwrap();
+    assert_eq!((zone.dt_min_frames(), zone.dt_max_frames(), zone.df_abs_max_bins(), zone.max_pairs_per_anchor()), (5, 5, 0, 1));
+}
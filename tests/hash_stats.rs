@@ -0,0 +1,40 @@
+mod common;
+
+use sivana::hashing::{create_hashes, create_hashes_with_stats, HashConfig, HashStats, TargetZone};
+use sivana::peaks::Peak;
+
+// One peak per frame, so anchor i can pair with every later peak up to dt_max frames away.
+fn peak_per_frame(frames: usize) -> Vec<Peak> {
+    (0..frames).map(|t| Peak { time_idx: t, freq_bin_idx: 100 + (t * 7) % 50, magnitude: 1.0 }).collect()
+}
+
+#[test]
+fn stats_count_anchors_pairs_and_cap_hits() {
+    let peaks = peak_per_frame(20);
+    let zone = TargetZone::new(1, 10, 200, 3).unwrap();
+    let (fingerprints, stats) = create_hashes_with_stats(&peaks, zone, HashConfig::default());
+
+    assert_eq!(fingerprints, create_hashes(&peaks, zone, HashConfig::default()));
+    // The last three anchors have only 2, 1 and 0 later peaks to pair with.
+    assert_eq!(stats, HashStats { anchors_considered: 20, pairs_emitted: 17 * 3 + 2 + 1, anchors_hitting_cap: 17 });
+}
+
+#[test]
+fn generous_cap_is_never_reached() {
+    let peaks = peak_per_frame(20);
+    let (_, stats) = create_hashes_with_stats(&peaks, TargetZone::new(1, 5, 200, 10).unwrap(), HashConfig::default());
+    assert_eq!(stats.anchors_hitting_cap, 0);
+    assert_eq!(stats.anchors_considered, 20);
+}
+
+#[test]
+fn fingerprinter_stats_match_plain_fingerprints() {
+    let fingerprinter = sivana::Fingerprinter::default();
+    let samples = common::synthetic_samples(fingerprinter.sample_rate, 5);
+    let (fingerprints, stats) = fingerprinter.fingerprint_with_stats(&samples);
+
+    assert_eq!(fingerprints, fingerprinter.fingerprint(&samples));
+    assert_eq!(stats.hashes.pairs_emitted, fingerprints.len());
+    assert_eq!(stats.hashes.anchors_considered, stats.peaks);
+    assert!(stats.frames > 0 && stats.peaks > 0);
+}
@@ -0,0 +1,54 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use sivana::audio_loader::{load_audio_file, load_audio_from_reader, load_wav_fast};
+use sivana::{LoadError, SivanaError};
+
+fn write_temp(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("sivana-load-errors-{}-{}", std::process::id(), name));
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+// Header of a mono 16-bit 22050 Hz WAV whose data chunk claims `data_len` bytes.
+fn wav_header(format_tag: u16, data_len: u32) -> Vec<u8> {
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend((36 + data_len).to_le_bytes());
+    bytes.extend(b"WAVEfmt ");
+    bytes.extend(16u32.to_le_bytes());
+    bytes.extend(format_tag.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(22050u32.to_le_bytes());
+    bytes.extend((22050u32 * 2).to_le_bytes());
+    bytes.extend(2u16.to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"data");
+    bytes.extend(data_len.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn missing_files_are_reported_as_not_found() {
+    let missing = std::env::temp_dir().join(format!("sivana-load-errors-{}-missing.wav", std::process::id()));
+    for result in [load_audio_file(&missing, 22050), load_wav_fast(&missing, 22050)] {
+        match result {
+            Err(SivanaError::Load(LoadError::FileNotFound { path })) => assert_eq!(path, missing),
+            other => panic!("expected FileNotFound, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn unsupported_and_empty_audio_are_told_apart() {
+    let text = write_temp("notes.mp3", b"these are not the samples you are looking for");
+    let result = load_audio_file(&text, 22050);
+    assert!(matches!(result, Err(SivanaError::Load(LoadError::UnsupportedFormat(_)))), "{:?}", result);
+    std::fs::remove_file(&text).unwrap();
+
+    // A valid WAV header with an unknown codec (format tag 0x1234).
+    let result = load_audio_from_reader(Cursor::new(wav_header(0x1234, 4)), Some("wav"), 22050);
+    assert!(matches!(result, Err(SivanaError::Load(LoadError::UnsupportedFormat(_)))), "{:?}", result);
+
+    let result = load_audio_from_reader(Cursor::new(wav_header(1, 0)), Some("wav"), 22050);
+    assert!(matches!(result, Err(SivanaError::Load(LoadError::Empty))), "{:?}", result);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::{open_in_memory_connection, query_db_and_match_topn, QueryOptions};
+use sivana::hashing::{pair_strength, Fingerprint};
+use sivana::matching::OffsetHistogram;
+use sivana::peaks::Peak;
+use sivana::Fingerprinter;
+
+fn peak(magnitude: f32) -> Peak {
+    Peak { time_idx: 0, freq_bin_idx: 0, magnitude }
+}
+
+#[test]
+fn pair_strength_follows_the_weaker_peak_on_a_log_scale() {
+    assert_eq!(pair_strength(&peak(0.5), &peak(1000.0)), 1);
+    assert_eq!(pair_strength(&peak(-20.0), &peak(-3.0)), 1);
+    assert_eq!(pair_strength(&peak(f32::NAN), &peak(f32::NAN)), 1);
+    assert_eq!(pair_strength(&peak(64.0), &peak(1000.0)), pair_strength(&peak(1000.0), &peak(64.0)));
+    assert!(pair_strength(&peak(128.0), &peak(128.0)) > pair_strength(&peak(64.0), &peak(128.0)));
+    assert_eq!(pair_strength(&peak(f32::MAX), &peak(f32::MAX)), 255);
+}
+
+#[test]
+fn weighting_prefers_fewer_strong_hits_over_more_weak_ones() {
+    let fp = |hash: u64, strength: u8| Fingerprint { hash, anchor_time_idx: 0, target_delta_frames: 1, strength };
+    let query = [fp(1, 20), fp(2, 20), fp(3, 2), fp(4, 2), fp(5, 2)];
+    let hits = |histogram: &mut OffsetHistogram| {
+        // Song 1 holds the two strong landmarks, song 2 the three weak ones.
+        for hash in [1, 2] {
+            histogram.add_with_strength(0, (hash, 1, 10, Some(1)), Some(20));
+        }
+        for hash in [3, 4, 5] {
+            histogram.add_with_strength(0, (hash, 2, 10, Some(1)), Some(2));
+        }
+    };
+
+    let mut plain = OffsetHistogram::new(&query);
+    hits(&mut plain);
+    let plain = plain.into_candidates(2, 1, 0.1);
+    assert_eq!(plain[0].song_id, 2);
+    assert_eq!(plain[0].weighted_score, plain[0].score);
+
+    let mut weighted = OffsetHistogram::new(&query).with_magnitude_weighting(true);
+    hits(&mut weighted);
+    let weighted = weighted.into_candidates(2, 1, 0.1);
+    assert_eq!((weighted[0].song_id, weighted[0].score, weighted[0].weighted_score), (1, 2, 40));
+    assert_eq!((weighted[1].song_id, weighted[1].score, weighted[1].weighted_score), (2, 3, 6));
+    // The raw hit count is still what min_score applies to.
+    let mut thresholded = OffsetHistogram::new(&query).with_magnitude_weighting(true);
+    hits(&mut thresholded);
+    assert_eq!(thresholded.into_candidates(2, 3, 0.1).iter().map(|c| c.song_id).collect::<Vec<_>>(), [2]);
+}
+
+#[test]
+fn enrolled_strengths_are_used_by_weighted_database_queries() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 12);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+    let unknown_strengths: i64 = conn.query_row("SELECT COUNT(*) FROM fingerprints WHERE strength IS NULL", [], |row| row.get(0)).unwrap();
+    assert_eq!(unknown_strengths, 0);
+
+    let query = fingerprinter.fingerprint(&song[100 * fingerprinter.hop_size..][..fingerprinter.sample_rate as usize * 5]);
+    let options = fingerprinter.query_options(20);
+    let plain = &query_db_and_match_topn(&conn, &query, 1, &options)[0];
+    let weighted = &query_db_and_match_topn(&conn, &query, 1, &QueryOptions { weight_by_magnitude: true, ..options })[0];
+    assert_eq!((plain.song_id, weighted.song_id), (song_id, song_id));
+    assert_eq!(plain.time_offset_in_song_frames, weighted.time_offset_in_song_frames);
+    assert_eq!(plain.weighted_score, plain.score);
+    assert!(weighted.weighted_score > weighted.score);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::{attach_database, ensure_indexes, init_db, open_db_connection, open_in_memory_connection};
+use sivana::Fingerprinter;
+
+fn index_names(conn: &rusqlite::Connection) -> Vec<String> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = 'fingerprints' ORDER BY name")
+        .unwrap();
+    stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+}
+
+#[test]
+fn missing_indexes_are_rebuilt_on_populated_tables() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 10);
+    fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+    assert_eq!(index_names(&conn), ["idx_fingerprints_song_id", "idx_fp_hash_cover"]);
+    assert!(ensure_indexes(&conn).unwrap().is_empty());
+
+    conn.execute_batch("DROP INDEX idx_fp_hash_cover;").unwrap();
+    assert_eq!(ensure_indexes(&conn).unwrap(), ["idx_fp_hash_cover"]);
+    assert_eq!(index_names(&conn), ["idx_fingerprints_song_id", "idx_fp_hash_cover"]);
+
+    // A database from before the covering index, with only the old single-column one.
+    conn.execute_batch("DROP INDEX idx_fp_hash_cover; DROP INDEX idx_fingerprints_song_id; CREATE INDEX idx_fingerprints_hash ON fingerprints (hash);").unwrap();
+    init_db(&conn).unwrap();
+    assert_eq!(index_names(&conn), ["idx_fingerprints_song_id", "idx_fp_hash_cover"]);
+    let snippet = &song[50 * fingerprinter.hop_size..][..5 * fingerprinter.sample_rate as usize];
+    assert_eq!(fingerprinter.identify(&conn, snippet, 20).unwrap().time_offset_in_song_frames, 50);
+}
+
+#[test]
+fn attaching_an_unindexed_database_still_works() {
+    let dir = std::env::temp_dir().join(format!("sivana-missing-indexes-{}", std::process::id()));
+    let fingerprinter = Fingerprinter::default();
+    let song = synthetic_samples(fingerprinter.sample_rate, 10);
+    let path = dir.join("old.sqlite");
+    let mut old = open_db_connection(&path).unwrap();
+    init_db(&old).unwrap();
+    fingerprinter.enroll(&mut old, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+    old.execute_batch("DROP INDEX idx_fp_hash_cover; DROP INDEX idx_fingerprints_song_id;").unwrap();
+    drop(old);
+
+    // Attaching only warns and leaves the file alone.
+    let main = open_in_memory_connection().unwrap();
+    attach_database(&main, &path).unwrap();
+    let snippet = &song[50 * fingerprinter.hop_size..][..5 * fingerprinter.sample_rate as usize];
+    assert_eq!(fingerprinter.identify(&main, snippet, 20).unwrap().source_db, 1);
+    drop(main);
+    assert!(index_names(&open_db_connection(&path).unwrap()).is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "ndarray")]
+
+mod common;
+
+use common::synthetic_samples;
+use ndarray::Array2;
+use sivana::peaks::{find_peaks, find_peaks_array, Peak, PeakParams};
+use sivana::spectrogram::{create_spectrogram, create_spectrogram_ndarray, SpectrogramBuilder};
+
+const PARAMS: PeakParams =
+    PeakParams { time_radius: 2, freq_radius: 5, min_magnitude: 1.0, max_peaks_per_frame: Some(5), min_frame_energy: 0.0 };
+
+fn positions(peaks: &[Peak]) -> Vec<(usize, usize, f32)> {
+    peaks.iter().map(|p| (p.time_idx, p.freq_bin_idx, p.magnitude)).collect()
+}
+
+#[test]
+fn array_spectrogram_holds_the_same_frames_as_the_nested_one() {
+    let samples = synthetic_samples(22050, 3);
+    let builder = SpectrogramBuilder::new(1024);
+    let frames = builder.build(&samples, 512);
+    let array = builder.build_array(&samples, 512);
+    assert_eq!(array.dim(), (frames.len(), 513));
+    for (row, frame) in array.rows().into_iter().zip(&frames) {
+        assert_eq!(row.to_vec(), *frame);
+    }
+    assert_eq!(create_spectrogram_ndarray(&samples, 22050, 1024, 512), array);
+    assert_eq!(create_spectrogram(&samples, 22050, 1024, 512).len(), frames.len());
+
+    let empty = builder.build_array(&samples[..100], 512);
+    assert_eq!(empty.nrows(), 0);
+    assert!(find_peaks_array(empty.view(), PeakParams { min_magnitude: 1.0, min_frame_energy: 0.0, ..PeakParams::default() }).is_empty());
+}
+
+#[test]
+fn array_peak_picking_matches_the_nested_version_for_any_layout() {
+    let samples = synthetic_samples(22050, 3);
+    let builder = SpectrogramBuilder::new(1024);
+    let frames = builder.build(&samples, 512);
+    let expected = find_peaks(&frames, PARAMS);
+    assert!(!expected.is_empty());
+
+    let array = builder.build_array(&samples, 512);
+    assert_eq!(positions(&find_peaks_array(array.view(), PARAMS)), positions(&expected));
+
+    // A column-major copy is not contiguous row by row but must give the same peaks.
+    let mut transposed = Array2::<f32>::zeros((array.ncols(), array.nrows()));
+    transposed.assign(&array.t());
+    assert_eq!(positions(&find_peaks_array(transposed.t(), PARAMS)), positions(&expected));
+}
@@ -0,0 +1,69 @@
+use sivana::spectrogram::{window_sum, MagnitudeScale, SpectrogramBuilder, WindowType};
+use sivana::Fingerprinter;
+
+const SAMPLE_RATE: f32 = 22050.0;
+
+fn tone(amplitude: f32, hz: f32, len: usize) -> Vec<f32> {
+    (0..len).map(|i| amplitude * (2.0 * std::f32::consts::PI * hz * i as f32 / SAMPLE_RATE).sin()).collect()
+}
+
+// Largest value of the first frame.
+fn peak_magnitude(builder: SpectrogramBuilder, samples: &[f32]) -> f32 {
+    let frame = &builder.build(samples, builder.window_size())[0];
+    frame.iter().copied().fold(0.0, f32::max)
+}
+
+#[test]
+fn normalized_tone_peaks_agree_across_window_sizes() {
+    // 1378.125 Hz falls exactly on a bin at both sizes, so there's no scalloping loss.
+    let samples = tone(0.8, 1378.125, 8192);
+    let raw_small = peak_magnitude(SpectrogramBuilder::new(2048), &samples);
+    let raw_large = peak_magnitude(SpectrogramBuilder::new(4096), &samples);
+    assert!((raw_large / raw_small - 2.0).abs() < 0.01, "raw magnitudes should double: {} vs {}", raw_small, raw_large);
+
+    let small = peak_magnitude(SpectrogramBuilder::new(2048).with_normalized_magnitudes(true), &samples);
+    let large = peak_magnitude(SpectrogramBuilder::new(4096).with_normalized_magnitudes(true), &samples);
+    assert!((small - large).abs() < 0.01 * small, "{} vs {}", small, large);
+    assert!((small - 0.4).abs() < 0.01, "half the amplitude expected, got {}", small);
+
+    // The window type no longer matters either.
+    let blackman = peak_magnitude(SpectrogramBuilder::with_window(4096, WindowType::BlackmanHarris).with_normalized_magnitudes(true), &samples);
+    assert!((blackman - large).abs() < 0.01 * large, "{} vs {}", blackman, large);
+}
+
+#[test]
+fn normalization_scales_power_by_the_squared_window_sum() {
+    let samples = tone(0.5, 1000.0, 4096);
+    let builder = || SpectrogramBuilder::new(2048).with_magnitude_scale(MagnitudeScale::Power);
+    let raw = peak_magnitude(builder(), &samples);
+    let normalized = peak_magnitude(builder().with_normalized_magnitudes(true), &samples);
+    let sum = window_sum(WindowType::Hann, 2048);
+    assert!((sum - 1023.5).abs() < 0.01);
+    assert!((normalized * sum * sum / raw - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn quiet_audio_keeps_its_peaks_with_normalization() {
+    // -30 dBFS: raw frame energy clears the default floor, normalized energy would not.
+    let samples = tone(10f32.powf(-30.0 / 20.0), 1000.0, 22050);
+    let raw = Fingerprinter::default();
+    assert!(!raw.spectrogram_and_peaks(&samples).1.is_empty());
+
+    let mut normalized = Fingerprinter::default();
+    normalized.normalized_magnitudes = true;
+    normalized.scale_peak_params_for_normalization(window_sum(WindowType::Hann, normalized.window_size));
+    assert!(!normalized.spectrogram_and_peaks(&samples).1.is_empty());
+
+    let mut power = Fingerprinter::default();
+    power.magnitude_scale = MagnitudeScale::Power;
+    power.normalized_magnitudes = true;
+    power.peak_params.min_magnitude = raw.peak_params.min_magnitude * raw.peak_params.min_magnitude;
+    power.scale_peak_params_for_normalization(window_sum(WindowType::Hann, power.window_size));
+    assert!(!power.spectrogram_and_peaks(&samples).1.is_empty());
+
+    // Only rescaling the threshold, as before, loses every frame to the energy floor.
+    let mut threshold_only = Fingerprinter::default();
+    threshold_only.normalized_magnitudes = true;
+    threshold_only.peak_params.min_magnitude /= window_sum(WindowType::Hann, threshold_only.window_size);
+    assert!(threshold_only.spectrogram_and_peaks(&samples).1.is_empty());
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::matching::refine_offset;
+use sivana::Fingerprinter;
+
+#[test]
+fn refinement_recovers_the_exact_sample_offset_of_a_frame_level_match() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+
+    // Starts between two frames, so the frame-level offset is off by part of a hop.
+    let start = 150 * fingerprinter.hop_size + 377;
+    let snippet = &song[start..][..6 * fingerprinter.sample_rate as usize];
+    let coarse = fingerprinter.identify(&conn, snippet, 20).expect("snippet should match");
+    let coarse_samples = coarse.time_offset_in_song_frames * fingerprinter.hop_size as isize;
+    assert_ne!(coarse_samples, start as isize);
+
+    let excerpt = &snippet[..2 * fingerprinter.sample_rate as usize];
+    let refined = refine_offset(&song, excerpt, coarse_samples, fingerprinter.hop_size).unwrap();
+    assert_eq!(refined.offset_samples, start as isize);
+    assert!(refined.correlation > 0.99, "{:?}", refined);
+
+    // Offsets past the song's ends are never tried.
+    let tail = &song[song.len() - 1000..];
+    assert_eq!(refine_offset(&song, tail, song.len() as isize, 5000).unwrap().offset_samples, (song.len() - 1000) as isize);
+}
+
+#[test]
+fn refinement_reports_low_correlation_or_nothing_for_unrelated_audio() {
+    let song = synthetic_samples(11025, 5);
+    let unrelated = other_synthetic_samples(11025, 1);
+    let refined = refine_offset(&song, &unrelated, 10_000, 256).unwrap();
+    assert!(refined.correlation < 0.5, "{:?}", refined);
+    assert!(refine_offset(&song, &vec![0.0; 1000], 10_000, 256).is_none());
+    assert!(refine_offset(&song[..500], &unrelated, 0, 256).is_none());
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::{open_in_memory_connection, query_db_and_match_topn, QueryOptions};
+use sivana::hashing::Fingerprint;
+use sivana::matching::OffsetHistogram;
+use sivana::Fingerprinter;
+
+#[test]
+fn tolerance_merges_a_peak_split_across_neighboring_offsets() {
+    let fp = |hash: u64, anchor_time_idx: usize| Fingerprint { hash, anchor_time_idx, target_delta_frames: 1, strength: 0 };
+    let query: Vec<Fingerprint> = (0..20).map(|i| fp(i, 10 * i as usize)).collect();
+    let hits = |histogram: &mut OffsetHistogram| {
+        // Song 1's hits drift from offset 100 to 101 halfway through; song 2 has 8 at one offset.
+        for i in 0..12u64 {
+            histogram.add((i, 1, 10 * i as usize + 100 + (i >= 6) as usize, Some(1)));
+        }
+        for i in 12..20u64 {
+            histogram.add((i, 2, 10 * i as usize + 50, Some(1)));
+        }
+    };
+
+    let mut exact = OffsetHistogram::new(&query);
+    hits(&mut exact);
+    let exact = exact.into_candidates(2, 1, 0.1);
+    assert_eq!((exact[0].song_id, exact[0].score), (2, 8));
+    assert_eq!((exact[1].song_id, exact[1].score), (1, 6));
+
+    let mut tolerant = OffsetHistogram::new(&query).with_offset_tolerance(1);
+    hits(&mut tolerant);
+    let tolerant = tolerant.into_candidates(2, 1, 0.1);
+    assert_eq!((tolerant[0].song_id, tolerant[0].score, tolerant[0].distinct_hash_score), (1, 12, 12));
+    assert_eq!(tolerant[0].time_offset_in_song_frames, 100);
+    assert_eq!((tolerant[1].song_id, tolerant[1].score), (2, 8));
+}
+
+// Plays `samples` `speedup` times faster by linear interpolation.
+fn sped_up(samples: &[f32], speedup: f64) -> Vec<f32> {
+    let len = ((samples.len() - 1) as f64 / speedup) as usize;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * speedup;
+            let (idx, frac) = (pos as usize, (pos - pos.floor()) as f32);
+            samples[idx] * (1.0 - frac) + samples[idx + 1] * frac
+        })
+        .collect()
+}
+
+#[test]
+fn tolerance_recovers_score_lost_to_clock_drift() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 45);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+
+    let snippet = &song[200 * fingerprinter.hop_size..][..30 * fingerprinter.sample_rate as usize];
+    // A 0.3% fast clock: the hits slide by a couple of frames over the 30 s snippet.
+    let query = fingerprinter.fingerprint(&sped_up(snippet, 1.003));
+    let options = fingerprinter.query_options(1);
+    let exact = &query_db_and_match_topn(&conn, &query, 1, &options)[0];
+    let tolerant = &query_db_and_match_topn(&conn, &query, 1, &QueryOptions { offset_tolerance_frames: 2, ..options })[0];
+    assert_eq!((exact.song_id, tolerant.song_id), (song_id, song_id));
+    assert!(tolerant.score > 2 * exact.score, "exact {}, tolerant {}", exact.score, tolerant.score);
+    assert!(tolerant.time_offset_in_song_frames.abs_diff(200) <= 2);
+}
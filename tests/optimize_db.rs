@@ -0,0 +1,58 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::{
+    checkpoint_wal, delete_song, get_db_stats, init_db, open_bulk_db_connection, open_db_connection, optimize_db,
+};
+use sivana::Fingerprinter;
+
+#[test]
+fn optimize_reclaims_deleted_space_and_keeps_remaining_songs() {
+    let dir = std::env::temp_dir().join(format!("sivana-optimize-{}", std::process::id()));
+    let path = dir.join("library.sqlite");
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_db_connection(&path).unwrap();
+    init_db(&conn).unwrap();
+
+    let tags = AudioTags::default();
+    let kept = synthetic_samples(fingerprinter.sample_rate, 20);
+    let kept_id = fingerprinter.enroll(&mut conn, "kept", Some("kept.wav"), &tags, &kept).unwrap();
+    let dropped = fingerprinter.enroll(&mut conn, "dropped", Some("dropped.wav"), &tags, &other_synthetic_samples(fingerprinter.sample_rate, 60)).unwrap();
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())).unwrap();
+    delete_song(&mut conn, dropped).unwrap();
+    let before = get_db_stats(&conn).unwrap();
+
+    optimize_db(&conn).unwrap();
+    let after = get_db_stats(&conn).unwrap();
+    assert!(after.db_file_size_bytes < before.db_file_size_bytes);
+    assert_eq!(after.wal_file_size_bytes.unwrap_or(0), 0);
+    assert_eq!((after.song_count, after.fingerprint_count), (before.song_count, before.fingerprint_count));
+    assert_eq!(fingerprinter.identify(&conn, &kept[..fingerprinter.sample_rate as usize * 6], 20).map(|m| m.song_id), Some(kept_id));
+
+    drop(conn);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn checkpoint_truncates_the_wal_after_bulk_writes() {
+    let dir = std::env::temp_dir().join(format!("sivana-checkpoint-{}", std::process::id()));
+    let path = dir.join("library.sqlite");
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_bulk_db_connection(&path).unwrap();
+    init_db(&conn).unwrap();
+
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+    assert!(get_db_stats(&conn).unwrap().wal_file_size_bytes.unwrap_or(0) > 0);
+
+    assert!(checkpoint_wal(&conn).unwrap());
+    assert_eq!(get_db_stats(&conn).unwrap().wal_file_size_bytes.unwrap_or(0), 0);
+    conn.close().map_err(|(_, e)| e).unwrap();
+
+    // Everything written before the checkpoint is in the main file.
+    let conn = open_db_connection(&path).unwrap();
+    assert_eq!(fingerprinter.identify(&conn, &song[..fingerprinter.sample_rate as usize * 6], 20).map(|m| m.song_id), Some(song_id));
+    drop(conn);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
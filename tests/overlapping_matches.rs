@@ -0,0 +1,50 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::Fingerprinter;
+
+// `first` fading into `second` over the last `fade` samples of `first`.
+fn crossfade(first: &[f32], second: &[f32], fade: usize) -> Vec<f32> {
+    let fade_start = first.len() - fade;
+    let mut mixed = first.to_vec();
+    for (i, sample) in second.iter().enumerate() {
+        let position = fade_start + i;
+        let gain = (i as f32 / fade as f32).min(1.0);
+        match mixed.get_mut(position) {
+            Some(existing) => *existing = *existing * (1.0 - gain) + sample * gain,
+            None => mixed.push(*sample),
+        }
+    }
+    mixed
+}
+
+#[test]
+fn peeling_finds_both_songs_of_a_crossfade() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let (rate, hop) = (fingerprinter.sample_rate as usize, fingerprinter.hop_size);
+    let outgoing = synthetic_samples(fingerprinter.sample_rate, 20);
+    let incoming = other_synthetic_samples(fingerprinter.sample_rate, 20);
+    let outgoing_id = fingerprinter.enroll(&mut conn, "outgoing", Some("outgoing.wav"), &tags, &outgoing).unwrap();
+    let incoming_id = fingerprinter.enroll(&mut conn, "incoming", Some("incoming.wav"), &tags, &incoming).unwrap();
+
+    // The outgoing song from frame 100, with the incoming one from frame 0 taking over.
+    let snippet = crossfade(&outgoing[100 * hop..][..6 * rate], &incoming[..5 * rate], 2 * rate);
+    let single = fingerprinter.identify(&conn, &snippet, 20).expect("the outgoing song should match");
+    assert_eq!(single.song_id, outgoing_id);
+
+    let found = fingerprinter.identify_overlapping(&conn, &snippet, 3, 20);
+    assert_eq!(found.iter().map(|m| m.song_id).collect::<Vec<_>>(), [outgoing_id, incoming_id]);
+    assert_eq!(found[0].time_offset_in_song_frames, 100);
+    // The incoming song starts where the fade does, 4 s into the snippet.
+    let incoming_start = (4 * rate / hop) as isize;
+    assert!((found[1].time_offset_in_song_frames + incoming_start).abs() <= 1, "{}", found[1].time_offset_in_song_frames);
+    assert!(found[1].query_match_start_seconds > found[0].query_match_start_seconds);
+
+    assert_eq!(fingerprinter.identify_overlapping(&conn, &snippet, 1, 20).len(), 1);
+    let unknown: Vec<f32> = (0..5 * rate).map(|i| ((i * 7919) % 1000) as f32 / 1000.0 - 0.5).collect();
+    assert!(fingerprinter.identify_overlapping(&conn, &unknown, 3, 20).is_empty());
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "rayon")]
+
+mod common;
+
+use common::synthetic_samples;
+use sivana::hashing::{create_hashes_parallel, create_hashes_serial, HashConfig, TargetZone};
+use sivana::peaks::{find_peaks, Peak};
+use sivana::spectrogram::create_spectrogram;
+use sivana::Fingerprinter;
+
+#[test]
+fn parallel_hashes_match_serial_for_real_peaks() {
+    let fingerprinter = Fingerprinter::default();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 10);
+    let spectrogram = create_spectrogram(&samples, fingerprinter.sample_rate, fingerprinter.window_size, fingerprinter.hop_size);
+    let peaks = find_peaks(&spectrogram, fingerprinter.peak_params);
+
+    let serial = create_hashes_serial(&peaks, fingerprinter.target_zone, fingerprinter.hash_config);
+    let parallel = create_hashes_parallel(&peaks, fingerprinter.target_zone, fingerprinter.hash_config);
+    assert!(!serial.is_empty());
+    assert_eq!(serial, parallel);
+}
+
+#[test]
+fn parallel_hashes_match_serial_for_dense_constellation() {
+    // Many peaks per frame, so anchors hit the max-pairs cap and the target-zone filters.
+    let peaks: Vec<Peak> = (0..400)
+        .flat_map(|t| (0..8).map(move |k| Peak { time_idx: t, freq_bin_idx: (t * 37 + k * 61) % 1024, magnitude: 1.0 }))
+        .collect();
+    let config = HashConfig::default();
+    let zone = TargetZone::new(1, 50, 200, 5).unwrap();
+
+    let serial = create_hashes_serial(&peaks, zone, config);
+    let parallel = create_hashes_parallel(&peaks, zone, config);
+    assert!(!serial.is_empty());
+    assert_eq!(serial, parallel);
+}
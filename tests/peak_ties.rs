@@ -0,0 +1,38 @@
+use sivana::peaks::{find_peaks, PeakParams};
+
+fn flat_spectrogram(frames: usize, bins: usize) -> Vec<Vec<f32>> {
+    vec![vec![1.0; bins]; frames]
+}
+
+fn peak_positions(spectrogram: &[Vec<f32>]) -> Vec<(usize, usize)> {
+    find_peaks(spectrogram, PeakParams { min_frame_energy: 0.0, ..PeakParams::default() })
+        .iter()
+        .map(|p| (p.time_idx, p.freq_bin_idx))
+        .collect()
+}
+
+#[test]
+fn equal_peaks_inside_one_neighborhood_are_both_kept() {
+    let mut spectrogram = flat_spectrogram(9, 40);
+    // Same magnitude, 3 bins apart with a dip between: two distinct peaks, not a plateau.
+    spectrogram[4][10] = 8.0;
+    spectrogram[4][13] = 8.0;
+    assert_eq!(peak_positions(&spectrogram), vec![(4, 10), (4, 13)]);
+}
+
+#[test]
+fn equal_peaks_far_apart_are_both_kept() {
+    let mut spectrogram = flat_spectrogram(20, 60);
+    spectrogram[3][5] = 8.0;
+    spectrogram[15][50] = 8.0;
+    assert_eq!(peak_positions(&spectrogram), vec![(3, 5), (15, 50)]);
+}
+
+#[test]
+fn adjacent_plateau_collapses_to_one_peak() {
+    let mut spectrogram = flat_spectrogram(9, 40);
+    for (t, f) in [(4, 20), (4, 21), (5, 21), (5, 22)] {
+        spectrogram[t][f] = 8.0;
+    }
+    assert_eq!(peak_positions(&spectrogram), vec![(4, 20)]);
+}
@@ -0,0 +1,50 @@
+use sivana::peaks::{find_peaks_with_threshold, PeakParams, Threshold};
+
+const PARAMS: PeakParams =
+    PeakParams { time_radius: 1, freq_radius: 2, min_magnitude: 0.0, max_peaks_per_frame: None, min_frame_energy: 0.0 };
+
+// Deterministic pseudo-random magnitudes in 0..1 (a small LCG), so every bin value differs.
+fn noise_spectrogram(frames: usize, bins: usize, scale: f32) -> Vec<Vec<f32>> {
+    let mut state: u32 = 12345;
+    (0..frames)
+        .map(|_| {
+            (0..bins)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 8) as f32 / (1u32 << 24) as f32 * scale
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn percentile_threshold_keeps_about_the_expected_share_of_bins() {
+    let spectrogram = noise_spectrogram(200, 128, 1.0);
+    let total = (200 * 128) as f32;
+    for percentile in [50.0, 90.0, 95.0, 99.0] {
+        let threshold = Threshold::Percentile(percentile).resolve(&spectrogram);
+        let above = spectrogram.iter().flatten().filter(|&&v| v >= threshold).count() as f32;
+        let expected = 1.0 - percentile / 100.0;
+        assert!((above / total - expected).abs() < 0.005, "p{}: {} of bins kept", percentile, above / total);
+    }
+    assert_eq!(Threshold::Absolute(2.5).resolve(&spectrogram), 2.5);
+    assert_eq!(Threshold::Percentile(90.0).resolve(&[]), 0.0);
+}
+
+#[test]
+fn percentile_peaks_clear_the_threshold_and_ignore_overall_level() {
+    let spectrogram = noise_spectrogram(200, 128, 1.0);
+    let threshold = Threshold::Percentile(95.0).resolve(&spectrogram);
+    let peaks = find_peaks_with_threshold(&spectrogram, PARAMS, Threshold::Percentile(95.0));
+    assert!(!peaks.is_empty());
+    assert!(peaks.iter().all(|p| p.magnitude >= threshold));
+    assert!(peaks.len() <= 200 * 128 / 20);
+
+    // The same content 1000x louder: an absolute threshold would keep far more peaks, the
+    // percentile keeps exactly the same ones.
+    let louder: Vec<Vec<f32>> = spectrogram.iter().map(|frame| frame.iter().map(|v| v * 1000.0).collect()).collect();
+    let positions = |peaks: &[sivana::peaks::Peak]| peaks.iter().map(|p| (p.time_idx, p.freq_bin_idx)).collect::<Vec<_>>();
+    assert_eq!(positions(&find_peaks_with_threshold(&louder, PARAMS, Threshold::Percentile(95.0))), positions(&peaks));
+    assert_eq!(positions(&find_peaks_with_threshold(&spectrogram, PARAMS, threshold)), positions(&peaks));
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::{open_in_memory_connection, DEFAULT_MIN_MATCH_SCORE};
+use sivana::error::SivanaError;
+use sivana::Fingerprinter;
+
+#[test]
+fn fingerprints_computed_up_front_are_stored_as_enroll_would() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let song = synthetic_samples(fingerprinter.sample_rate, 10);
+    let other = other_synthetic_samples(fingerprinter.sample_rate, 10);
+
+    // Fingerprinted out of order (as a thread pool might), stored in input order.
+    let other_fingerprints = fingerprinter.fingerprint_for_enrollment("other", &other).unwrap();
+    let song_fingerprints = fingerprinter.fingerprint_for_enrollment("song", &song).unwrap();
+    assert_eq!(song_fingerprints, fingerprinter.fingerprint(&song));
+    let song_id = fingerprinter.enroll_fingerprinted(&mut conn, "song", Some("song.wav"), &tags, &song_fingerprints, true).unwrap();
+    let other_id = fingerprinter.enroll_fingerprinted(&mut conn, "other", Some("other.wav"), &tags, &other_fingerprints, true).unwrap();
+    assert!(song_id < other_id);
+    assert_eq!(fingerprinter.identify(&conn, &song, DEFAULT_MIN_MATCH_SCORE).map(|m| m.song_id), Some(song_id));
+}
+
+#[test]
+fn unique_storage_rejects_audio_enrolled_under_another_path() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let song = synthetic_samples(fingerprinter.sample_rate, 10);
+    let original = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &tags, &song).unwrap();
+
+    let fingerprints = fingerprinter.fingerprint_for_enrollment("copy", &song).unwrap();
+    match fingerprinter.enroll_fingerprinted(&mut conn, "copy", Some("copy.wav"), &tags, &fingerprints, true) {
+        Err(SivanaError::DuplicateDetected { existing_song_id }) => assert_eq!(existing_song_id, original),
+        other => panic!("expected a duplicate, got {:?}", other),
+    }
+    let copy = fingerprinter.enroll_fingerprinted(&mut conn, "copy", Some("copy.wav"), &tags, &fingerprints, false).unwrap();
+    assert_ne!(copy, original);
+}
+
+#[test]
+fn silence_fails_before_reaching_the_database() {
+    let fingerprinter = Fingerprinter::default();
+    let silence = vec![0.0; fingerprinter.sample_rate as usize * 5];
+    assert!(matches!(fingerprinter.fingerprint_for_enrollment("silence", &silence), Err(SivanaError::NoPeaks { .. })));
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::{open_in_memory_connection, query_db_and_match_topn, MatchResult, SongId};
+use sivana::hashing::Fingerprint;
+use sivana::matching::{match_candidates, match_candidates_topn, CandidateEntry};
+use sivana::Fingerprinter;
+
+fn entries(song_id: SongId, fingerprints: &[Fingerprint]) -> impl Iterator<Item = CandidateEntry> + '_ {
+    fingerprints.iter().map(move |fp| (fp.hash, song_id, fp.anchor_time_idx, Some(fp.target_delta_frames)))
+}
+
+#[test]
+fn in_memory_index_finds_song_and_offset() {
+    let fingerprinter = Fingerprinter::default();
+    let first = synthetic_samples(fingerprinter.sample_rate, 12);
+    let second = other_synthetic_samples(fingerprinter.sample_rate, 12);
+    let (first_fps, second_fps) = (fingerprinter.fingerprint(&first), fingerprinter.fingerprint(&second));
+
+    let offset_frames = 100;
+    let query = fingerprinter.fingerprint(&first[offset_frames * fingerprinter.hop_size..][..fingerprinter.sample_rate as usize * 5]);
+    let best = match_candidates(&query, entries(1, &first_fps).chain(entries(2, &second_fps)), 20, fingerprinter.frame_duration_seconds())
+        .unwrap();
+    assert_eq!((best.song_id, best.time_offset_in_song_frames), (1, offset_frames as isize));
+
+    assert!(match_candidates(&query, entries(2, &second_fps), 20, fingerprinter.frame_duration_seconds()).is_none());
+}
+
+#[test]
+fn sqlite_matcher_agrees_with_pure_matcher() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let first = synthetic_samples(fingerprinter.sample_rate, 12);
+    let second = other_synthetic_samples(fingerprinter.sample_rate, 12);
+    let first_id = fingerprinter.enroll(&mut conn, "first", Some("first.wav"), &AudioTags::default(), &first).unwrap();
+    let second_id = fingerprinter.enroll(&mut conn, "second", Some("second.wav"), &AudioTags::default(), &second).unwrap();
+
+    let query = fingerprinter.fingerprint(&first[fingerprinter.sample_rate as usize * 3..][..fingerprinter.sample_rate as usize * 6]);
+    let (first_fps, second_fps) = (fingerprinter.fingerprint(&first), fingerprinter.fingerprint(&second));
+    let pure = match_candidates_topn(
+        &query, entries(first_id, &first_fps).chain(entries(second_id, &second_fps)), 5, 1, fingerprinter.frame_duration_seconds(),
+    );
+    let sqlite = query_db_and_match_topn(&conn, &query, 5, &fingerprinter.query_options(1));
+
+    let summary = |results: &[MatchResult]| -> Vec<(SongId, usize, isize)> {
+        results.iter().map(|m| (m.song_id, m.score, m.time_offset_in_song_frames)).collect()
+    };
+    assert!(!pure.is_empty());
+    assert_eq!(summary(&pure), summary(&sqlite));
+}
+
+#[test]
+fn distinct_hashes_break_score_ties_against_repeated_loops() {
+    let fp = |hash: u64, anchor_time_idx: usize| Fingerprint { hash, anchor_time_idx, target_delta_frames: 1, strength: 1 };
+    // The query repeats one landmark (a loop) and also has four different ones.
+    let loop_hash = 7;
+    let mut query: Vec<Fingerprint> = (0..4).map(|i| fp(loop_hash, i * 10)).collect();
+    query.extend((0..4).map(|i| fp(100 + i as u64, i)));
+
+    // Song 1 has the same loop, so offset 100 collects 4 hits of a single hash;
+    // song 2 matches the four distinct landmarks at offset 50.
+    let looped: Vec<CandidateEntry> = (0..4).map(|i| (loop_hash, 1, 100 + i * 10, None)).collect();
+    let distinct: Vec<CandidateEntry> = (0..4).map(|i| (100 + i as u64, 2, 50 + i, None)).collect();
+
+    let ranked = match_candidates_topn(&query, looped.into_iter().chain(distinct), 2, 1, 0.05);
+    let summary: Vec<(SongId, usize, usize, isize)> =
+        ranked.iter().map(|m| (m.song_id, m.score, m.distinct_hash_score, m.time_offset_in_song_frames)).collect();
+    assert_eq!(summary, vec![(2, 4, 4, 50), (1, 4, 1, 100)]);
+}
+
+#[test]
+fn matched_span_covers_only_the_agreeing_hits_in_song_and_query() {
+    let fp = |hash: u64, anchor_time_idx: usize| Fingerprint { hash, anchor_time_idx, target_delta_frames: 3, strength: 0 };
+    // Hashes 2-4 line up 100 frames into song 1; 1 and 5 are not in the song at all.
+    let query = [fp(1, 0), fp(2, 5), fp(3, 10), fp(4, 20), fp(5, 40)];
+    let song = [fp(2, 105), fp(3, 110), fp(4, 120), fp(9, 300)];
+
+    let best = match_candidates(&query, entries(1, &song), 3, 0.1).unwrap();
+    assert_eq!(best.time_offset_in_song_frames, 100);
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+    assert!(close(best.query_match_start_seconds, 0.5) && close(best.query_match_end_seconds, 2.4), "{:?}", best);
+    assert!(close(best.match_start_seconds, 10.5) && close(best.match_end_seconds, 12.4), "{:?}", best);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::{open_in_memory_connection, query_db_and_match, QueryOptions};
+use sivana::hashing::thin_fingerprints;
+use sivana::Fingerprinter;
+
+#[test]
+fn thinning_keeps_an_even_spread_of_unchanged_fingerprints() {
+    let fingerprinter = Fingerprinter::default();
+    let query = fingerprinter.fingerprint(&synthetic_samples(fingerprinter.sample_rate, 10));
+    assert!(query.len() > 100);
+
+    assert_eq!(thin_fingerprints(&query, query.len() + 1).len(), query.len());
+    let thinned = thin_fingerprints(&query, 100);
+    assert_eq!(thinned.len(), 100);
+    assert_eq!(thinned[0].anchor_time_idx, query[0].anchor_time_idx);
+    assert!(thinned.iter().all(|fp| query.contains(fp)));
+    // The kept fingerprints reach into the last tenth of the query.
+    let last_anchor = query.last().unwrap().anchor_time_idx;
+    assert!(thinned.last().unwrap().anchor_time_idx >= last_anchor * 9 / 10);
+    assert!(thin_fingerprints(&query, 0).is_empty());
+}
+
+#[test]
+fn a_capped_query_still_finds_the_song_at_the_same_offset() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 30);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+
+    let query = fingerprinter.fingerprint(&song[100 * fingerprinter.hop_size..][..20 * fingerprinter.sample_rate as usize]);
+    let options = QueryOptions { min_query_coverage: None, verify_min_fraction: None, ..fingerprinter.query_options(20) };
+    let full = query_db_and_match(&conn, &query, &options).unwrap();
+    let capped = query_db_and_match(&conn, &thin_fingerprints(&query, query.len() / 4), &options).unwrap();
+    assert_eq!((capped.song_id, capped.time_offset_in_song_frames), (song_id, full.time_offset_in_song_frames));
+    assert!(capped.score < full.score);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::{delete_song, log_query, open_in_memory_connection, recent_queries};
+use sivana::Fingerprinter;
+
+#[test]
+fn query_log_records_matches_and_misses_newest_first() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    let song_id = fingerprinter.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+    assert!(recent_queries(&conn, 10).unwrap().is_empty());
+
+    let snippet = &song[100 * fingerprinter.hop_size..][..6 * fingerprinter.sample_rate as usize];
+    let hit = fingerprinter.identify(&conn, snippet, 20).expect("snippet should match");
+    let miss = fingerprinter.identify(&conn, &other_synthetic_samples(fingerprinter.sample_rate, 6), 20);
+    assert!(miss.is_none());
+
+    let hit_id = log_query(&conn, Some("hit.wav"), Some(&hit)).unwrap();
+    let miss_id = log_query(&conn, Some("miss.wav"), miss.as_ref()).unwrap();
+
+    let entries = recent_queries(&conn, 10).unwrap();
+    assert_eq!(entries.iter().map(|e| e.query_id).collect::<Vec<_>>(), [miss_id, hit_id]);
+    let (newest, oldest) = (&entries[0], &entries[1]);
+    assert_eq!(newest.snippet_path.as_deref(), Some("miss.wav"));
+    assert_eq!((newest.song_id, newest.score, newest.confidence), (None, None, None));
+    assert_eq!(oldest.song_id, Some(song_id));
+    assert_eq!(oldest.song_name.as_deref(), Some("song"));
+    assert_eq!(oldest.score, Some(hit.score));
+    assert!(!oldest.queried_at.is_empty());
+    assert_eq!(recent_queries(&conn, 1).unwrap().len(), 1);
+
+    // The log outlives the song; only the name goes away.
+    assert!(delete_song(&mut conn, song_id).unwrap());
+    let entries = recent_queries(&conn, 10).unwrap();
+    assert_eq!((entries[1].song_id, entries[1].song_name.as_deref()), (Some(song_id), None));
+}
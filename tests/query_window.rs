@@ -0,0 +1,44 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::hashing::{fingerprints_in_window, Fingerprint};
+use sivana::Fingerprinter;
+
+#[test]
+fn window_keeps_anchors_in_range_and_rebases_them() {
+    let fp = |anchor_time_idx: usize| Fingerprint { hash: anchor_time_idx as u64, anchor_time_idx, target_delta_frames: 1, strength: 0 };
+    let all: Vec<Fingerprint> = [3, 9, 10, 14, 15, 20].into_iter().map(fp).collect();
+    let window = fingerprints_in_window(&all, 10, 15);
+    assert_eq!(window.iter().map(|f| (f.hash, f.anchor_time_idx)).collect::<Vec<_>>(), [(10, 0), (14, 4)]);
+    assert!(fingerprints_in_window(&all, 21, 30).is_empty());
+}
+
+#[test]
+fn identify_window_matches_only_the_recent_part_of_a_live_buffer() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let first = synthetic_samples(fingerprinter.sample_rate, 20);
+    let second = other_synthetic_samples(fingerprinter.sample_rate, 20);
+    let first_id = fingerprinter.enroll(&mut conn, "first", Some("first.wav"), &tags, &first).unwrap();
+    let second_id = fingerprinter.enroll(&mut conn, "second", Some("second.wav"), &tags, &second).unwrap();
+
+    // The buffer holds 15 s of the first song followed by 10 s of the second, fingerprinted once.
+    let second_start = 15 * fingerprinter.sample_rate as usize;
+    let mut buffer = first[..second_start].to_vec();
+    buffer.extend_from_slice(&second[..10 * fingerprinter.sample_rate as usize]);
+    let fingerprints = fingerprinter.fingerprint(&buffer);
+    let frames_per_second = fingerprinter.sample_rate as usize / fingerprinter.hop_size;
+    let end_frame = buffer.len() / fingerprinter.hop_size;
+
+    let start_frame = end_frame - 6 * frames_per_second;
+    let recent = fingerprinter.identify_window(&conn, &fingerprints, start_frame, end_frame, 20).expect("recent audio should match");
+    assert_eq!(recent.song_id, second_id);
+    let expected_offset = (start_frame * fingerprinter.hop_size - second_start) / fingerprinter.hop_size;
+    assert!(recent.time_offset_in_song_frames.abs_diff(expected_offset as isize) <= 1, "{}", recent.time_offset_in_song_frames);
+
+    let earlier = fingerprinter.identify_window(&conn, &fingerprints, 2 * frames_per_second, 8 * frames_per_second, 20).unwrap();
+    assert_eq!((earlier.song_id, earlier.time_offset_in_song_frames), (first_id, 2 * frames_per_second as isize));
+}
@@ -0,0 +1,55 @@
+use sivana::peaks::{find_peaks, find_peaks_adaptive, find_peaks_banded, PeakParams, StreamingPeakFinder};
+
+const PARAMS: PeakParams =
+    PeakParams { time_radius: 1, freq_radius: 2, min_magnitude: 2.0, max_peaks_per_frame: None, min_frame_energy: 0.0 };
+
+// Flat frames of `bins` bins with one spike per frame at a bin that moves with time.
+fn spiky_frame(t: usize, bins: usize) -> Vec<f32> {
+    let mut frame = vec![1.0; bins];
+    frame[(3 + 5 * t) % bins] = 50.0;
+    frame
+}
+
+// Frames 2 and 5 are narrower and frame 7 wider than the rest, with spikes past frame 0's width.
+fn ragged_spectrogram() -> Vec<Vec<f32>> {
+    (0..10)
+        .map(|t| match t {
+            2 | 5 => spiky_frame(t, 4),
+            7 => {
+                let mut frame = spiky_frame(t, 64);
+                frame[50] = 100.0;
+                frame
+            }
+            _ => spiky_frame(t, 32),
+        })
+        .collect()
+}
+
+#[test]
+fn ragged_frames_are_skipped_without_panicking() {
+    let spectrogram = ragged_spectrogram();
+    let ragged_times = [2, 5, 7];
+
+    let peaks = find_peaks(&spectrogram, PARAMS);
+    assert!(!peaks.is_empty());
+    assert!(peaks.iter().all(|p| !ragged_times.contains(&p.time_idx) && p.freq_bin_idx < 32));
+
+    let adaptive = find_peaks_adaptive(&spectrogram, 1, 2, 1.0);
+    assert!(adaptive.iter().all(|p| !ragged_times.contains(&p.time_idx) && p.freq_bin_idx < 32));
+
+    let banded = find_peaks_banded(&spectrogram, 4, 1);
+    assert!(!banded.is_empty());
+    assert!(banded.iter().all(|p| !ragged_times.contains(&p.time_idx) && p.freq_bin_idx < 32));
+}
+
+#[test]
+fn streaming_peak_finder_skips_ragged_frames_like_find_peaks() {
+    let spectrogram = ragged_spectrogram();
+    let expected = find_peaks(&spectrogram, PARAMS);
+
+    let mut finder = StreamingPeakFinder::new(PARAMS);
+    let mut streamed: Vec<_> = spectrogram.iter().flat_map(|frame| finder.push(frame.clone())).collect();
+    streamed.extend(finder.finish());
+    let positions = |peaks: &[sivana::peaks::Peak]| peaks.iter().map(|p| (p.time_idx, p.freq_bin_idx)).collect::<Vec<_>>();
+    assert_eq!(positions(&streamed), positions(&expected));
+}
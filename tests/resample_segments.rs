@@ -0,0 +1,25 @@
+use sivana::audio_loader::{resample_segments, ResampleQuality};
+use std::f32::consts::PI;
+
+fn tone(sample_rate: u32, seconds: f32, freq: f32) -> Vec<f32> {
+    let n = (sample_rate as f32 * seconds) as usize;
+    (0..n).map(|i| 0.5 * (2.0 * PI * freq * i as f32 / sample_rate as f32).sin()).collect()
+}
+
+#[test]
+fn two_rate_segments_are_resampled_and_concatenated() {
+    let target = 22050;
+    let segments = vec![
+        (44100, tone(44100, 1.0, 440.0)),
+        (22050, tone(22050, 1.0, 880.0)),
+    ];
+
+    let output = resample_segments(segments, target, ResampleQuality::Fast).unwrap();
+
+    // One second from each segment at the target rate; the sinc resampler may trim a
+    // little at the edges, so allow some slack.
+    let expected = 2 * target as usize;
+    assert!(output.len().abs_diff(expected) < target as usize / 10, "got {} samples", output.len());
+    // The second segment was already at the target rate and is passed through unchanged.
+    assert_eq!(&output[output.len() - 22050..], &tone(22050, 1.0, 880.0)[..]);
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::{Fingerprinter, SivanaError};
+
+#[test]
+fn databases_remember_their_sample_rate() {
+    let hifi = Fingerprinter::new(44100, 2048, 1024);
+    let mut conn = open_in_memory_connection().unwrap();
+    hifi.store_params(&conn).unwrap();
+    let song = synthetic_samples(hifi.sample_rate, 10);
+    hifi.enroll(&mut conn, "song", Some("song.wav"), &AudioTags::default(), &song).unwrap();
+    hifi.check_params(&conn).unwrap();
+
+    // Storing other settings later changes nothing: the first writer wins.
+    Fingerprinter::new(22050, 2048, 1024).store_params(&conn).unwrap();
+    match Fingerprinter::default().check_params(&conn) {
+        Err(SivanaError::ParamMismatch { mismatches }) => {
+            assert_eq!(mismatches, ["sample_rate: database uses 44100, current setting is 22050"]);
+        }
+        other => panic!("expected a sample rate mismatch, got {:?}", other),
+    }
+
+    let snippet = &song[100 * hifi.hop_size..][..6 * hifi.sample_rate as usize];
+    let found = hifi.identify(&conn, snippet, 20).expect("same-rate query should match");
+    assert_eq!(found.time_offset_in_song_frames, 100);
+}
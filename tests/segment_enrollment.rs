@@ -0,0 +1,47 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::{slice_seconds, AudioTags};
+use sivana::database::open_in_memory_connection;
+use sivana::Fingerprinter;
+
+#[test]
+fn slicing_checks_the_segment_lies_within_the_audio() {
+    let samples: Vec<f32> = (0..1000).map(|i| i as f32).collect();
+    assert_eq!(slice_seconds(&samples, 100, 2.0, Some(3.5)).unwrap(), &samples[200..350]);
+    assert_eq!(slice_seconds(&samples, 100, 9.0, None).unwrap(), &samples[900..]);
+    assert_eq!(slice_seconds(&samples, 100, 0.0, Some(10.0)).unwrap().len(), 1000);
+
+    assert!(slice_seconds(&samples, 100, 3.0, Some(3.0)).is_err());
+    assert!(slice_seconds(&samples, 100, 4.0, Some(2.0)).is_err());
+    assert!(slice_seconds(&samples, 100, -1.0, None).is_err());
+    assert!(slice_seconds(&samples, 100, 10.0, None).is_err());
+    assert!(slice_seconds(&samples, 100, 0.0, Some(10.5)).is_err());
+    assert!(slice_seconds(&samples, 100, f32::NAN, None).is_err());
+}
+
+#[test]
+fn an_open_ended_segment_of_long_audio_runs_to_the_last_sample() {
+    // Past 2^24 samples an f32 duration no longer converts back to the exact length.
+    let sample_rate = 22050;
+    for len in (1 << 24) + 3_000_000..(1 << 24) + 3_000_050 {
+        let samples = vec![0.0f32; len];
+        let segment = slice_seconds(&samples, sample_rate, 60.0, None).unwrap();
+        assert_eq!(segment.len(), len - 60 * sample_rate as usize, "{}", len);
+    }
+}
+
+#[test]
+fn a_segment_is_matched_at_offsets_relative_to_its_start() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let recording = synthetic_samples(fingerprinter.sample_rate, 30);
+    // The segment starts on a frame boundary, 200 frames in.
+    let segment_start = 200.0 * fingerprinter.frame_duration_seconds();
+    let segment = slice_seconds(&recording, fingerprinter.sample_rate, segment_start, Some(segment_start + 12.0)).unwrap();
+    let song_id = fingerprinter.enroll(&mut conn, "segment", Some("mix.wav"), &AudioTags::default(), segment).unwrap();
+
+    let snippet = &recording[(200 + 50) * fingerprinter.hop_size..][..6 * fingerprinter.sample_rate as usize];
+    let found = fingerprinter.identify(&conn, snippet, 20).expect("snippet from the segment should match");
+    assert_eq!((found.song_id, found.time_offset_in_song_frames), (song_id, 50));
+}
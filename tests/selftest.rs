@@ -0,0 +1,31 @@
+use sivana::database::{get_db_stats, open_in_memory_connection};
+use sivana::selftest::{run_selftest, selftest_samples, SelftestWorkload};
+use sivana::Fingerprinter;
+
+#[test]
+fn selftest_runs_every_stage_and_matches_every_query() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let workload = SelftestWorkload { songs: 3, song_seconds: 12, queries: 6, query_seconds: 5 };
+    let report = run_selftest(&fingerprinter, &mut conn, workload).unwrap();
+
+    assert!(report.passed(), "{:?}", report);
+    assert_eq!((report.queries.items, report.queries_matched), (6, 6));
+    assert!(report.spectrogram.items > 0 && report.peaks.items == report.spectrogram.items);
+    assert!(report.hashing.items > 0 && report.enrollment.items == report.hashing.items);
+    let stats = get_db_stats(&conn).unwrap();
+    assert_eq!((stats.song_count, stats.fingerprint_count as usize), (3, report.enrollment.items));
+}
+
+#[test]
+fn selftest_audio_is_reproducible_and_differs_per_song() {
+    let song = selftest_samples(22050, 2, 0);
+    assert_eq!(song.len(), 44100);
+    assert_eq!(song, selftest_samples(22050, 2, 0));
+    assert_ne!(song, selftest_samples(22050, 2, 1));
+    assert!(song.iter().all(|s| s.abs() <= 0.9));
+
+    let report = run_selftest(&Fingerprinter::default(), &mut open_in_memory_connection().unwrap(), SelftestWorkload { songs: 0, ..SelftestWorkload::default() }).unwrap();
+    assert!(!report.passed());
+    assert_eq!(report.queries.items, 0);
+}
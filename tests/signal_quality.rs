@@ -0,0 +1,65 @@
+use std::io::Cursor;
+
+use sivana::audio_loader::{load_audio_from_reader_with_info, remove_dc_offset, signal_quality, LoadOptions, CLIPPED_FRACTION_WARNING, DC_OFFSET_WARNING};
+
+const SAMPLE_RATE: u32 = 22050;
+
+// Mono 16-bit PCM WAV in memory, at SAMPLE_RATE so no resampling happens.
+fn mono_wav_bytes(samples: &[f32]) -> Vec<u8> {
+    let data: Vec<u8> = samples.iter().flat_map(|s| ((s.clamp(-1.0, 1.0) * 32767.0) as i16).to_le_bytes()).collect();
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend((36 + data.len() as u32).to_le_bytes());
+    bytes.extend(b"WAVEfmt ");
+    bytes.extend(16u32.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(SAMPLE_RATE.to_le_bytes());
+    bytes.extend((SAMPLE_RATE * 2).to_le_bytes());
+    bytes.extend(2u16.to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"data");
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend(data);
+    bytes
+}
+
+fn offset_tone(offset: f32, amplitude: f32) -> Vec<f32> {
+    (0..SAMPLE_RATE as usize)
+        .map(|i| offset + amplitude * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / SAMPLE_RATE as f32).sin())
+        .collect()
+}
+
+#[test]
+fn signal_quality_measures_clipping_and_dc_offset() {
+    assert_eq!(signal_quality(&[]).clipped_fraction, 0.0);
+
+    let clean = signal_quality(&offset_tone(0.0, 0.5));
+    assert_eq!(clean.clipped_fraction, 0.0);
+    assert!(clean.dc_offset.abs() < DC_OFFSET_WARNING);
+
+    // Driven well past full scale, a large share of the sine sits on the rails.
+    let clipped: Vec<f32> = offset_tone(0.0, 3.0).iter().map(|s| s.clamp(-1.0, 1.0)).collect();
+    assert!(signal_quality(&clipped).clipped_fraction > 0.5);
+    assert!(signal_quality(&clipped).clipped_fraction > CLIPPED_FRACTION_WARNING);
+
+    let mut shifted = offset_tone(0.2, 0.5);
+    assert!((signal_quality(&shifted).dc_offset - 0.2).abs() < 1e-3);
+    assert!((remove_dc_offset(&mut shifted) - 0.2).abs() < 1e-3);
+    assert!(signal_quality(&shifted).dc_offset.abs() < 1e-6);
+}
+
+#[test]
+fn loader_removes_dc_offset_only_when_asked() {
+    let wav = mono_wav_bytes(&offset_tone(0.2, 0.5));
+    let load = |remove_dc: bool| {
+        let options = LoadOptions { remove_dc, ..LoadOptions::default() };
+        load_audio_from_reader_with_info(Cursor::new(wav.clone()), Some("wav"), SAMPLE_RATE, &options).unwrap().samples
+    };
+
+    let kept = load(false);
+    assert!((signal_quality(&kept).dc_offset - 0.2).abs() < 1e-3);
+    let removed = load(true);
+    assert_eq!(removed.len(), kept.len());
+    assert!(signal_quality(&removed).dc_offset.abs() < 1e-4);
+    assert!((removed[0] - (kept[0] - 0.2)).abs() < 1e-3);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::peaks::{find_peaks, PeakParams, DEFAULT_MIN_FRAME_ENERGY};
+use sivana::spectrogram::SpectrogramBuilder;
+
+const SAMPLE_RATE: u32 = 22050;
+const WINDOW_SIZE: usize = 2048;
+const HOP_SIZE: usize = 1024;
+
+/// Two seconds of near-digital silence (tiny deterministic dither) followed by two seconds of tones.
+fn half_silent_samples() -> Vec<f32> {
+    let mut state: u32 = 12345;
+    let mut samples: Vec<f32> = (0..SAMPLE_RATE as usize * 2)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((state >> 16) as f32 / 32768.0 - 1.0) * 1e-6
+        })
+        .collect();
+    samples.extend(synthetic_samples(SAMPLE_RATE, 2));
+    samples
+}
+
+// Frames whose window lies entirely within the silent first half.
+fn silent_frame_count() -> usize {
+    (SAMPLE_RATE as usize * 2 - WINDOW_SIZE) / HOP_SIZE + 1
+}
+
+#[test]
+fn silent_frames_yield_no_peaks_with_energy_gate() {
+    let spectrogram = SpectrogramBuilder::new(WINDOW_SIZE).build(&half_silent_samples(), HOP_SIZE);
+    // A zero magnitude threshold lets the dither through, isolating the energy check.
+    let peaks = find_peaks(&spectrogram, PeakParams { min_magnitude: 0.0, min_frame_energy: DEFAULT_MIN_FRAME_ENERGY, ..PeakParams::default() });
+
+    assert!(peaks.iter().all(|p| p.time_idx >= silent_frame_count()));
+    assert!(peaks.iter().any(|p| p.time_idx >= silent_frame_count()));
+}
+
+#[test]
+fn silent_frames_produce_junk_peaks_without_energy_gate() {
+    let spectrogram = SpectrogramBuilder::new(WINDOW_SIZE).build(&half_silent_samples(), HOP_SIZE);
+    let peaks = find_peaks(&spectrogram, PeakParams { min_magnitude: 0.0, min_frame_energy: 0.0, ..PeakParams::default() });
+
+    assert!(peaks.iter().any(|p| p.time_idx < silent_frame_count()));
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::{delete_song, get_song_fingerprint_count, open_in_memory_connection, LOW_FINGERPRINT_COUNT};
+use sivana::Fingerprinter;
+
+#[test]
+fn fingerprint_count_tracks_each_song_and_flags_short_ones() {
+    let fingerprinter = Fingerprinter::default();
+    let mut conn = open_in_memory_connection().unwrap();
+    let tags = AudioTags::default();
+    let song = synthetic_samples(fingerprinter.sample_rate, 20);
+    let long_id = fingerprinter.enroll(&mut conn, "long", Some("long.wav"), &tags, &song).unwrap();
+    let short_id = fingerprinter.enroll(&mut conn, "short", Some("short.wav"), &tags, &song[..fingerprinter.sample_rate as usize]).unwrap();
+
+    let long_count = get_song_fingerprint_count(&conn, long_id).unwrap();
+    assert_eq!(long_count, fingerprinter.fingerprint(&song).len());
+    assert!(long_count >= LOW_FINGERPRINT_COUNT);
+    assert!(get_song_fingerprint_count(&conn, short_id).unwrap() < LOW_FINGERPRINT_COUNT);
+
+    assert!(delete_song(&mut conn, long_id).unwrap());
+    assert_eq!(get_song_fingerprint_count(&conn, long_id).unwrap(), 0);
+}
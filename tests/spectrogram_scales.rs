@@ -0,0 +1,52 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::spectrogram::{bin_to_hz, MagnitudeScale, SpectrogramBuilder};
+
+#[test]
+fn power_spectrogram_squares_linear_magnitudes() {
+    let samples = synthetic_samples(22050, 1);
+    let linear = SpectrogramBuilder::new(1024).build(&samples, 512);
+    let power = SpectrogramBuilder::new(1024).with_magnitude_scale(MagnitudeScale::Power).build(&samples, 512);
+    assert_eq!(linear.len(), power.len());
+    for (linear_frame, power_frame) in linear.iter().zip(&power) {
+        for (&l, &p) in linear_frame.iter().zip(power_frame) {
+            assert!((l * l - p).abs() <= 1e-3 * p.max(1.0), "{} squared != {}", l, p);
+        }
+    }
+}
+
+#[test]
+fn strongest_bin_of_a_tone_maps_back_to_its_frequency() {
+    let (sample_rate, window_size) = (22050, 2048);
+    let tone_hz = 1000.0;
+    let samples: Vec<f32> = (0..window_size * 2)
+        .map(|i| (2.0 * std::f32::consts::PI * tone_hz * i as f32 / sample_rate as f32).sin())
+        .collect();
+    let frame = &SpectrogramBuilder::new(window_size).build(&samples, window_size)[0];
+    assert_eq!(frame.len(), window_size / 2 + 1);
+
+    let loudest = (0..frame.len()).max_by(|&a, &b| frame[a].total_cmp(&frame[b])).unwrap();
+    let resolution = bin_to_hz(1, sample_rate, window_size);
+    assert!((bin_to_hz(loudest, sample_rate, window_size) - tone_hz).abs() <= resolution / 2.0);
+    assert_eq!(bin_to_hz(window_size / 2, sample_rate, window_size), sample_rate as f32 / 2.0);
+}
+
+#[cfg(feature = "mel")]
+#[test]
+fn mel_spectrogram_has_one_value_per_band() {
+    let samples = synthetic_samples(22050, 1);
+    let mel = SpectrogramBuilder::new(1024).with_mel_bands(22050, 40).build(&samples, 512);
+    assert!(!mel.is_empty());
+    assert!(mel.iter().all(|frame| frame.len() == 40));
+    assert!(mel.iter().any(|frame| frame.iter().any(|&v| v > 0.0)));
+}
+
+#[test]
+fn invalid_hop_sizes_yield_no_frames() {
+    let samples = synthetic_samples(22050, 1);
+    let builder = SpectrogramBuilder::new(1024);
+    assert!(builder.build(&samples, 0).is_empty());
+    assert!(builder.build(&samples, 1025).is_empty());
+    assert!(!builder.build(&samples, 1024).is_empty());
+}
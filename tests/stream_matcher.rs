@@ -0,0 +1,36 @@
+mod common;
+
+use common::{other_synthetic_samples, synthetic_samples};
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::stream_matcher::StreamMatcher;
+use sivana::Fingerprinter;
+
+#[test]
+fn reports_each_song_once_as_the_stream_moves_on() {
+    let fingerprinter = Fingerprinter::default();
+    let rate = fingerprinter.sample_rate;
+    let mut conn = open_in_memory_connection().unwrap();
+    let first = fingerprinter.enroll(&mut conn, "first", Some("first.wav"), &AudioTags::default(), &synthetic_samples(rate, 12)).unwrap();
+    let second = fingerprinter.enroll(&mut conn, "second", Some("second.wav"), &AudioTags::default(), &other_synthetic_samples(rate, 12)).unwrap();
+
+    // Start the second song on a frame boundary: windows off the enrolled frame grid score far lower.
+    let mut stream = synthetic_samples(rate, 12);
+    stream.truncate(stream.len() / fingerprinter.hop_size * fingerprinter.hop_size);
+    stream.extend(other_synthetic_samples(rate, 12));
+    let mut matcher = StreamMatcher::new(&fingerprinter, &conn, 4.0, 20).with_query_interval(1.0);
+    let mut changes = Vec::new();
+    for chunk in stream.chunks(rate as usize / 3) {
+        changes.extend(matcher.push_samples(chunk));
+    }
+
+    let summary: Vec<_> = changes.iter()
+        .map(|c| (c.previous, c.current.as_ref().map(|m| m.song_id), c.stream_seconds))
+        .collect();
+    assert_eq!(changes.len(), 2, "{:?}", summary);
+    assert_eq!((changes[0].previous, changes[0].current.as_ref().map(|m| m.song_id)), (None, Some(first)));
+    assert!(changes[0].stream_seconds < 6.0);
+    assert_eq!((changes[1].previous, changes[1].current.as_ref().map(|m| m.song_id)), (Some(first), Some(second)));
+    assert!(changes[1].stream_seconds > 12.0 && changes[1].stream_seconds < 18.0);
+    assert_eq!(matcher.current_song(), Some(second));
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::hashing::{Fingerprint, TargetZone};
+use sivana::Fingerprinter;
+
+fn fingerprint_in_chunks(fingerprinter: &Fingerprinter, samples: &[f32], chunk_size: usize) -> Vec<Fingerprint> {
+    let mut stream = fingerprinter.fingerprint_stream();
+    let mut fingerprints = Vec::new();
+    for chunk in samples.chunks(chunk_size) {
+        fingerprints.extend(stream.push_samples(chunk));
+    }
+    fingerprints.extend(stream.finish());
+    fingerprints
+}
+
+#[test]
+fn streaming_matches_whole_signal_for_any_chunk_size() {
+    let fingerprinter = Fingerprinter::default();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 8);
+    let expected = fingerprinter.fingerprint(&samples);
+    assert!(!expected.is_empty());
+
+    // Smaller than a hop, between hop and window, larger than a window, and all at once.
+    for chunk_size in [317, 1500, 5000, samples.len()] {
+        assert_eq!(fingerprint_in_chunks(&fingerprinter, &samples, chunk_size), expected, "chunk size {}", chunk_size);
+    }
+}
+
+#[test]
+fn hop_larger_than_window_yields_nothing_whole_or_streamed() {
+    let fingerprinter = Fingerprinter::new(22050, 512, 700);
+    let samples = synthetic_samples(fingerprinter.sample_rate, 5);
+    assert!(fingerprinter.fingerprint(&samples).is_empty());
+    assert!(fingerprint_in_chunks(&fingerprinter, &samples, 333).is_empty());
+}
+
+#[test]
+fn unbounded_target_zone_streams_without_overflow() {
+    let mut fingerprinter = Fingerprinter::default();
+    fingerprinter.target_zone = TargetZone::new(1, usize::MAX, 200, 5).unwrap();
+    let samples = synthetic_samples(fingerprinter.sample_rate, 5);
+    let expected = fingerprinter.fingerprint(&samples);
+    assert!(!expected.is_empty());
+    assert_eq!(fingerprint_in_chunks(&fingerprinter, &samples, 1500), expected);
+}
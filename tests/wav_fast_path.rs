@@ -0,0 +1,70 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::synthetic_samples;
+use sivana::audio_loader::{load_audio_file, load_wav_fast};
+
+const SAMPLE_RATE: u32 = 22050;
+
+// Minimal RIFF/WAVE writer: a 16-byte fmt chunk, an odd-sized JUNK chunk to exercise
+// padding, then the data chunk.
+fn wav_bytes(format_tag: u16, channels: u16, bits_per_sample: u16, data: &[u8]) -> Vec<u8> {
+    let block_align = channels * bits_per_sample / 8;
+    let mut fmt = Vec::new();
+    fmt.extend(format_tag.to_le_bytes());
+    fmt.extend(channels.to_le_bytes());
+    fmt.extend(SAMPLE_RATE.to_le_bytes());
+    fmt.extend((SAMPLE_RATE * block_align as u32).to_le_bytes());
+    fmt.extend(block_align.to_le_bytes());
+    fmt.extend(bits_per_sample.to_le_bytes());
+
+    let mut body = b"WAVE".to_vec();
+    for (id, chunk) in [(b"fmt ", fmt.as_slice()), (b"JUNK", b"abc".as_slice()), (b"data", data)] {
+        body.extend(id);
+        body.extend((chunk.len() as u32).to_le_bytes());
+        body.extend(chunk);
+        if chunk.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend((body.len() as u32).to_le_bytes());
+    bytes.extend(body);
+    bytes
+}
+
+fn write_temp(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("sivana-{}-{}.wav", std::process::id(), name));
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn stereo_pcm16_matches_symphonia_decode() {
+    let left = synthetic_samples(SAMPLE_RATE, 1);
+    let mut data = Vec::new();
+    for (i, l) in left.iter().enumerate() {
+        let right = if i % 2 == 0 { 0.25 } else { -0.25 };
+        data.extend(((l * 32767.0) as i16).to_le_bytes());
+        data.extend(((right * 32767.0) as i16).to_le_bytes());
+    }
+    let path = write_temp("pcm16", &wav_bytes(1, 2, 16, &data));
+
+    let fast = load_wav_fast(&path, SAMPLE_RATE).unwrap();
+    let reference = load_audio_file(&path, SAMPLE_RATE).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(fast.len(), left.len());
+    assert_eq!(fast, reference);
+}
+
+#[test]
+fn float_wav_falls_back_to_symphonia() {
+    let samples = synthetic_samples(SAMPLE_RATE, 1);
+    let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    let path = write_temp("float", &wav_bytes(3, 1, 32, &data));
+
+    let loaded = load_wav_fast(&path, SAMPLE_RATE).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, samples);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::synthetic_samples;
+use sivana::audio_loader::AudioTags;
+use sivana::database::open_in_memory_connection;
+use sivana::hashing::Fingerprint;
+use sivana::spectrogram::SpectrogramBuilder;
+use sivana::Fingerprinter;
+
+#[test]
+fn padding_gives_input_shorter_than_a_window_one_frame() {
+    let samples = synthetic_samples(22050, 1);
+    let short = &samples[..1000];
+    assert!(SpectrogramBuilder::new(2048).build(short, 1024).is_empty());
+
+    let padded = SpectrogramBuilder::new(2048).with_pad_final_frame(true);
+    assert_eq!(padded.build(short, 1024).len(), 1);
+    assert!(padded.build(&[], 1024).is_empty());
+    // Tail samples past the last full frame get one extra frame; an exact fit does not.
+    assert_eq!(padded.build(&samples[..2048 + 1024 + 10], 1024).len(), 3);
+    assert_eq!(padded.build(&samples[..2048 + 1024], 1024).len(), 2);
+}
+
+#[test]
+fn streaming_with_padding_matches_whole_signal() {
+    let mut fingerprinter = Fingerprinter::default();
+    fingerprinter.pad_final_frame = true;
+    let samples = synthetic_samples(fingerprinter.sample_rate, 3);
+    let samples = &samples[..samples.len() - 777];
+    let expected = fingerprinter.fingerprint(samples);
+
+    for chunk_size in [500, 4096, samples.len()] {
+        let mut stream = fingerprinter.fingerprint_stream();
+        let mut streamed: Vec<Fingerprint> = samples.chunks(chunk_size).flat_map(|chunk| stream.push_samples(chunk)).collect();
+        streamed.extend(stream.finish());
+        assert_eq!(streamed, expected, "chunk size {}", chunk_size);
+    }
+}
+
+#[test]
+fn half_second_clip_enrolls_and_matches_itself_with_padding() {
+    let mut fingerprinter = Fingerprinter::default();
+    fingerprinter.pad_final_frame = true;
+    assert_eq!(fingerprinter.window_size, 2048);
+    let clip = synthetic_samples(fingerprinter.sample_rate, 1);
+    let clip = &clip[..fingerprinter.sample_rate as usize / 2];
+    let mut conn = open_in_memory_connection().unwrap();
+
+    let song_id = fingerprinter.enroll(&mut conn, "jingle", Some("jingle.wav"), &AudioTags::default(), clip).unwrap();
+    assert_eq!(fingerprinter.identify(&conn, clip, 1).map(|m| m.song_id), Some(song_id));
+}